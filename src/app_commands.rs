@@ -1,36 +1,245 @@
 use std::{path::PathBuf, sync::Arc};
 
-use druid::{im::HashSet, Selector};
+use druid::{
+    im::{HashMap, HashSet},
+    FileInfo, Modifiers, Point, Selector,
+};
 
 use crate::{
     app_data::{ImageFolder, Thumbnail},
-    folder_view::FolderView,
+    config::JpegRotation,
+    edits::EditStack,
+    folder_view::{Annotate, FolderView, Rotate, SortedImages},
+    search::SearchResults,
+    sidecar::Annotations,
+    trash::TrashedFile,
+    widgets::ZoomPreset,
 };
 
 pub const SELECTED_FOLDER: Selector<usize> =
     Selector::new("app.selected-folder");
 
+/// An image picked with "Open File", which is opened on its own in the image
+/// view.
+pub const OPEN_IMAGE: Selector<FileInfo> = Selector::new("app.open-image");
+
+/// The folder of an image picked with "Open File", listed on another thread
+/// with the image selected.
+pub const OPENED_IMAGE: Selector<ImageFolder> =
+    Selector::new("app.opened-image");
+
+/// Opens the folder of the image shown by the image view as a folder of the
+/// library, still showing the image, adding the folder to the library first
+/// if it isn't in it yet.
+pub const OPEN_IMAGE_FOLDER: Selector<Arc<PathBuf>> =
+    Selector::new("app.open-image-folder");
+
 pub const FINISHED_READING_ALL_PATHS: Selector<HashSet<Arc<PathBuf>>> =
     Selector::new("app.finished-reading-all-paths");
 
 pub const FINISHED_READING_FOLDER_IMAGE: Selector<ImageFolder> =
     Selector::new("finished_reading_image_folder");
 
+pub const RECONCILED_FOLDER: Selector<ImageFolder> =
+    Selector::new("app.reconciled-folder");
+
+pub const REMOVED_FOLDER: Selector<Arc<PathBuf>> =
+    Selector::new("app.removed-folder");
+
+pub const CHANGED_IMAGE: Selector<Arc<PathBuf>> =
+    Selector::new("app.changed-image");
+
 pub const POP_VIEW: Selector<()> = Selector::new("app.pop-view");
 
 pub const POP_FOLDER_VIEW: Selector<()> = Selector::new("app.pop-folder-view");
 
+/// Starts a slideshow of the open folder from the selected image.
+pub const START_SLIDESHOW: Selector<()> = Selector::new("app.start-slideshow");
+
 pub const PUSH_VIEW_WITH_SELECTED_IMAGE: Selector<(FolderView, usize)> =
     Selector::new("app.push-view-with-selected-image");
 
 pub const SELECT_IMAGE_SELECTOR: Selector<usize> =
     Selector::new("select_thumbnail");
 
+/// A click with Ctrl or Shift on the thumbnail at the index, which changes
+/// the selection instead of opening the image.
+pub const SELECT_WITH_CLICK: Selector<(usize, Modifiers)> =
+    Selector::new("app.select-with-click");
+
 pub const FINISHED_READING_IMAGE: Selector<()> =
     Selector::new("finished_reading_image");
 
-pub const CREATED_THUMBNAIL: Selector<Thumbnail> =
+pub const CREATED_THUMBNAIL: Selector<(Arc<PathBuf>, Thumbnail)> =
     Selector::new("created_thumbnail");
 
-pub const CREATED_FIRST_IMAGE_THUMBNAIL: Selector<(Thumbnail, usize)> =
+pub const CREATED_FIRST_IMAGE_THUMBNAIL: Selector<(Thumbnail, Arc<PathBuf>)> =
     Selector::new("app.created-first-image-thumbnail");
+
+pub const TAKE_FOCUS: Selector<()> = Selector::new("app.take-focus");
+
+pub const SET_ZOOM: Selector<ZoomPreset> = Selector::new("app.set-zoom");
+
+pub const SORTED_IMAGES: Selector<SortedImages> =
+    Selector::new("app.sorted-images");
+
+/// Shows an error to the user in a toast.
+pub const SHOW_ERROR: Selector<String> = Selector::new("app.show-error");
+
+pub const DISMISS_TOAST: Selector<u64> = Selector::new("app.dismiss-toast");
+
+/// Shows the context menu of the image at the index, at the position in the
+/// window.
+pub const SHOW_IMAGE_MENU: Selector<(usize, Point)> =
+    Selector::new("app.show-image-menu");
+
+/// Starts renaming the selected image.
+pub const RENAME_IMAGES: Selector<()> = Selector::new("app.rename-images");
+
+pub const CONFIRM_RENAME: Selector<()> = Selector::new("app.confirm-rename");
+
+pub const CANCEL_RENAME: Selector<()> = Selector::new("app.cancel-rename");
+
+/// Asks for the folder to copy the selected images to.
+pub const COPY_IMAGES: Selector<()> = Selector::new("app.copy-images");
+
+/// Asks for the folder to move the selected images to.
+pub const MOVE_IMAGES: Selector<()> = Selector::new("app.move-images");
+
+pub const COPY_IMAGES_TO: Selector<FileInfo> =
+    Selector::new("app.copy-images-to");
+
+pub const MOVE_IMAGES_TO: Selector<FileInfo> =
+    Selector::new("app.move-images-to");
+
+/// Moves the selected images to the trash.
+pub const TRASH_IMAGES: Selector<()> = Selector::new("app.trash-images");
+
+/// Images that were moved to the trash together, so they can be restored,
+/// along with the files trashed with them.
+pub const TRASHED_IMAGES: Selector<(Vec<Arc<PathBuf>>, Vec<TrashedFile>)> =
+    Selector::new("app.trashed-images");
+
+/// Images that were moved to another folder or renamed, from their old path
+/// to their new one. The watcher sends every file and directory renamed on
+/// disk with it.
+pub const MOVED_IMAGES: Selector<Vec<(Arc<PathBuf>, Arc<PathBuf>)>> =
+    Selector::new("app.moved-images");
+
+/// Restores the images that were moved to the trash last.
+pub const UNDO_TRASH: Selector<()> = Selector::new("app.undo-trash");
+
+/// The places in albums, as the album's name, the index and the path, of
+/// images that were restored from the trash, once they're restored.
+pub const RESTORED_IMAGES: Selector<Vec<(Arc<PathBuf>, usize, Arc<PathBuf>)>> =
+    Selector::new("app.restored-images");
+
+/// Creates the cover of the folder again, after its first image changed.
+pub const REFRESH_COVER: Selector<Arc<PathBuf>> =
+    Selector::new("app.refresh-cover");
+
+/// Rotates or flips the selected images.
+pub const ROTATE_IMAGES: Selector<Rotate> = Selector::new("app.rotate-images");
+
+/// Changes how JPEGs are rotated and flipped from now on.
+pub const SET_JPEG_ROTATION: Selector<JpegRotation> =
+    Selector::new("app.set-jpeg-rotation");
+
+/// Images that were rotated or flipped on disk, so their thumbnails and
+/// decoded images have to be made again.
+pub const ROTATED_IMAGES: Selector<Vec<Arc<PathBuf>>> =
+    Selector::new("app.rotated-images");
+
+/// The edit stacks of images that were rotated or flipped, turned along with
+/// them and already saved to their sidecars.
+pub const ROTATED_EDITS: Selector<Vec<(Arc<PathBuf>, EditStack)>> =
+    Selector::new("app.rotated-edits");
+
+/// The edits of the image changed, so its thumbnails have to be made again.
+pub const EDITED_IMAGE: Selector<Arc<PathBuf>> =
+    Selector::new("app.edited-image");
+
+/// The edit stacks read from the sidecars of a folder's images, with the
+/// folder they're from. Images without edits are left out.
+pub const LOADED_EDITS: Selector<(
+    Arc<PathBuf>,
+    HashMap<Arc<PathBuf>, EditStack>,
+)> = Selector::new("app.loaded-edits");
+
+/// Takes the edits off the selected images.
+pub const RESET_EDITS: Selector<()> = Selector::new("app.reset-edits");
+
+/// Writes copies of the selected images with their edits applied, next to
+/// them.
+pub const EXPORT_EDITED: Selector<()> = Selector::new("app.export-edited");
+
+/// Rates, labels or flags the selected images.
+pub const ANNOTATE: Selector<Annotate> = Selector::new("app.annotate");
+
+/// The annotations read from the sidecars of a folder's images, with the
+/// folder they're from. Images without a sidecar are left out.
+pub const LOADED_ANNOTATIONS: Selector<(
+    Arc<PathBuf>,
+    HashMap<Arc<PathBuf>, Annotations>,
+)> = Selector::new("app.loaded-annotations");
+
+/// The annotations of images whose sidecars couldn't be written, as they are
+/// on disk.
+pub const UNSAVED_ANNOTATIONS: Selector<Vec<(Arc<PathBuf>, Annotations)>> =
+    Selector::new("app.unsaved-annotations");
+
+/// The edits of images whose sidecars couldn't be written, as they are on
+/// disk.
+pub const UNSAVED_EDITS: Selector<Vec<(Arc<PathBuf>, EditStack)>> =
+    Selector::new("app.unsaved-edits");
+
+/// Starts editing the tags of the selected images.
+pub const EDIT_TAGS: Selector<()> = Selector::new("app.edit-tags");
+
+pub const CONFIRM_TAGS: Selector<()> = Selector::new("app.confirm-tags");
+
+pub const CANCEL_TAGS: Selector<()> = Selector::new("app.cancel-tags");
+
+/// Searches the library for the query and opens the images found.
+pub const RUN_SEARCH: Selector<String> = Selector::new("app.run-search");
+
+pub const SEARCH_RESULTS: Selector<SearchResults> =
+    Selector::new("app.search-results");
+
+/// Starts naming the current search, to save it.
+pub const SAVE_SEARCH: Selector<()> = Selector::new("app.save-search");
+
+pub const CONFIRM_SAVE_SEARCH: Selector<()> =
+    Selector::new("app.confirm-save-search");
+
+pub const CANCEL_SAVE_SEARCH: Selector<()> =
+    Selector::new("app.cancel-save-search");
+
+/// Removes the saved search with the name.
+pub const REMOVE_SAVED_SEARCH: Selector<String> =
+    Selector::new("app.remove-saved-search");
+
+/// Starts naming a new album.
+pub const NEW_ALBUM: Selector<()> = Selector::new("app.new-album");
+
+pub const CONFIRM_NEW_ALBUM: Selector<()> =
+    Selector::new("app.confirm-new-album");
+
+pub const CANCEL_NEW_ALBUM: Selector<()> =
+    Selector::new("app.cancel-new-album");
+
+/// Deletes the album with the name. Its images stay where they are.
+pub const DELETE_ALBUM: Selector<Arc<PathBuf>> =
+    Selector::new("app.delete-album");
+
+/// Adds the images to the end of the album with the name, leaving out the
+/// ones that are already in it.
+pub const ADD_TO_ALBUM: Selector<(Arc<PathBuf>, Vec<Arc<PathBuf>>)> =
+    Selector::new("app.add-to-album");
+
+/// Takes the selected images out of the open album.
+pub const REMOVE_FROM_ALBUM: Selector<()> =
+    Selector::new("app.remove-from-album");
+
+/// The images of the open album changed, so the library has to be saved.
+pub const CHANGED_ALBUM: Selector<()> = Selector::new("app.changed-album");
@@ -1,21 +1,104 @@
 use std::{path::PathBuf, sync::Arc};
 
 use druid::{
-    im::{HashSet, Vector},
+    im::{HashMap, HashSet, Vector},
+    kurbo::BezPath,
+    piet::{Text, TextLayoutBuilder},
     widget::{Controller, Image},
-    Data, Env, Event, ImageBuf, Lens, LifeCycle, LifeCycleCtx, UpdateCtx,
-    Widget,
+    Color, Data, Env, FontFamily, ImageBuf, Lens, LifeCycle, LifeCycleCtx,
+    PaintCtx, Point, Rect, RenderContext, Size, UpdateCtx, Vec2, Widget,
 };
 
-use crate::main_view::AppView;
+use crate::{
+    cli::PendingFolder,
+    config::{ImageFormats, JpegRotation, SlideshowSettings},
+    main_view::AppView,
+    metadata::ImageMetadata,
+    search::SavedSearch,
+    sort::FolderSort,
+    workers::CancelToken,
+};
 
 #[derive(Clone, Data, Lens, Debug)]
 pub struct AppState {
+    pub roots: HashSet<Arc<PathBuf>>,
     pub folder_paths: HashSet<Arc<PathBuf>>,
     pub current_image_idx: usize,
     pub views: Vector<AppView>,
     pub all_images: Vector<ImageFolder>,
     pub selected_folder: Option<usize>,
+    /// The folder highlighted in the main grid by keyboard navigation.
+    pub focused_folder: Option<usize>,
+    pub image_formats: ImageFormats,
+    pub folder_sort: FolderSort,
+    pub folder_sort_ascending: bool,
+    /// Only folders whose name contains this are shown in the main grid.
+    pub folder_filter: String,
+    /// Indices into `all_images` of the folders shown in the main grid, in
+    /// the order they are shown.
+    pub visible_folders: Vector<usize>,
+    pub slideshow: SlideshowSettings,
+    pub jpeg_rotation: JpegRotation,
+    /// Errors shown at the bottom of the window until they time out or are
+    /// clicked away.
+    pub toasts: Vector<Toast>,
+    /// Cancels the thumbnails of the open folder that are still queued once
+    /// it's closed.
+    pub folder_jobs: CancelToken,
+    /// The query typed into the search box.
+    pub search_query: String,
+    /// Whether a search is running.
+    pub searching: bool,
+    /// Cancels the running search once another one starts.
+    pub search_jobs: CancelToken,
+    /// The images found by the last search, which the folder view shows when
+    /// no folder is selected.
+    pub search_results: Option<ImageFolder>,
+    pub saved_searches: Vector<SavedSearch>,
+    /// Whether the bar naming the search to save is shown.
+    pub naming_search: bool,
+    /// The name typed into that bar.
+    pub search_name: String,
+    /// Whether the bar naming a new album is shown.
+    pub naming_album: bool,
+    /// The name typed into that bar.
+    pub album_name: String,
+    /// The folder of the image opened on its own, which the image view shows.
+    pub image_view_folder: Option<ImageFolder>,
+    /// The folder to open once it has been read, given on the command line or
+    /// opened from the image view.
+    pub pending_folder: Option<PendingFolder>,
+}
+
+impl AppState {
+    /// The folder shown by the folder view, either the selected folder of the
+    /// library, the results of a search or the folder of an image opened on
+    /// its own.
+    pub fn open_folder(&self) -> Option<&ImageFolder> {
+        match self.selected_folder {
+            Some(idx) => self.all_images.get(idx),
+            None if self.views.last() == Some(&AppView::ImageView) => {
+                self.image_view_folder.as_ref()
+            }
+            None => self.search_results.as_ref(),
+        }
+    }
+
+    pub fn open_folder_mut(&mut self) -> Option<&mut ImageFolder> {
+        match self.selected_folder {
+            Some(idx) => self.all_images.get_mut(idx),
+            None if self.views.last() == Some(&AppView::ImageView) => {
+                self.image_view_folder.as_mut()
+            }
+            None => self.search_results.as_mut(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Data, Lens)]
+pub struct Toast {
+    pub id: u64,
+    pub message: String,
 }
 
 #[derive(Debug, Clone, Data, Lens)]
@@ -23,19 +106,79 @@ pub struct ImageFolder {
     pub name: Arc<PathBuf>,
     pub folder_thumbnail: Thumbnail,
     pub paths: Vector<Arc<PathBuf>>,
+    /// The image the folder view starts out showing, if any.
     pub selected: Option<usize>,
     pub thumbnails: Vector<Thumbnail>,
+    pub modified: u64,
+    pub metadata: HashMap<Arc<PathBuf>, Arc<ImageMetadata>>,
+    /// Whether this is an album put together by hand rather than a directory.
+    /// Its images can come from any folder, its name is the one it was given
+    /// and its images are kept in the order they were arranged in.
+    pub album: bool,
+}
+
+impl ImageFolder {
+    /// An album named `name` with the images at `paths`.
+    pub fn album(name: &str, paths: Vector<Arc<PathBuf>>) -> Self {
+        let thumbnails = (0..paths.len()).map(Thumbnail::empty).collect();
+        ImageFolder {
+            name: Arc::new(PathBuf::from(name)),
+            folder_thumbnail: Thumbnail::empty(0),
+            paths,
+            selected: None,
+            thumbnails,
+            modified: 0,
+            metadata: HashMap::new(),
+            album: true,
+        }
+    }
 }
 
 #[derive(Clone, Lens, Debug)]
 pub struct Thumbnail {
     pub index: usize,
     pub image: ImageBuf,
+    /// The label of the image's format shown on its thumbnail, which is known
+    /// once its folder has been listed or its thumbnail has been created, as
+    /// the format is read from the start of the file.
+    pub format: Option<&'static str>,
+    pub state: ThumbnailState,
+}
+
+#[derive(Debug, Clone, Data, PartialEq)]
+pub enum ThumbnailState {
+    /// The thumbnail hasn't been created yet.
+    Empty,
+    Loaded,
+    /// The image couldn't be read, for the given reason. This is shown as a
+    /// placeholder instead of the thumbnail.
+    Failed(Arc<str>),
+}
+
+impl Thumbnail {
+    /// A thumbnail whose image hasn't been created yet.
+    pub fn empty(index: usize) -> Self {
+        Thumbnail {
+            index,
+            image: ImageBuf::empty(),
+            format: None,
+            state: ThumbnailState::Empty,
+        }
+    }
+
+    pub fn failed(index: usize, reason: &str) -> Self {
+        Thumbnail {
+            state: ThumbnailState::Failed(reason.into()),
+            ..Thumbnail::empty(index)
+        }
+    }
 }
 
 impl Data for Thumbnail {
     fn same(&self, other: &Self) -> bool {
         self.index == other.index
+            && self.format == other.format
+            && self.state.same(&other.state)
             && self
                 .image
                 .raw_pixels_shared()
@@ -43,6 +186,48 @@ impl Data for Thumbnail {
     }
 }
 
+/// Paints a broken image placeholder, along with the reason, in place of a
+/// thumbnail that couldn't be created.
+pub fn paint_failed_thumbnail(
+    ctx: &mut PaintCtx,
+    thumbnail: &Thumbnail,
+    _env: &Env,
+) {
+    let reason = match &thumbnail.state {
+        ThumbnailState::Failed(reason) => reason,
+        _ => return,
+    };
+    let rect = ctx.size().to_rect().inset(-4.);
+    ctx.fill(rect, &Color::grey8(0xee));
+    ctx.stroke(rect, &Color::grey8(0xaa), 1.);
+
+    // a picture frame with a crack running through it
+    let icon = Rect::from_center_size(
+        rect.center() - Vec2::new(0., rect.height() / 6.),
+        Size::new(40., 30.),
+    );
+    ctx.stroke(icon, &Color::grey8(0x88), 2.);
+    let mut crack = BezPath::new();
+    crack.move_to(Point::new(icon.x0 + 16., icon.y0));
+    crack.line_to(Point::new(icon.x0 + 22., icon.y0 + 12.));
+    crack.line_to(Point::new(icon.x0 + 15., icon.y0 + 18.));
+    crack.line_to(Point::new(icon.x0 + 21., icon.y1));
+    ctx.stroke(crack, &Color::rgb8(0xb0, 0x2a, 0x2a), 2.);
+
+    let layout = ctx
+        .text()
+        .new_text_layout(reason.to_string())
+        .font(FontFamily::SYSTEM_UI, 10.)
+        .text_color(Color::grey8(0x55))
+        .max_width(rect.width() - 8.)
+        .build()
+        .unwrap();
+    ctx.with_save(|ctx| {
+        ctx.clip(rect);
+        ctx.draw_text(&layout, Point::new(rect.x0 + 4., icon.y1 + 8.));
+    });
+}
+
 pub struct GalleryThumbnailController;
 
 impl Controller<Thumbnail, Image> for GalleryThumbnailController {
@@ -80,52 +265,3 @@ impl Controller<Thumbnail, Image> for GalleryThumbnailController {
         child.update(ctx, old_data, data, env)
     }
 }
-
-pub struct ThumbnailController;
-
-impl Controller<(usize, Thumbnail), Image> for ThumbnailController {
-    fn event(
-        &mut self,
-        child: &mut Image,
-        ctx: &mut druid::EventCtx,
-        event: &Event,
-        data: &mut (usize, Thumbnail),
-        env: &Env,
-    ) {
-        child.event(ctx, event, data, env)
-    }
-
-    fn lifecycle(
-        &mut self,
-        child: &mut Image,
-        ctx: &mut LifeCycleCtx,
-        event: &LifeCycle,
-        data: &(usize, Thumbnail),
-        env: &Env,
-    ) {
-        if let LifeCycle::WidgetAdded = event {
-            child.set_image_data(data.1.image.clone());
-            ctx.request_layout();
-            ctx.request_paint();
-        }
-        child.lifecycle(ctx, event, data, env)
-    }
-
-    fn update(
-        &mut self,
-        child: &mut Image,
-        ctx: &mut UpdateCtx,
-        old_data: &(usize, Thumbnail),
-        data: &(usize, Thumbnail),
-        env: &Env,
-    ) {
-        let (_, old_image) = old_data;
-        let (_, current_image) = data;
-        if !current_image.same(old_image) {
-            child.set_image_data(current_image.image.clone());
-            ctx.request_layout();
-            ctx.request_paint();
-        }
-        child.update(ctx, old_data, data, env)
-    }
-}
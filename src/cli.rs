@@ -0,0 +1,229 @@
+//! The command line, which opens a folder or an image right away so the
+//! gallery can be used as the default image viewer of a file manager.
+//!
+//! A folder is added to the library, as with "+ Add Folder", and opened once
+//! it has been read. An image is opened on its own, with the other images of
+//! its folder before and after it. Only the first path is opened, any
+//! folders after it are only added to the library.
+
+use std::{ffi::OsString, path::PathBuf, sync::Arc};
+
+use druid::Data;
+use druid_navigator::navigator::ViewController;
+
+use crate::{
+    app_data::AppState,
+    main_view::{image_with_folder, AppView},
+};
+
+pub const USAGE: &str = "\
+Usage: image-viewer [OPTIONS] [PATH]...
+
+Opens PATH, a folder or an image. Folders are added to the library.
+
+Options:
+  -f, --fullscreen  Start maximized without the title bar, as close to
+                    fullscreen as the window can get
+  -s, --slideshow   Start a slideshow of the folder, or from the image
+  -h, --help        Print this help";
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub paths: Vec<PathBuf>,
+    /// druid has no fullscreen window state, so this starts maximized
+    /// without the title bar instead.
+    pub fullscreen: bool,
+    pub slideshow: bool,
+    pub help: bool,
+}
+
+impl Args {
+    /// Parses the arguments after the name of the program. Everything after
+    /// `--` is taken as a path, even if it starts with a dash.
+    pub fn parse(
+        args: impl IntoIterator<Item = OsString>,
+    ) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut only_paths = false;
+        for arg in args {
+            if only_paths {
+                parsed.paths.push(arg.into());
+                continue;
+            }
+            match arg.to_str() {
+                Some("--") => only_paths = true,
+                Some("-f") | Some("--fullscreen") => parsed.fullscreen = true,
+                Some("-s") | Some("--slideshow") => parsed.slideshow = true,
+                Some("-h") | Some("--help") => parsed.help = true,
+                Some(option) if option.starts_with('-') && option.len() > 1 => {
+                    return Err(format!("unknown option {}", option));
+                }
+                _ => parsed.paths.push(arg.into()),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// A folder given on the command line, or the folder of an image opened on
+/// its own, that's still being read, which is opened as soon as it is.
+#[derive(Debug, Clone, Data)]
+pub struct PendingFolder {
+    pub path: Arc<PathBuf>,
+    pub slideshow: bool,
+    /// The image shown once the folder is opened, instead of its grid.
+    pub image: Option<Arc<PathBuf>>,
+}
+
+/// What's left to do once the app is launched, after `open`.
+#[derive(Debug, Default)]
+pub struct Opened {
+    /// Folders that were added to the library and still have to be read.
+    pub new_roots: Vec<PathBuf>,
+    /// The image the image view shows, which the folder view switches to once
+    /// it's up.
+    pub show_image: Option<usize>,
+    /// Whether the view that was opened starts a slideshow.
+    pub start_slideshow: bool,
+}
+
+/// Adds the folders of `args` to the library in `state` and opens the first
+/// path. Paths that can't be opened are reported on stderr, since the window
+/// isn't up yet.
+pub fn open(args: &Args, state: &mut AppState) -> Opened {
+    let mut opened = Opened::default();
+    let mut opened_any = false;
+    for path in args.paths.iter() {
+        // the library keeps absolute paths, as the file dialog gives them
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(err) => {
+                eprintln!("Couldn't open {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        // paths that don't exist don't count as the first
+        let first = !opened_any;
+        opened_any = true;
+        if path.is_dir() {
+            let in_library = state
+                .roots
+                .iter()
+                .any(|root| path.starts_with(root.as_ref()));
+            if !in_library {
+                state.roots.insert(Arc::new(path.clone()));
+                opened.new_roots.push(path.clone());
+            }
+            if first {
+                let open = open_folder(state, Arc::new(path), args.slideshow);
+                opened.start_slideshow = args.slideshow && open;
+            }
+        } else if first {
+            opened.show_image = open_image(state, path);
+            opened.start_slideshow =
+                args.slideshow && opened.show_image.is_some();
+        }
+    }
+    opened
+}
+
+/// Opens the folder at `path`, or the first folder inside it, if it's in the
+/// library already. Otherwise it's opened once it has been read. Returns
+/// whether it was opened.
+fn open_folder(
+    state: &mut AppState,
+    path: Arc<PathBuf>,
+    slideshow: bool,
+) -> bool {
+    // paths sort parents before the folders inside them
+    let folder = state
+        .all_images
+        .iter()
+        .enumerate()
+        .filter(|(_, folder)| !folder.album && folder.name.starts_with(&*path))
+        .min_by(|(_, a), (_, b)| a.name.cmp(&b.name))
+        .map(|(idx, _)| idx);
+    match folder {
+        Some(idx) => {
+            state.selected_folder = Some(idx);
+            state.add_view(AppView::FolderView);
+            true
+        }
+        None => {
+            state.pending_folder = Some(PendingFolder {
+                path,
+                slideshow,
+                image: None,
+            });
+            false
+        }
+    }
+}
+
+/// Opens the image at `path` in the image view, along with the other images
+/// of its folder. Returns its index in the folder, if it could be opened.
+fn open_image(state: &mut AppState, path: PathBuf) -> Option<usize> {
+    match image_with_folder(&path, &state.image_formats) {
+        Ok(folder) => {
+            let index = folder.selected;
+            state.image_view_folder = Some(folder);
+            state.add_view(AppView::ImageView);
+            index
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_arguments() {
+        // the paths, then whether fullscreen, slideshow and help are set
+        let cases: [(&[&str], &[&str], bool, bool, bool); 11] = [
+            (&[], &[], false, false, false),
+            (&["photos"], &["photos"], false, false, false),
+            (&["a.jpg", "b"], &["a.jpg", "b"], false, false, false),
+            (&["-f", "photos"], &["photos"], true, false, false),
+            (&["photos", "--fullscreen"], &["photos"], true, false, false),
+            (&["-s", "--slideshow"], &[], false, true, false),
+            (&["-h"], &[], false, false, true),
+            (&["--help", "-f"], &[], true, false, true),
+            // a lone dash is a path
+            (&["-"], &["-"], false, false, false),
+            // so is everything after `--`
+            (&["--", "-s", "--"], &["-s", "--"], false, false, false),
+            (&["-f", "--", "-x"], &["-x"], true, false, false),
+        ];
+        for (args, paths, fullscreen, slideshow, help) in cases.iter() {
+            let parsed = Args::parse(args.iter().map(OsString::from)).unwrap();
+            let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+            assert_eq!(parsed.paths, paths, "{:?}", args);
+            assert_eq!(parsed.fullscreen, *fullscreen, "{:?}", args);
+            assert_eq!(parsed.slideshow, *slideshow, "{:?}", args);
+            assert_eq!(parsed.help, *help, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn refuses_unknown_options() {
+        let cases: [(&[&str], &str); 3] = [
+            (&["-x"], "-x"),
+            (&["photos", "--full"], "--full"),
+            (&["-fs"], "-fs"),
+        ];
+        for (args, option) in cases.iter() {
+            let parsed = Args::parse(args.iter().map(OsString::from));
+            assert_eq!(
+                parsed.unwrap_err(),
+                format!("unknown option {}", option),
+                "{:?}",
+                args
+            );
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::HashSet, fs::File, io::BufReader, path::Path, sync::Arc,
+};
+
+use druid::{Data, Lens};
+use image::{io::Reader, ImageFormat};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+const CONFIG_DIR: &str = "photo-gallery";
+const CONFIG_FILE: &str = "config.json";
+
+/// Every format the `image` crate is built to decode.
+pub const DECODABLE_FORMATS: [ImageFormat; 12] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Gif,
+    ImageFormat::WebP,
+    ImageFormat::Pnm,
+    ImageFormat::Tiff,
+    ImageFormat::Tga,
+    ImageFormat::Dds,
+    ImageFormat::Bmp,
+    ImageFormat::Ico,
+    ImageFormat::Hdr,
+    ImageFormat::Farbfeld,
+];
+
+/// User settings, read from `config.json` in the platform's config directory.
+///
+/// Any setting that is missing from the file keeps its default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// File extensions of the formats that are shown in the gallery, for
+    /// example `["jpg", "webp", "tiff"]`.
+    pub formats: Vec<String>,
+    pub slideshow: SlideshowSettings,
+    pub image_cache: ImageCacheSettings,
+    /// `"lossless"` or `"orientation"`, how JPEGs are rotated until it's
+    /// switched from the context menu of an image.
+    pub jpeg_rotation: JpegRotation,
+}
+
+/// How JPEGs are rotated and flipped, as neither way loses any quality.
+#[derive(Debug, Clone, Copy, PartialEq, Data, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JpegRotation {
+    /// The compressed image is rearranged, so it's turned for every program,
+    /// whether it reads the EXIF orientation or not. Images this can't be
+    /// done for have their orientation changed instead.
+    Lossless,
+    /// Only the EXIF orientation is changed, which is quicker.
+    Orientation,
+}
+
+impl Default for JpegRotation {
+    fn default() -> Self {
+        JpegRotation::Lossless
+    }
+}
+
+/// How the slideshow of a folder plays, which can also be changed while it's
+/// running.
+#[derive(Debug, Clone, Data, Lens, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SlideshowSettings {
+    /// Seconds each image is shown for.
+    pub interval: f64,
+    /// Whether the slideshow starts over after the last image instead of
+    /// stopping.
+    #[serde(rename = "loop")]
+    pub looping: bool,
+    pub shuffle: bool,
+    /// Seconds the crossfade between two images takes, 0 to cut between them.
+    pub transition: f64,
+}
+
+impl Default for SlideshowSettings {
+    fn default() -> Self {
+        SlideshowSettings {
+            interval: 5.,
+            looping: true,
+            shuffle: false,
+            transition: 0.5,
+        }
+    }
+}
+
+/// How many decoded images are kept in memory for the single image view.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageCacheSettings {
+    /// Megabytes of decoded pixels kept before the least recently shown
+    /// images are dropped.
+    pub memory_mb: usize,
+    /// How many images before and after the one shown are read ahead.
+    pub prefetch: usize,
+}
+
+impl Default for ImageCacheSettings {
+    fn default() -> Self {
+        ImageCacheSettings {
+            memory_mb: 512,
+            prefetch: 2,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let formats = [
+            "png", "jpg", "gif", "webp", "ppm", "tiff", "tga", "dds", "bmp",
+            "ico", "hdr", "ff",
+        ]
+        .iter()
+        .map(|extension| extension.to_string())
+        .collect();
+        Config {
+            formats,
+            slideshow: SlideshowSettings::default(),
+            image_cache: ImageCacheSettings::default(),
+            jpeg_rotation: JpegRotation::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let path = match dirs::config_dir() {
+            Some(dir) => dir.join(CONFIG_DIR).join(CONFIG_FILE),
+            None => return Config::default(),
+        };
+        if !path.exists() {
+            return Config::default();
+        }
+
+        let config =
+            File::open(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|file| {
+                    serde_json::from_reader(BufReader::new(file))
+                        .map_err(|err| err.to_string())
+                });
+        match config {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Error reading config {}: {}", path.display(), err);
+                Config::default()
+            }
+        }
+    }
+
+    pub fn image_formats(&self) -> ImageFormats {
+        let formats = self
+            .formats
+            .iter()
+            .filter_map(|extension| {
+                match ImageFormat::from_extension(extension) {
+                    Some(format) if DECODABLE_FORMATS.contains(&format) => {
+                        Some(format)
+                    }
+                    _ => {
+                        error!("Unsupported image format: {}", extension);
+                        None
+                    }
+                }
+            })
+            .collect();
+        ImageFormats(Arc::new(formats))
+    }
+}
+
+/// The set of formats the gallery shows.
+#[derive(Debug, Clone)]
+pub struct ImageFormats(Arc<HashSet<ImageFormat>>);
+
+impl ImageFormats {
+    pub fn contains(&self, format: ImageFormat) -> bool {
+        self.0.contains(&format)
+    }
+}
+
+impl Data for ImageFormats {
+    fn same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// The format of the image at `path`, guessed from the start of the file the
+/// same way it's guessed when the image is read, so misnamed images are
+/// recognized.
+pub fn guess_format(path: &Path) -> Option<ImageFormat> {
+    Reader::open(path)
+        .and_then(|image| image.with_guessed_format())
+        .ok()?
+        .format()
+}
+
+/// The short name of `format`, which is shown as a badge on the thumbnails
+/// of images in it.
+pub fn format_label(format: ImageFormat) -> Option<&'static str> {
+    let label = match format {
+        ImageFormat::Png => "PNG",
+        ImageFormat::Jpeg => "JPEG",
+        ImageFormat::Gif => "GIF",
+        ImageFormat::WebP => "WEBP",
+        ImageFormat::Pnm => "PNM",
+        ImageFormat::Tiff => "TIFF",
+        ImageFormat::Tga => "TGA",
+        ImageFormat::Dds => "DDS",
+        ImageFormat::Bmp => "BMP",
+        ImageFormat::Ico => "ICO",
+        ImageFormat::Hdr => "HDR",
+        ImageFormat::Farbfeld => "FF",
+        _ => return None,
+    };
+    Some(label)
+}
@@ -0,0 +1,744 @@
+//! Keeps the library in `AppState` up to date.
+//!
+//! The navigators only pass commands to the view that is shown, so commands
+//! that change the library, which can arrive at any time from the threads
+//! scanning and watching the disk, are handled here instead.
+
+use std::{
+    collections::{HashMap as StdHashMap, HashSet as StdHashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use druid::{
+    commands::OPEN_FILE,
+    im::{HashMap, Vector},
+    AppDelegate, Command, Data, DelegateCtx, Env, ExtEventSink, Handled,
+    Target,
+};
+use druid_navigator::navigator::ViewController;
+
+use crate::{
+    app_commands::{
+        ADD_TO_ALBUM, CHANGED_ALBUM, CHANGED_IMAGE,
+        CREATED_FIRST_IMAGE_THUMBNAIL, CREATED_THUMBNAIL, DELETE_ALBUM,
+        EDITED_IMAGE, FINISHED_READING_ALL_PATHS,
+        FINISHED_READING_FOLDER_IMAGE, MOVED_IMAGES, OPEN_IMAGE_FOLDER,
+        PUSH_VIEW_WITH_SELECTED_IMAGE, RECONCILED_FOLDER, REFRESH_COVER,
+        REMOVED_FOLDER, RESTORED_IMAGES, ROTATED_IMAGES, SEARCH_RESULTS,
+        SELECTED_FOLDER, START_SLIDESHOW, TRASHED_IMAGES, UNDO_TRASH,
+    },
+    app_data::{AppState, ImageFolder, Thumbnail, ThumbnailState},
+    cli::PendingFolder,
+    error::{report_error, GalleryError},
+    folder_view::FolderView,
+    image_cache,
+    library::save_library,
+    main_view::{create_cover_thumbnails, flatten_and_add_paths},
+    metadata::ImageMetadata,
+    thumbnail_cache,
+    trash::{self, TrashedFile},
+    watcher::LibraryWatcher,
+};
+
+/// An image that was taken out of an album because it was trashed, as the
+/// name of the album, the place of the image in it and its path.
+type AlbumEntry = (Arc<PathBuf>, usize, Arc<PathBuf>);
+
+/// Images that were moved to the trash together and are restored together.
+struct TrashedBatch {
+    files: Vec<TrashedFile>,
+    /// Where the images were in albums, so restoring puts them back.
+    albums: Vec<AlbumEntry>,
+}
+
+pub struct Delegate {
+    watcher: LibraryWatcher,
+    /// The images moved to the trash, in batches that are restored
+    /// together, the last one first.
+    trashed: Vec<TrashedBatch>,
+    /// The folders whose covers are being created, so scans that finish in
+    /// the meantime don't create them again.
+    queued_covers: StdHashSet<Arc<PathBuf>>,
+}
+
+impl Delegate {
+    pub fn new(watcher: LibraryWatcher) -> Self {
+        Delegate {
+            watcher,
+            trashed: Vec::new(),
+            queued_covers: StdHashSet::new(),
+        }
+    }
+
+    /// Leaves the image view for the folder of its image in the library,
+    /// adding the folder to the library first if it isn't in it yet.
+    fn open_image_folder(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        data: &mut AppState,
+        image: &Arc<PathBuf>,
+    ) {
+        let folder = match data.image_view_folder.take() {
+            Some(folder) => folder,
+            None => return,
+        };
+        data.folder_jobs.cancel();
+        data.pop_view();
+
+        if let Some(idx) = data
+            .all_images
+            .iter()
+            .position(|known| !known.album && known.name == folder.name)
+        {
+            open_folder(ctx, data, idx, Some(image));
+            return;
+        }
+        // folders under a root are added by the watcher
+        let path = folder.name.to_path_buf();
+        if !data
+            .roots
+            .iter()
+            .any(|root| path.starts_with(root.as_ref()))
+        {
+            data.roots.insert(folder.name.clone());
+            self.watcher.watch(path.clone());
+            flatten_and_add_paths(
+                path,
+                data.folder_paths.clone(),
+                data.image_formats.clone(),
+                ctx.get_external_handle(),
+            );
+        }
+        data.pending_folder = Some(PendingFolder {
+            path: folder.name,
+            slideshow: false,
+            image: Some(image.clone()),
+        });
+    }
+
+    /// Restores the images trashed last on another thread, as restoring
+    /// them can copy them from another file system, and has them put back in
+    /// the albums they were in with `RESTORED_IMAGES`. The watcher adds them
+    /// back to their folders.
+    fn undo_trash(&mut self, handle: ExtEventSink) {
+        let batch = match self.trashed.pop() {
+            Some(batch) => batch,
+            None => return,
+        };
+        thread::spawn(move || {
+            for file in batch.files {
+                if let Err(err) = trash::restore(&file) {
+                    let err = GalleryError::file_operation(
+                        "restore",
+                        &file.original,
+                        err,
+                    );
+                    report_error(&handle, &err);
+                }
+            }
+            // images that couldn't be restored stay out of their albums
+            let restored: Vec<AlbumEntry> = batch
+                .albums
+                .into_iter()
+                .filter(|(_, _, path)| path.exists())
+                .collect();
+            if !restored.is_empty() {
+                // this only fails when the app is closing
+                let _ = handle.submit_command(
+                    RESTORED_IMAGES,
+                    restored,
+                    Target::Auto,
+                );
+            }
+        });
+    }
+}
+
+impl AppDelegate<AppState> for Delegate {
+    fn command(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        _target: Target,
+        cmd: &Command,
+        data: &mut AppState,
+        _env: &Env,
+    ) -> Handled {
+        if let Some(file_info) = cmd.get(OPEN_FILE) {
+            // the main view still adds the new root to the library
+            self.watcher.watch(file_info.path().to_path_buf());
+            return Handled::No;
+        }
+
+        let handle = ctx.get_external_handle();
+        if let Some(image_folder) = cmd.get(FINISHED_READING_FOLDER_IMAGE) {
+            // a folder can be read again while it's already in the library,
+            // when it's added twice or its scans overlap
+            if !data
+                .all_images
+                .iter()
+                .any(|folder| !folder.album && folder.name == image_folder.name)
+            {
+                data.all_images.push_back(image_folder.clone());
+            }
+            open_pending_folder(ctx, data, &image_folder.name);
+        } else if let Some(current_folders) =
+            cmd.get(FINISHED_READING_ALL_PATHS)
+        {
+            // the library is checked while folders added from the command
+            // line or the image view are read, so neither scan replaces what
+            // the other found, and folders removed meanwhile stay out
+            let in_library: StdHashSet<&Arc<PathBuf>> =
+                data.all_images.iter().map(|folder| &folder.name).collect();
+            let found: Vec<Arc<PathBuf>> = current_folders
+                .iter()
+                .filter(|path| in_library.contains(path))
+                .cloned()
+                .collect();
+            data.folder_paths.extend(found);
+            save_library(data);
+            // folders that were reconciled had their covers created already
+            let covers: Vec<ImageFolder> = data
+                .all_images
+                .iter()
+                .filter(|folder| {
+                    folder.folder_thumbnail.state == ThumbnailState::Empty
+                        && !folder.paths.is_empty()
+                        && !self.queued_covers.contains(&folder.name)
+                })
+                .cloned()
+                .collect();
+            self.queued_covers
+                .extend(covers.iter().map(|folder| folder.name.clone()));
+            create_cover_thumbnails(covers, handle);
+        } else if let Some((thumbnail, name)) =
+            cmd.get(CREATED_FIRST_IMAGE_THUMBNAIL)
+        {
+            self.queued_covers.remove(name);
+            if let Some(folder) = data
+                .all_images
+                .iter_mut()
+                .find(|folder| &folder.name == name)
+            {
+                folder.folder_thumbnail = thumbnail.clone();
+            }
+        } else if let Some(image_folder) = cmd.get(RECONCILED_FOLDER) {
+            if reconcile_folder(data, image_folder, handle) {
+                self.queued_covers.insert(image_folder.name.clone());
+            }
+            open_pending_folder(ctx, data, &image_folder.name);
+        } else if let Some(removed) = cmd.get(REMOVED_FOLDER) {
+            remove_folder(data, removed);
+        } else if let Some(path) = cmd.get(CHANGED_IMAGE) {
+            change_image(data, path, handle);
+            data.update_visible_folders();
+            // the image view shows the image again, if it's the one changed
+            return Handled::No;
+        } else if let Some(path) = cmd.get(EDITED_IMAGE) {
+            // the decoded image is still the same, only its thumbnails
+            // show the edits
+            clear_thumbnails(data, path, false, handle);
+        } else if let Some(name) = cmd.get(REFRESH_COVER) {
+            if let Some(folder) = data
+                .all_images
+                .iter_mut()
+                .find(|folder| &folder.name == name)
+            {
+                if folder.paths.is_empty() {
+                    // only albums can be left without images
+                    folder.folder_thumbnail = Thumbnail::empty(0);
+                } else {
+                    create_cover_thumbnails(vec![folder.clone()], handle);
+                }
+            }
+        } else if let Some((removed, trashed)) = cmd.get(TRASHED_IMAGES) {
+            let paths: Vec<&Path> =
+                removed.iter().map(|path| path.as_path()).collect();
+            let albums = remove_from_albums(data, &paths, handle);
+            self.trashed.push(TrashedBatch {
+                files: trashed.clone(),
+                albums,
+            });
+            data.update_visible_folders();
+            // the folder view drops the images, if it's still shown
+            return Handled::No;
+        } else if let Some(moved) = cmd.get(MOVED_IMAGES) {
+            move_in_albums(data, moved);
+            data.update_visible_folders();
+            // the folder view drops or renames the images, if it's shown
+            return Handled::No;
+        } else if let Some((album, paths)) = cmd.get(ADD_TO_ALBUM) {
+            add_to_album(data, album, paths, handle);
+        } else if let Some(album) = cmd.get(DELETE_ALBUM) {
+            if let Some(idx) = data
+                .all_images
+                .iter()
+                .position(|folder| folder.album && &folder.name == album)
+            {
+                remove_at(data, idx);
+                save_library(data);
+            }
+        } else if let Some(image) = cmd.get(OPEN_IMAGE_FOLDER) {
+            self.open_image_folder(ctx, data, image);
+        } else if cmd.is(CHANGED_ALBUM) {
+            save_library(data);
+        } else if cmd.is(UNDO_TRASH) {
+            self.undo_trash(handle);
+        } else if let Some(restored) = cmd.get(RESTORED_IMAGES) {
+            return_to_albums(data, restored, handle);
+        } else if let Some(results) = cmd.get(SEARCH_RESULTS) {
+            add_metadata(data, &results.metadata);
+            if results.jobs.same(&data.search_jobs) {
+                data.searching = false;
+            }
+            // the main view opens the results, if it's still shown
+            return Handled::No;
+        } else if let Some(paths) = cmd.get(ROTATED_IMAGES) {
+            for path in paths {
+                change_image(data, path, handle.clone());
+            }
+            // the image view shows the image again, if it's one of them
+            return Handled::No;
+        } else if let Some((path, thumbnail)) = cmd.get(CREATED_THUMBNAIL) {
+            set_thumbnail(data, path, thumbnail);
+            // the folder view also shows it, if it's the current view
+            return Handled::No;
+        } else {
+            return Handled::No;
+        }
+        data.update_visible_folders();
+        Handled::Yes
+    }
+}
+
+/// Opens the folder waiting to be read once the first folder in it has been
+/// read, unless another view was opened in the meantime.
+fn open_pending_folder(
+    ctx: &mut DelegateCtx,
+    data: &mut AppState,
+    name: &Arc<PathBuf>,
+) {
+    let pending = match &data.pending_folder {
+        Some(pending) if name.starts_with(pending.path.as_path()) => {
+            pending.clone()
+        }
+        _ => return,
+    };
+    data.pending_folder = None;
+    if data.views.len() > 1 {
+        return;
+    }
+    if let Some(idx) = data
+        .all_images
+        .iter()
+        .position(|folder| &folder.name == name)
+    {
+        open_folder(ctx, data, idx, pending.image.as_ref());
+        if pending.slideshow {
+            ctx.submit_command(START_SLIDESHOW);
+        }
+    }
+}
+
+/// Opens the folder at `idx` in the folder view, showing `image` if it's in
+/// it.
+fn open_folder(
+    ctx: &mut DelegateCtx,
+    data: &AppState,
+    idx: usize,
+    image: Option<&Arc<PathBuf>>,
+) {
+    ctx.submit_command(SELECTED_FOLDER.with(idx));
+    let index =
+        image.and_then(|image| data.all_images[idx].paths.index_of(image));
+    if let Some(index) = index {
+        ctx.submit_command(
+            PUSH_VIEW_WITH_SELECTED_IMAGE
+                .with((FolderView::SingleImage, index)),
+        );
+    }
+}
+
+/// The index of the folder containing the image at `path`.
+fn folder_of(data: &AppState, path: &Path) -> Option<usize> {
+    let parent = path.parent()?;
+    data.all_images
+        .iter()
+        .position(|folder| folder.name.as_path() == parent)
+}
+
+/// Updates a folder from a new listing of its images, or adds it if it's new.
+/// Returns whether its cover is created again.
+fn reconcile_folder(
+    data: &mut AppState,
+    image_folder: &ImageFolder,
+    handle: ExtEventSink,
+) -> bool {
+    let idx = match data
+        .all_images
+        .iter()
+        .position(|folder| folder.name == image_folder.name)
+    {
+        Some(idx) => idx,
+        None => {
+            data.folder_paths.insert(image_folder.name.clone());
+            data.all_images.push_back(image_folder.clone());
+            create_cover_thumbnails(vec![image_folder.clone()], handle);
+            return !image_folder.paths.is_empty();
+        }
+    };
+
+    let folder = &data.all_images[idx];
+    let listed: StdHashSet<&Arc<PathBuf>> = image_folder.paths.iter().collect();
+    let thumbnails: StdHashMap<&Arc<PathBuf>, &Thumbnail> =
+        folder.paths.iter().zip(folder.thumbnails.iter()).collect();
+    // the folder view can have sorted the images, so their order is kept and
+    // new images are added at the end
+    let mut paths: Vector<Arc<PathBuf>> = folder
+        .paths
+        .iter()
+        .filter(|path| listed.contains(path))
+        .cloned()
+        .collect();
+    for path in image_folder.paths.iter() {
+        if !thumbnails.contains_key(path) {
+            paths.push_back(path.clone());
+        }
+    }
+    // thumbnails of the new images are created by the folder view once
+    // they're in view
+    let new_thumbnails: Vector<Thumbnail> = paths
+        .iter()
+        .enumerate()
+        .map(|(index, path)| match thumbnails.get(path) {
+            Some(thumbnail) => Thumbnail {
+                index,
+                ..(*thumbnail).clone()
+            },
+            None => {
+                let new_idx = image_folder.paths.index_of(path).unwrap_or(0);
+                Thumbnail {
+                    index,
+                    ..image_folder.thumbnails[new_idx].clone()
+                }
+            }
+        })
+        .collect();
+    let cover_changed = folder.paths.get(0) != paths.get(0);
+    let metadata = folder
+        .metadata
+        .clone()
+        .into_iter()
+        .filter(|(path, _)| listed.contains(path))
+        .collect();
+
+    let folder = &mut data.all_images[idx];
+    folder.paths = paths;
+    folder.thumbnails = new_thumbnails;
+    folder.metadata = metadata;
+    folder.modified = image_folder.modified;
+    if cover_changed {
+        create_cover_thumbnails(vec![folder.clone()], handle);
+    }
+    cover_changed && !folder.paths.is_empty()
+}
+
+/// Removes the folder at `removed`, along with the folders inside it if the
+/// directory itself is gone.
+fn remove_folder(data: &mut AppState, removed: &Arc<PathBuf>) {
+    let gone = !removed.is_dir();
+    while let Some(idx) = data.all_images.iter().position(|folder| {
+        &folder.name == removed
+            || (gone && folder.name.starts_with(removed.as_path()))
+    }) {
+        let name = remove_at(data, idx);
+        data.folder_paths.remove(&name);
+    }
+}
+
+/// Removes the folder or album at `idx`, returning its name. The indices of
+/// the folders after it shift back.
+fn remove_at(data: &mut AppState, idx: usize) -> Arc<PathBuf> {
+    let name = data.all_images.remove(idx).name;
+    let shift = |index: Option<usize>| match index {
+        Some(index) if index == idx => None,
+        Some(index) if index > idx => Some(index - 1),
+        index => index,
+    };
+    data.selected_folder = shift(data.selected_folder);
+    data.focused_folder = shift(data.focused_folder);
+    name
+}
+
+/// Adds `paths` to the end of the album named `album`, leaving out the images
+/// that are already in it.
+fn add_to_album(
+    data: &mut AppState,
+    album: &Arc<PathBuf>,
+    paths: &[Arc<PathBuf>],
+    handle: ExtEventSink,
+) {
+    let album = match data
+        .all_images
+        .iter_mut()
+        .find(|folder| folder.album && &folder.name == album)
+    {
+        Some(album) => album,
+        None => return,
+    };
+    let was_empty = album.paths.is_empty();
+    let mut contained: StdHashSet<Arc<PathBuf>> =
+        album.paths.iter().cloned().collect();
+    for path in paths {
+        if !contained.insert(path.clone()) {
+            continue;
+        }
+        let index = album.paths.len();
+        album.paths.push_back(path.clone());
+        album.thumbnails.push_back(Thumbnail::empty(index));
+    }
+    if was_empty && !album.paths.is_empty() {
+        create_cover_thumbnails(vec![album.clone()], handle);
+    }
+    save_library(data);
+}
+
+/// Takes the images at `paths` out of every album, after they were trashed,
+/// and returns where they were.
+fn remove_from_albums(
+    data: &mut AppState,
+    paths: &[&Path],
+    handle: ExtEventSink,
+) -> Vec<AlbumEntry> {
+    let mut removed = Vec::new();
+    for album in data.all_images.iter_mut().filter(|folder| folder.album) {
+        let in_album: Vec<AlbumEntry> = album
+            .paths
+            .iter()
+            .enumerate()
+            .filter(|(_, path)| paths.contains(&path.as_path()))
+            .map(|(index, path)| (album.name.clone(), index, path.clone()))
+            .collect();
+        if in_album.is_empty() {
+            continue;
+        }
+        removed.extend(in_album);
+        let first = album.paths.get(0).cloned();
+        let kept: Vec<Arc<PathBuf>> = album
+            .paths
+            .iter()
+            .filter(|path| !paths.contains(&path.as_path()))
+            .cloned()
+            .collect();
+        let thumbnails: StdHashMap<&Arc<PathBuf>, &Thumbnail> =
+            album.paths.iter().zip(album.thumbnails.iter()).collect();
+        let kept_thumbnails = kept
+            .iter()
+            .enumerate()
+            .map(|(index, path)| Thumbnail {
+                index,
+                ..thumbnails[path].clone()
+            })
+            .collect();
+        album.thumbnails = kept_thumbnails;
+        album.paths = kept.into_iter().collect();
+        if album.paths.get(0) != first.as_ref() {
+            if album.paths.is_empty() {
+                album.folder_thumbnail = Thumbnail::empty(0);
+            } else {
+                create_cover_thumbnails(vec![album.clone()], handle.clone());
+            }
+        }
+    }
+    if !removed.is_empty() {
+        save_library(data);
+    }
+    removed
+}
+
+/// Puts images that were restored from the trash back where they were in
+/// their albums, unless they were added to them again in the meantime.
+fn return_to_albums(
+    data: &mut AppState,
+    entries: &[AlbumEntry],
+    handle: ExtEventSink,
+) {
+    let mut changed = false;
+    for album in data.all_images.iter_mut().filter(|folder| folder.album) {
+        let mut returning: Vec<(usize, &Arc<PathBuf>)> = entries
+            .iter()
+            .filter(|(name, _, path)| {
+                name == &album.name && !album.paths.contains(path)
+            })
+            .map(|(_, index, path)| (*index, path))
+            .collect();
+        if returning.is_empty() {
+            continue;
+        }
+        // the earlier places come first, so the later ones are where they
+        // were once those are filled
+        returning.sort_by_key(|(index, _)| *index);
+        let first = album.paths.get(0).cloned();
+        for (index, path) in returning {
+            let index = index.min(album.paths.len());
+            album.paths.insert(index, path.clone());
+            album.thumbnails.insert(index, Thumbnail::empty(index));
+        }
+        for (index, thumbnail) in album.thumbnails.iter_mut().enumerate() {
+            thumbnail.index = index;
+        }
+        if album.paths.get(0) != first.as_ref() {
+            create_cover_thumbnails(vec![album.clone()], handle.clone());
+        }
+        changed = true;
+    }
+    if changed {
+        save_library(data);
+    }
+}
+
+/// Points the albums at the new paths of images that were moved or renamed,
+/// and of the images inside directories that were.
+fn move_in_albums(data: &mut AppState, moved: &[(Arc<PathBuf>, Arc<PathBuf>)]) {
+    let mut changed = false;
+    for album in data.all_images.iter_mut().filter(|folder| folder.album) {
+        for index in 0..album.paths.len() {
+            let old = album.paths[index].clone();
+            let new = match moved_path(&old, moved) {
+                Some(new) => new,
+                None => continue,
+            };
+            if let Some(metadata) = album.metadata.remove(&old) {
+                album.metadata.insert(new.clone(), metadata);
+            }
+            album.paths[index] = new;
+            changed = true;
+        }
+    }
+    if changed {
+        save_library(data);
+    }
+}
+
+/// Where the image at `path` is after the moves in `moved`, if it moved.
+fn moved_path(
+    path: &Path,
+    moved: &[(Arc<PathBuf>, Arc<PathBuf>)],
+) -> Option<Arc<PathBuf>> {
+    moved.iter().find_map(|(old, new)| {
+        if path == old.as_path() {
+            Some(new.clone())
+        } else {
+            let inside = path.strip_prefix(old.as_path()).ok()?;
+            Some(Arc::new(new.join(inside)))
+        }
+    })
+}
+
+/// Clears the thumbnail and metadata of an image that was rewritten, so they
+/// are read again, the thumbnail once it's in view. This goes for its folder
+/// as well as the albums, search results and image view showing it.
+fn change_image(
+    data: &mut AppState,
+    path: &Arc<PathBuf>,
+    handle: ExtEventSink,
+) {
+    image_cache::remove(path);
+    // the image can have been rewritten within the second of its
+    // modification time, which leaves the cached thumbnail looking valid.
+    // This is done before the thumbnails are created again, so they aren't
+    // read from the cache
+    thumbnail_cache::remove(path);
+    clear_thumbnails(data, path, true, handle);
+}
+
+/// Clears the thumbnails of the image at `path` wherever it's shown, so they
+/// get created again, along with the covers it's on. Its metadata is cleared
+/// too if the file changed on disk.
+fn clear_thumbnails(
+    data: &mut AppState,
+    path: &Arc<PathBuf>,
+    changed_on_disk: bool,
+    handle: ExtEventSink,
+) {
+    let parent = path.parent();
+    let mut covers = Vec::new();
+    for folder in data.all_images.iter_mut() {
+        if !folder.album && Some(folder.name.as_path()) != parent {
+            continue;
+        }
+        if clear_image(folder, path, changed_on_disk) == Some(0) {
+            covers.push(folder.clone());
+        }
+    }
+    for folder in data
+        .search_results
+        .iter_mut()
+        .chain(data.image_view_folder.iter_mut())
+    {
+        clear_image(folder, path, changed_on_disk);
+    }
+    if !covers.is_empty() {
+        create_cover_thumbnails(covers, handle);
+    }
+}
+
+/// Clears the thumbnail of the image at `path` in `folder`, and its metadata
+/// if `metadata` is set, and returns its index if it's in the folder.
+fn clear_image(
+    folder: &mut ImageFolder,
+    path: &Arc<PathBuf>,
+    metadata: bool,
+) -> Option<usize> {
+    let index = folder.paths.index_of(path)?;
+    folder.thumbnails[index] = Thumbnail {
+        format: folder.thumbnails[index].format,
+        ..Thumbnail::empty(index)
+    };
+    if metadata {
+        folder.metadata.remove(path);
+    }
+    Some(index)
+}
+
+/// Keeps the metadata a search read in the folders of the images, so it
+/// doesn't have to be read again.
+fn add_metadata(
+    data: &mut AppState,
+    metadata: &HashMap<Arc<PathBuf>, Arc<ImageMetadata>>,
+) {
+    for (path, metadata) in metadata.iter() {
+        if let Some(idx) = folder_of(data, path) {
+            data.all_images[idx]
+                .metadata
+                .insert(path.clone(), metadata.clone());
+        }
+    }
+}
+
+fn set_thumbnail(
+    data: &mut AppState,
+    path: &Arc<PathBuf>,
+    thumbnail: &Thumbnail,
+) {
+    // only the open folder drops the thumbnails far from its viewport, so
+    // thumbnails of albums and search results aren't kept in the folders
+    // their images are in, where they'd never be dropped
+    let folder = match data.open_folder_mut() {
+        Some(folder) => folder,
+        None => return,
+    };
+    // the index is only a hint, the images could have been sorted since
+    let index = if folder.paths.get(thumbnail.index) == Some(path) {
+        Some(thumbnail.index)
+    } else {
+        folder.paths.index_of(path)
+    };
+    if let Some(index) = index {
+        folder.thumbnails[index] = Thumbnail {
+            index,
+            ..thumbnail.clone()
+        };
+    }
+}
@@ -0,0 +1,951 @@
+//! Non-destructive edits: cropping, straightening and adjusting the tones and
+//! colors of an image, kept as a stack of edits in its sidecar and applied
+//! whenever the image is shown, so the original file is never changed.
+//!
+//! The stack keeps the edits in the order they were made. An edit of the same
+//! kind as the last one replaces it, so dragging a slider adds a single edit,
+//! and undoing takes the last one off. Only the last edit of each kind
+//! counts, and they're applied in a fixed order: straightening, cropping,
+//! white balance and exposure, contrast, saturation and then sharpening.
+
+use std::{fmt, mem, path::Path};
+
+use druid::{im::Vector, Data, Lens};
+use image::{imageops, Rgb, RgbImage};
+use log::warn;
+
+use crate::{orientation, sidecar::read_edits};
+
+/// How far straightening can turn an image either way, in degrees.
+const MAX_STRAIGHTEN: f64 = 45.;
+
+/// The aspect ratio of a crop, which is turned to match the image, so a 3:2
+/// crop of a portrait image is 2:3.
+#[derive(Debug, Clone, Copy, Data, PartialEq, Eq)]
+pub enum Aspect {
+    Original,
+    Square,
+    FourThree,
+    ThreeTwo,
+    SixteenNine,
+}
+
+impl Default for Aspect {
+    fn default() -> Self {
+        Aspect::Original
+    }
+}
+
+impl Aspect {
+    pub const ALL: [Aspect; 5] = [
+        Aspect::Original,
+        Aspect::Square,
+        Aspect::FourThree,
+        Aspect::ThreeTwo,
+        Aspect::SixteenNine,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aspect::Original => "Original",
+            Aspect::Square => "1:1",
+            Aspect::FourThree => "4:3",
+            Aspect::ThreeTwo => "3:2",
+            Aspect::SixteenNine => "16:9",
+        }
+    }
+
+    /// The aspect named `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Aspect::ALL
+            .iter()
+            .copied()
+            .find(|aspect| aspect.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// The width over the height of a crop of an image of `width` by
+    /// `height` pixels.
+    fn ratio(self, width: f64, height: f64) -> f64 {
+        let ratio = match self {
+            Aspect::Original => return width / height,
+            Aspect::Square => 1.,
+            Aspect::FourThree => 4. / 3.,
+            Aspect::ThreeTwo => 3. / 2.,
+            Aspect::SixteenNine => 16. / 9.,
+        };
+        if width >= height {
+            ratio
+        } else {
+            1. / ratio
+        }
+    }
+}
+
+/// The part of the straightened image that's kept, as fractions of its width
+/// and height, which makes it independent of the size the image is shown at.
+///
+/// The rectangle is kept to the ratio of its aspect when it's applied, by
+/// shrinking it around its center, so a crop that was saved without knowing
+/// the size of the image still comes out right.
+#[derive(Debug, Clone, Copy, Data, PartialEq)]
+pub struct Crop {
+    pub aspect: Aspect,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Default for Crop {
+    fn default() -> Self {
+        Crop {
+            aspect: Aspect::Original,
+            x: 0.,
+            y: 0.,
+            width: 1.,
+            height: 1.,
+        }
+    }
+}
+
+impl Crop {
+    /// The smallest a crop can be, as a fraction of the image.
+    pub const MIN_SIZE: f64 = 0.1;
+
+    /// The largest centered crop of `aspect` of an image with `dimensions`,
+    /// or the whole image to be shrunk to `aspect` when they aren't known.
+    pub fn largest(aspect: Aspect, dimensions: Option<(u32, u32)>) -> Crop {
+        let (width, height) = match dimensions {
+            Some((width, height)) if width > 0 && height > 0 => {
+                (width as f64, height as f64)
+            }
+            _ => {
+                return Crop {
+                    aspect,
+                    ..Crop::default()
+                }
+            }
+        };
+        let ratio = aspect.ratio(width, height) / (width / height);
+        let (crop_width, crop_height) = if ratio < 1. {
+            (ratio, 1.)
+        } else {
+            (1., 1. / ratio)
+        };
+        Crop {
+            aspect,
+            x: (1. - crop_width) / 2.,
+            y: (1. - crop_height) / 2.,
+            width: crop_width,
+            height: crop_height,
+        }
+    }
+
+    /// How large the crop is compared to the largest one of its aspect.
+    pub fn size(&self) -> f64 {
+        self.width.max(self.height)
+    }
+
+    /// The crop scaled around its center to `size`, moved back inside the
+    /// image if that took it out.
+    pub fn with_size(self, size: f64) -> Crop {
+        let scale = size.clamp(Crop::MIN_SIZE, 1.) / self.size();
+        let (width, height) = (self.width * scale, self.height * scale);
+        let center_x = self.x + self.width / 2.;
+        let center_y = self.y + self.height / 2.;
+        // rounding can leave a side a hair longer than the image
+        Crop {
+            x: (center_x - width / 2.).min(1. - width).max(0.),
+            y: (center_y - height / 2.).min(1. - height).max(0.),
+            width,
+            height,
+            ..self
+        }
+    }
+
+    /// Where the crop is horizontally, from 0 at the left edge of the image
+    /// to 1 at the right one.
+    pub fn horizontal(&self) -> f64 {
+        position(self.x, self.width)
+    }
+
+    pub fn with_horizontal(self, position: f64) -> Crop {
+        Crop {
+            x: position.clamp(0., 1.) * (1. - self.width),
+            ..self
+        }
+    }
+
+    /// Where the crop is vertically, from 0 at the top of the image to 1 at
+    /// the bottom.
+    pub fn vertical(&self) -> f64 {
+        position(self.y, self.height)
+    }
+
+    pub fn with_vertical(self, position: f64) -> Crop {
+        Crop {
+            y: position.clamp(0., 1.) * (1. - self.height),
+            ..self
+        }
+    }
+
+    /// The same part of the image after the image is transformed like
+    /// `orientation::apply` does for `orientation`.
+    fn oriented(self, orientation: u32) -> Crop {
+        let (transpose, flip_x, flip_y) = orientation::axes(orientation);
+        let mut crop = self;
+        if transpose {
+            crop.x = self.y;
+            crop.y = self.x;
+            crop.width = self.height;
+            crop.height = self.width;
+        }
+        if flip_x {
+            crop.x = 1. - crop.x - crop.width;
+        }
+        if flip_y {
+            crop.y = 1. - crop.y - crop.height;
+        }
+        crop
+    }
+
+    /// The crop with its values made sensible, for crops that were read.
+    fn clamped(self) -> Crop {
+        let width = self.width.clamp(Crop::MIN_SIZE, 1.);
+        let height = self.height.clamp(Crop::MIN_SIZE, 1.);
+        Crop {
+            x: self.x.clamp(0., 1. - width),
+            y: self.y.clamp(0., 1. - height),
+            width,
+            height,
+            ..self
+        }
+    }
+
+    /// The pixels of an image of `width` by `height` that are kept, as the
+    /// left, top, width and height.
+    fn pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let crop = self.clamped();
+        let (image_width, image_height) = (width as f64, height as f64);
+        let mut crop_width = crop.width * image_width;
+        let mut crop_height = crop.height * image_height;
+        let ratio = crop.aspect.ratio(image_width, image_height);
+        if crop_width / crop_height > ratio {
+            crop_width = crop_height * ratio;
+        } else {
+            crop_height = crop_width / ratio;
+        }
+        let center_x = (crop.x + crop.width / 2.) * image_width;
+        let center_y = (crop.y + crop.height / 2.) * image_height;
+        let crop_width = (crop_width.round() as u32).clamp(1, width);
+        let crop_height = (crop_height.round() as u32).clamp(1, height);
+        let left = (center_x - crop_width as f64 / 2.).round().max(0.) as u32;
+        let top = (center_y - crop_height as f64 / 2.).round().max(0.) as u32;
+        (
+            left.min(width - crop_width),
+            top.min(height - crop_height),
+            crop_width,
+            crop_height,
+        )
+    }
+}
+
+/// Where a side of `length` starting at `start` is within the room it has.
+fn position(start: f64, length: f64) -> f64 {
+    if length < 1. {
+        (start / (1. - length)).clamp(0., 1.)
+    } else {
+        0.5
+    }
+}
+
+#[derive(Debug, Clone, Copy, Data, PartialEq)]
+pub enum Edit {
+    Crop(Crop),
+    /// Turns the image by the degrees, clockwise for positive ones, cropping
+    /// it so no empty corners are left. Up to `MAX_STRAIGHTEN` either way.
+    Straighten(f64),
+    /// In stops.
+    Exposure(f64),
+    /// From -1 to 1.
+    Contrast(f64),
+    /// Both from -1 to 1, warmer and more magenta for positive ones.
+    WhiteBalance {
+        temperature: f64,
+        tint: f64,
+    },
+    /// From -1, which leaves no color, to 1.
+    Saturation(f64),
+    /// From 0 to 2.
+    Sharpen(f64),
+}
+
+impl Edit {
+    /// Reads an edit as it's written, like `exposure 0.5` or
+    /// `crop 3:2 0 0.1 1 0.8`. Crops written with only their aspect are the
+    /// largest centered ones.
+    pub fn parse(text: &str) -> Option<Edit> {
+        let mut words = text.split_whitespace();
+        let name = words.next()?;
+        let aspect = match name {
+            "crop" => Some(Aspect::from_name(words.next()?)?),
+            _ => None,
+        };
+        let values = words
+            .map(|word| word.parse::<f64>().ok().filter(|v| v.is_finite()))
+            .collect::<Option<Vec<f64>>>()?;
+        if let Some(aspect) = aspect {
+            let crop = match values[..] {
+                [] => Crop {
+                    aspect,
+                    ..Crop::default()
+                },
+                [x, y, width, height] => Crop {
+                    aspect,
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+                .clamped(),
+                _ => return None,
+            };
+            return Some(Edit::Crop(crop));
+        }
+        match (name, &values[..]) {
+            ("straighten", &[degrees]) => Some(Edit::Straighten(
+                degrees.clamp(-MAX_STRAIGHTEN, MAX_STRAIGHTEN),
+            )),
+            ("exposure", &[stops]) => Some(Edit::Exposure(stops)),
+            ("contrast", &[contrast]) => Some(Edit::Contrast(contrast)),
+            ("white-balance", &[temperature, tint]) => {
+                Some(Edit::WhiteBalance { temperature, tint })
+            }
+            ("saturation", &[saturation]) => Some(Edit::Saturation(saturation)),
+            ("sharpen", &[amount]) => Some(Edit::Sharpen(amount)),
+            _ => None,
+        }
+    }
+}
+
+impl Edit {
+    /// The edit made to the image after it's transformed like
+    /// `orientation::apply` does for `orientation`, so it has the same
+    /// effect. Mirroring the image turns it the other way.
+    fn oriented(self, orientation: u32) -> Edit {
+        let (transpose, flip_x, flip_y) = orientation::axes(orientation);
+        match self {
+            Edit::Crop(crop) => Edit::Crop(crop.oriented(orientation)),
+            Edit::Straighten(degrees) if transpose ^ flip_x ^ flip_y => {
+                Edit::Straighten(-degrees)
+            }
+            edit => edit,
+        }
+    }
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Edit::Crop(crop) => write!(
+                f,
+                "crop {} {} {} {} {}",
+                crop.aspect.name(),
+                crop.x,
+                crop.y,
+                crop.width,
+                crop.height
+            ),
+            Edit::Straighten(degrees) => write!(f, "straighten {}", degrees),
+            Edit::Exposure(stops) => write!(f, "exposure {}", stops),
+            Edit::Contrast(contrast) => write!(f, "contrast {}", contrast),
+            Edit::WhiteBalance { temperature, tint } => {
+                write!(f, "white-balance {} {}", temperature, tint)
+            }
+            Edit::Saturation(saturation) => {
+                write!(f, "saturation {}", saturation)
+            }
+            Edit::Sharpen(amount) => write!(f, "sharpen {}", amount),
+        }
+    }
+}
+
+/// What the edits of a stack add up to, with each adjustment at 0 when it
+/// leaves the image as it is.
+#[derive(Debug, Clone, Copy, Default, Data, Lens, PartialEq)]
+pub struct Adjustments {
+    pub crop: Crop,
+    pub straighten: f64,
+    pub exposure: f64,
+    pub contrast: f64,
+    pub temperature: f64,
+    pub tint: f64,
+    pub saturation: f64,
+    pub sharpen: f64,
+}
+
+impl Adjustments {
+    fn apply(&mut self, edit: Edit) {
+        match edit {
+            Edit::Crop(crop) => self.crop = crop,
+            Edit::Straighten(degrees) => {
+                self.straighten = degrees.clamp(-MAX_STRAIGHTEN, MAX_STRAIGHTEN)
+            }
+            Edit::Exposure(stops) => self.exposure = stops,
+            Edit::Contrast(contrast) => self.contrast = contrast,
+            Edit::WhiteBalance { temperature, tint } => {
+                self.temperature = temperature;
+                self.tint = tint;
+            }
+            Edit::Saturation(saturation) => self.saturation = saturation,
+            Edit::Sharpen(amount) => self.sharpen = amount,
+        }
+    }
+
+    /// The edits that change `self` into `other`.
+    fn changes(&self, other: &Adjustments) -> Vec<Edit> {
+        let mut edits = Vec::new();
+        if self.crop != other.crop {
+            edits.push(Edit::Crop(other.crop));
+        }
+        if self.straighten != other.straighten {
+            edits.push(Edit::Straighten(other.straighten));
+        }
+        if self.exposure != other.exposure {
+            edits.push(Edit::Exposure(other.exposure));
+        }
+        if self.contrast != other.contrast {
+            edits.push(Edit::Contrast(other.contrast));
+        }
+        if self.temperature != other.temperature || self.tint != other.tint {
+            edits.push(Edit::WhiteBalance {
+                temperature: other.temperature,
+                tint: other.tint,
+            });
+        }
+        if self.saturation != other.saturation {
+            edits.push(Edit::Saturation(other.saturation));
+        }
+        if self.sharpen != other.sharpen {
+            edits.push(Edit::Sharpen(other.sharpen));
+        }
+        edits
+    }
+
+    /// Whether the image comes out any different.
+    pub fn changes_image(&self) -> bool {
+        self != &Adjustments::default()
+    }
+}
+
+#[derive(Debug, Clone, Default, Data, PartialEq)]
+pub struct EditStack {
+    pub edits: Vector<Edit>,
+}
+
+impl EditStack {
+    pub fn adjustments(&self) -> Adjustments {
+        let mut adjustments = Adjustments::default();
+        for edit in self.edits.iter() {
+            adjustments.apply(*edit);
+        }
+        adjustments
+    }
+
+    /// Adds `edit` on top, replacing the last edit if it's of the same kind.
+    pub fn push(&mut self, edit: Edit) {
+        if let Some(last) = self.edits.last() {
+            if mem::discriminant(last) == mem::discriminant(&edit) {
+                self.edits.pop_back();
+            }
+        }
+        self.edits.push_back(edit);
+    }
+
+    /// Takes the last edit off.
+    pub fn undo(&mut self) {
+        self.edits.pop_back();
+    }
+
+    /// The edits of the image after it's rotated or flipped on disk like
+    /// `orientation::apply` does for `orientation`, so the same part of it
+    /// stays cropped.
+    pub fn oriented(&self, orientation: u32) -> EditStack {
+        EditStack {
+            edits: self
+                .edits
+                .iter()
+                .map(|edit| edit.oriented(orientation))
+                .collect(),
+        }
+    }
+}
+
+/// Lenses the adjustments of a stack, pushing an edit for each adjustment
+/// that's changed through it.
+pub struct StackAdjustments;
+
+impl Lens<EditStack, Adjustments> for StackAdjustments {
+    fn with<V, F: FnOnce(&Adjustments) -> V>(
+        &self,
+        data: &EditStack,
+        f: F,
+    ) -> V {
+        f(&data.adjustments())
+    }
+
+    fn with_mut<V, F: FnOnce(&mut Adjustments) -> V>(
+        &self,
+        data: &mut EditStack,
+        f: F,
+    ) -> V {
+        let old = data.adjustments();
+        let mut new = old;
+        let value = f(&mut new);
+        for edit in old.changes(&new) {
+            data.push(edit);
+        }
+        value
+    }
+}
+
+/// Applies the edits saved in the sidecar of the image at `path` to `image`,
+/// which is the upright image or a thumbnail of it.
+pub fn apply_saved(path: &Path, image: RgbImage) -> RgbImage {
+    match read_edits(path) {
+        Ok(edits) => render(image, &edits.adjustments()),
+        Err(err) => {
+            warn!("Couldn't read the edits of {}: {}", path.display(), err);
+            image
+        }
+    }
+}
+
+/// Applies `adjustments` to `image`, which is upright.
+pub fn render(image: RgbImage, adjustments: &Adjustments) -> RgbImage {
+    let mut image = image;
+    if adjustments.straighten != 0. {
+        let degrees = adjustments
+            .straighten
+            .clamp(-MAX_STRAIGHTEN, MAX_STRAIGHTEN);
+        image = straighten(&image, degrees);
+    }
+    if adjustments.crop != Crop::default() {
+        let (width, height) = image.dimensions();
+        let (left, top, width, height) = adjustments.crop.pixels(width, height);
+        image = imageops::crop_imm(&image, left, top, width, height).to_image();
+    }
+    adjust_tones(&mut image, adjustments);
+    if adjustments.sharpen > 0. {
+        image = sharpen(&image, adjustments.sharpen);
+    }
+    image
+}
+
+/// Turns the image and cuts out the largest rectangle of its aspect ratio
+/// that fits inside it.
+fn straighten(image: &RgbImage, degrees: f64) -> RgbImage {
+    let (width, height) = image.dimensions();
+    let (width, height) = (width as f64, height as f64);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let scale = (width / (width * cos + height * sin.abs()))
+        .min(height / (width * sin.abs() + height * cos));
+    let new_width = ((width * scale).floor() as u32).max(1);
+    let new_height = ((height * scale).floor() as u32).max(1);
+
+    let mut straightened = RgbImage::new(new_width, new_height);
+    for (x, y, pixel) in straightened.enumerate_pixels_mut() {
+        let dx = x as f64 + 0.5 - new_width as f64 / 2.;
+        let dy = y as f64 + 0.5 - new_height as f64 / 2.;
+        // turned back to where it is in the image, with y going down
+        let source_x = cos * dx + sin * dy + width / 2. - 0.5;
+        let source_y = -sin * dx + cos * dy + height / 2. - 0.5;
+        *pixel = sample(image, source_x, source_y);
+    }
+    straightened
+}
+
+/// The color at a position between pixels, interpolated bilinearly.
+fn sample(image: &RgbImage, x: f64, y: f64) -> Rgb<u8> {
+    let (width, height) = image.dimensions();
+    let x = x.clamp(0., (width - 1) as f64);
+    let y = y.clamp(0., (height - 1) as f64);
+    let (left, top) = (x.floor() as u32, y.floor() as u32);
+    let (right, bottom) =
+        ((left + 1).min(width - 1), (top + 1).min(height - 1));
+    let (fx, fy) = (x - left as f64, y - top as f64);
+
+    let mut pixel = Rgb([0; 3]);
+    for (channel, value) in pixel.0.iter_mut().enumerate() {
+        let at = |x, y| image.get_pixel(x, y)[channel] as f64;
+        let upper = at(left, top) * (1. - fx) + at(right, top) * fx;
+        let lower = at(left, bottom) * (1. - fx) + at(right, bottom) * fx;
+        *value = (upper * (1. - fy) + lower * fy).round() as u8;
+    }
+    pixel
+}
+
+/// How finely linear values are looked up when they're turned back into
+/// sRGB.
+const LINEAR_STEPS: usize = 4095;
+
+fn adjust_tones(image: &mut RgbImage, adjustments: &Adjustments) {
+    let Adjustments {
+        exposure,
+        contrast,
+        temperature,
+        tint,
+        saturation,
+        ..
+    } = *adjustments;
+    if exposure == 0.
+        && contrast == 0.
+        && temperature == 0.
+        && tint == 0.
+        && saturation == 0.
+    {
+        return;
+    }
+
+    // white balance and exposure scale the light, so they're applied to
+    // linear values rather than to the sRGB ones
+    let exposure = 2f32.powf(exposure as f32);
+    let (temperature, tint) = (temperature as f32, tint as f32);
+    let gains = [
+        exposure * (1. + 0.3 * temperature),
+        exposure * (1. - 0.2 * tint),
+        exposure * (1. - 0.3 * temperature),
+    ];
+    let contrast = 2f32.powf(contrast as f32);
+    let saturation = 1. + saturation as f32;
+
+    let to_linear: Vec<f32> = (0..=255)
+        .map(|value| srgb_to_linear(value as f32 / 255.))
+        .collect();
+    let to_srgb: Vec<f32> = (0..=LINEAR_STEPS)
+        .map(|step| linear_to_srgb(step as f32 / LINEAR_STEPS as f32))
+        .collect();
+
+    for pixel in image.pixels_mut() {
+        let mut rgb = [0.; 3];
+        for (channel, value) in rgb.iter_mut().enumerate() {
+            let linear = to_linear[pixel[channel] as usize] * gains[channel];
+            let step = (linear.clamp(0., 1.) * LINEAR_STEPS as f32).round();
+            *value = (to_srgb[step as usize] - 0.5) * contrast + 0.5;
+        }
+        let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+        for (value, adjusted) in pixel.0.iter_mut().zip(rgb.iter()) {
+            let adjusted = luma + (adjusted - luma) * saturation;
+            *value = (adjusted.clamp(0., 1.) * 255.).round() as u8;
+        }
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Unsharp masking, which adds the difference from a blurred copy of the
+/// image back to it.
+fn sharpen(image: &RgbImage, amount: f64) -> RgbImage {
+    let blurred = imageops::blur(image, 1.);
+    let mut sharpened = image.clone();
+    for (pixel, blurred) in sharpened.pixels_mut().zip(blurred.pixels()) {
+        for (value, blurred) in pixel.0.iter_mut().zip(blurred.0.iter()) {
+            let detail = *value as f64 - *blurred as f64;
+            *value =
+                (*value as f64 + detail * amount).round().clamp(0., 255.) as u8;
+        }
+    }
+    sharpened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image whose pixels all differ, so a crop in the wrong place shows.
+    fn fixture(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 0]))
+    }
+
+    fn crop(aspect: Aspect, x: f64, y: f64, width: f64, height: f64) -> Crop {
+        Crop {
+            aspect,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn crops_pixels() {
+        // the crop, the size of the image and the pixels that are kept
+        let cases = [
+            (Crop::default(), (40, 20), (0, 0, 40, 20)),
+            // shrunk around its center to the ratio of the image
+            (
+                crop(Aspect::Original, 0., 0., 1., 0.5),
+                (40, 20),
+                (10, 0, 20, 10),
+            ),
+            (
+                crop(Aspect::Square, 0., 0., 1., 1.),
+                (40, 20),
+                (10, 0, 20, 20),
+            ),
+            (
+                crop(Aspect::ThreeTwo, 0.25, 0.1, 0.5, 0.5),
+                (40, 20),
+                (13, 2, 15, 10),
+            ),
+            // turned to match a portrait image
+            (
+                crop(Aspect::ThreeTwo, 0., 0., 1., 1.),
+                (20, 40),
+                (0, 5, 20, 30),
+            ),
+            // values that were read are made sensible
+            (
+                crop(Aspect::Original, 0.95, 0., 0.01, 1.),
+                (40, 20),
+                (36, 9, 4, 2),
+            ),
+            (
+                crop(Aspect::Original, -1., 2., 1., 1.),
+                (40, 20),
+                (0, 0, 40, 20),
+            ),
+            // never less than a pixel, nor more than the image
+            (
+                crop(Aspect::SixteenNine, 0., 0., 1., 1.),
+                (1, 1),
+                (0, 0, 1, 1),
+            ),
+        ];
+        for (crop, (width, height), pixels) in cases.iter() {
+            assert_eq!(crop.pixels(*width, *height), *pixels, "{:?}", crop);
+        }
+    }
+
+    #[test]
+    fn largest_crops_fill_the_image() {
+        let cases = [
+            (
+                Aspect::Original,
+                (40, 20),
+                crop(Aspect::Original, 0., 0., 1., 1.),
+            ),
+            (
+                Aspect::Square,
+                (40, 20),
+                crop(Aspect::Square, 0.25, 0., 0.5, 1.),
+            ),
+            (
+                Aspect::ThreeTwo,
+                (20, 40),
+                crop(Aspect::ThreeTwo, 0., 0.125, 1., 0.75),
+            ),
+            (
+                Aspect::FourThree,
+                (40, 20),
+                crop(Aspect::FourThree, 1. / 6., 0., 2. / 3., 1.),
+            ),
+        ];
+        for (aspect, dimensions, largest) in cases.iter() {
+            let crop = Crop::largest(*aspect, Some(*dimensions));
+            for (value, expected) in [
+                (crop.x, largest.x),
+                (crop.y, largest.y),
+                (crop.width, largest.width),
+                (crop.height, largest.height),
+            ]
+            .iter()
+            {
+                assert!((value - expected).abs() < 1e-9, "{:?}", crop);
+            }
+            assert_eq!(crop.aspect, *aspect);
+            // which is the whole image once it's kept to its ratio
+            let (width, height) = *dimensions;
+            let pixels = crop.pixels(width, height);
+            assert!(pixels.2 == width || pixels.3 == height, "{:?}", pixels);
+        }
+
+        // without the size of the image, it's shrunk to the ratio once it's
+        // applied
+        assert_eq!(
+            Crop::largest(Aspect::Square, None),
+            crop(Aspect::Square, 0., 0., 1., 1.)
+        );
+        assert_eq!(
+            Crop::largest(Aspect::Square, Some((0, 10))),
+            crop(Aspect::Square, 0., 0., 1., 1.)
+        );
+    }
+
+    #[test]
+    fn writes_and_reads_edits() {
+        let edits = [
+            Edit::Crop(Crop::default()),
+            Edit::Crop(crop(Aspect::ThreeTwo, 0.125, 0.25, 0.5, 0.625)),
+            Edit::Crop(crop(Aspect::SixteenNine, 0.1, 0.2, 0.3, 0.4)),
+            Edit::Straighten(-12.5),
+            Edit::Exposure(0.5),
+            Edit::Contrast(-1.),
+            Edit::WhiteBalance {
+                temperature: 0.25,
+                tint: -0.75,
+            },
+            Edit::Saturation(1.),
+            Edit::Sharpen(2.),
+        ];
+        for edit in edits.iter() {
+            assert_eq!(Edit::parse(&edit.to_string()), Some(*edit));
+        }
+    }
+
+    #[test]
+    fn parses_edits() {
+        let cases = [
+            (
+                "crop 3:2",
+                Some(Edit::Crop(crop(Aspect::ThreeTwo, 0., 0., 1., 1.))),
+            ),
+            (
+                "  crop original 0 0.5 1 0.5 ",
+                Some(Edit::Crop(crop(Aspect::Original, 0., 0.5, 1., 0.5))),
+            ),
+            // crops are kept inside the image
+            (
+                "crop 1:1 0.75 0 0.5 0.05",
+                Some(Edit::Crop(crop(Aspect::Square, 0.5, 0., 0.5, 0.1))),
+            ),
+            ("straighten 90", Some(Edit::Straighten(45.))),
+            ("straighten -50", Some(Edit::Straighten(-45.))),
+            ("exposure -1.5", Some(Edit::Exposure(-1.5))),
+            ("crop", None),
+            ("crop 5:4", None),
+            ("crop 3:2 0 0", None),
+            ("exposure", None),
+            ("exposure 1 2", None),
+            ("exposure NaN", None),
+            ("contrast inf", None),
+            ("white-balance 0.5", None),
+            ("vignette 0.5", None),
+            ("", None),
+        ];
+        for (text, edit) in cases.iter() {
+            assert_eq!(Edit::parse(text), *edit, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn orients_crops_with_the_image() {
+        let image = fixture(40, 20);
+        let crops = [
+            crop(Aspect::Original, 0.25, 0.1, 0.5, 0.5),
+            crop(Aspect::Square, 0.25, 0.1, 0.5, 0.5),
+        ];
+        for orientation in 1..=8 {
+            let turned = orientation::apply(image.clone(), orientation);
+            for crop in crops.iter() {
+                // cropping and then turning keeps the same pixels as turning
+                // and then cropping with the turned crop
+                let (left, top, width, height) = crop.pixels(40, 20);
+                let cropped =
+                    imageops::crop_imm(&image, left, top, width, height);
+                let expected =
+                    orientation::apply(cropped.to_image(), orientation);
+
+                let oriented = crop.oriented(orientation);
+                let (width, height) = turned.dimensions();
+                let (left, top, width, height) = oriented.pixels(width, height);
+                let cropped =
+                    imageops::crop_imm(&turned, left, top, width, height);
+                assert_eq!(
+                    cropped.to_image(),
+                    expected,
+                    "orientation {}",
+                    orientation
+                );
+                assert_eq!(
+                    Edit::Crop(*crop).oriented(orientation),
+                    Edit::Crop(oriented)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn orients_straightening_with_the_image() {
+        for orientation in 1..=8 {
+            // mirroring the image turns it the other way
+            let mirrored = matches!(orientation, 2 | 4 | 5 | 7);
+            let degrees = if mirrored { -10. } else { 10. };
+            assert_eq!(
+                Edit::Straighten(10.).oriented(orientation),
+                Edit::Straighten(degrees),
+                "orientation {}",
+                orientation
+            );
+            for edit in [Edit::Exposure(1.), Edit::Sharpen(0.5)].iter() {
+                assert_eq!(edit.oriented(orientation), *edit);
+            }
+        }
+    }
+
+    /// Whether the edits are the same, but for rounding.
+    fn same_edits(a: &EditStack, b: &EditStack) -> bool {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        a.edits.len() == b.edits.len()
+            && a.edits.iter().zip(b.edits.iter()).all(|edits| match edits {
+                (Edit::Crop(a), Edit::Crop(b)) => {
+                    a.aspect == b.aspect
+                        && close(a.x, b.x)
+                        && close(a.y, b.y)
+                        && close(a.width, b.width)
+                        && close(a.height, b.height)
+                }
+                (a, b) => a == b,
+            })
+    }
+
+    #[test]
+    fn orients_edit_stacks() {
+        let stack = EditStack {
+            edits: vec![
+                Edit::Crop(crop(Aspect::ThreeTwo, 0.1, 0.2, 0.3, 0.4)),
+                Edit::Straighten(5.),
+                Edit::Exposure(1.),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        for orientation in 1..=8 {
+            // turning the image back undoes the turned edits
+            let back = (1..=8)
+                .find(|&back| orientation::compose(orientation, back) == 1)
+                .unwrap();
+            let turned = stack.oriented(orientation).oriented(back);
+            assert!(
+                same_edits(&turned, &stack),
+                "orientation {}: {:?}",
+                orientation,
+                turned
+            );
+        }
+    }
+}
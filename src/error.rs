@@ -0,0 +1,130 @@
+//! Errors from reading the library and its images.
+
+use std::{
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use druid::{EventCtx, ExtEventSink, Target};
+use image::ImageError;
+use log::error;
+
+use crate::app_commands::SHOW_ERROR;
+
+#[derive(Debug)]
+pub enum GalleryError {
+    /// A directory couldn't be listed.
+    ReadDir { path: PathBuf, source: io::Error },
+    /// An image couldn't be opened or decoded, or its thumbnail couldn't be
+    /// created.
+    Image { path: PathBuf, source: ImageError },
+    /// An image couldn't be renamed, copied, moved or trashed, or its sidecar
+    /// couldn't be written.
+    FileOperation {
+        operation: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl GalleryError {
+    pub fn read_dir(path: &Path, source: io::Error) -> Self {
+        GalleryError::ReadDir {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn image(path: &Path, source: ImageError) -> Self {
+        GalleryError::Image {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// `operation` is the verb of the message, as in "Couldn't rename
+    /// <path>".
+    pub fn file_operation(
+        operation: &'static str,
+        path: &Path,
+        source: io::Error,
+    ) -> Self {
+        GalleryError::FileOperation {
+            operation,
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            GalleryError::ReadDir { path, .. } => path,
+            GalleryError::Image { path, .. } => path,
+            GalleryError::FileOperation { path, .. } => path,
+        }
+    }
+
+    /// Why this happened, without the path, for places that already show
+    /// which file it's about.
+    pub fn reason(&self) -> String {
+        match self {
+            GalleryError::ReadDir { source, .. } => source.to_string(),
+            GalleryError::Image { source, .. } => source.to_string(),
+            GalleryError::FileOperation { source, .. } => source.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for GalleryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GalleryError::ReadDir { path, source } => {
+                write!(
+                    f,
+                    "Couldn't read the folder {}: {}",
+                    path.display(),
+                    source
+                )
+            }
+            GalleryError::Image { path, source } => {
+                write!(f, "Couldn't open {}: {}", path.display(), source)
+            }
+            GalleryError::FileOperation {
+                operation,
+                path,
+                source,
+            } => write!(
+                f,
+                "Couldn't {} {}: {}",
+                operation,
+                path.display(),
+                source
+            ),
+        }
+    }
+}
+
+impl Error for GalleryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GalleryError::ReadDir { source, .. } => Some(source),
+            GalleryError::Image { source, .. } => Some(source),
+            GalleryError::FileOperation { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Logs `err` and shows it in a toast.
+pub fn show_error(ctx: &mut EventCtx, err: GalleryError) {
+    error!("{}", err);
+    ctx.submit_command(SHOW_ERROR.with(err.to_string()));
+}
+
+/// Logs `err` and shows it in a toast, from a thread other than the UI thread.
+pub fn report_error(handle: &ExtEventSink, err: &GalleryError) {
+    error!("{}", err);
+    // this only fails when the app is closing, when there's nothing left to
+    // show the error in anyway
+    let _ = handle.submit_command(SHOW_ERROR, err.to_string(), Target::Auto);
+}
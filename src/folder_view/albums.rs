@@ -0,0 +1,104 @@
+//! Adding the images of a folder to albums, and taking them out of the open
+//! album again, from the context menu of an image.
+//!
+//! The images of an album are put in order by dragging them around the grid,
+//! and every change to them is saved to the library right away.
+
+use druid::{
+    widget::Controller, Env, Event, EventCtx, LocalizedString, MenuDesc,
+    MenuItem, Widget, WidgetExt,
+};
+
+use crate::{
+    app_commands::{
+        ADD_TO_ALBUM, CHANGED_ALBUM, REFRESH_COVER, REMOVE_FROM_ALBUM,
+    },
+    app_data::AppState,
+};
+
+use super::FolderGalleryState;
+
+/// Handles taking images out of the open album, and saves the album whenever
+/// `content` changes its images.
+pub fn with_albums(
+    content: impl Widget<FolderGalleryState> + 'static,
+) -> impl Widget<FolderGalleryState> {
+    content.controller(AlbumsController)
+}
+
+/// Adds the items adding the selected images to another album, or taking
+/// them out of the open one, to the end of `menu`.
+pub fn album_menu(
+    menu: MenuDesc<AppState>,
+    data: &FolderGalleryState,
+) -> MenuDesc<AppState> {
+    let others: Vec<_> = data
+        .albums
+        .iter()
+        .filter(|album| !data.album || **album != data.name)
+        .collect();
+    if others.is_empty() && !data.album {
+        return menu;
+    }
+
+    let mut menu = menu.append_separator();
+    if !others.is_empty() {
+        let paths = data.selected_paths();
+        let mut albums_menu = MenuDesc::new(
+            LocalizedString::new("gallery-add-to-album")
+                .with_placeholder("Add to Album"),
+        );
+        for album in others {
+            albums_menu = albums_menu.append(MenuItem::new(
+                LocalizedString::new("gallery-album")
+                    .with_placeholder(album.to_string_lossy().to_string()),
+                ADD_TO_ALBUM.with((album.clone(), paths.clone())),
+            ));
+        }
+        menu = menu.append(albums_menu);
+    }
+    if data.album {
+        menu = menu.append(MenuItem::new(
+            LocalizedString::new("gallery-remove-from-album")
+                .with_placeholder("Remove from Album"),
+            REMOVE_FROM_ALBUM,
+        ));
+    }
+    menu
+}
+
+struct AlbumsController;
+
+impl<W: Widget<FolderGalleryState>> Controller<FolderGalleryState, W>
+    for AlbumsController
+{
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut FolderGalleryState,
+        env: &Env,
+    ) {
+        if !data.album {
+            return child.event(ctx, event, data, env);
+        }
+        let old_paths = data.paths.clone();
+        match event {
+            Event::Command(cmd) if cmd.is(REMOVE_FROM_ALBUM) => {
+                // the images themselves are left alone
+                let removed = data.selected_paths();
+                data.remove_images(&removed);
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+        // dragging the images around, renaming, moving or trashing them and
+        // sorting the album all change it
+        if !data.paths.same(&old_paths) {
+            ctx.submit_command(CHANGED_ALBUM);
+            if data.paths.get(0) != old_paths.get(0) {
+                ctx.submit_command(REFRESH_COVER.with(data.name.clone()));
+            }
+        }
+    }
+}
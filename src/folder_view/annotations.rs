@@ -0,0 +1,424 @@
+//! Star ratings, color labels, flags and tags of the images of a folder, from
+//! the context menu of an image or the keyboard.
+//!
+//! 0 to 5 set the stars of the selected images, 6 to 9 toggle the red,
+//! yellow, green and blue labels, P picks them, X rejects them, U takes the
+//! flag off again and T edits their tags.
+
+use std::{path::PathBuf, sync::Arc, thread};
+
+use druid::{
+    im::{HashMap, Vector},
+    widget::{Controller, CrossAxisAlignment, Either, Flex, SizedBox},
+    Command, Env, Event, EventCtx, ExtEventSink, KbKey, KeyEvent, LifeCycle,
+    LifeCycleCtx, LocalizedString, MenuDesc, MenuItem, Target, UpdateCtx,
+    Widget, WidgetExt, WidgetId,
+};
+use log::warn;
+
+use crate::{
+    app_commands::{
+        ANNOTATE, CANCEL_TAGS, CONFIRM_TAGS, EDIT_TAGS, LOADED_ANNOTATIONS,
+        TAKE_FOCUS, UNSAVED_ANNOTATIONS,
+    },
+    app_data::AppState,
+    sidecar::{read_annotations, Annotations, ColorLabel, Flag},
+};
+
+use super::{edit_bar, FolderGalleryState, FolderView, SidecarWriter};
+
+/// A change to the annotations of the selected images.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Annotate {
+    Rating(u8),
+    /// Toggles the label, which is taken off if all the images have it.
+    Label(ColorLabel),
+    Flag(Flag),
+}
+
+/// Adds the tags bar above `content`, loads the annotations of the folder's
+/// images and handles changing them, having `writer` write their sidecars.
+pub fn with_annotations(
+    content: impl Widget<FolderGalleryState> + 'static,
+    writer: SidecarWriter,
+) -> impl Widget<FolderGalleryState> {
+    let tags_box = WidgetId::next();
+    let tags_bar = Either::new(
+        |data: &FolderGalleryState, _env| data.editing_tags,
+        edit_bar(
+            "Tags",
+            "Save",
+            FolderGalleryState::new_tags,
+            tags_box,
+            CONFIRM_TAGS,
+            CANCEL_TAGS,
+        ),
+        SizedBox::empty(),
+    );
+
+    Flex::column()
+        .with_child(tags_bar)
+        .with_flex_child(content, 1.0)
+        .cross_axis_alignment(CrossAxisAlignment::Fill)
+        .controller(AnnotationsController { tags_box, writer })
+}
+
+/// Adds the items changing the annotations of the selected images to the end
+/// of `menu`.
+pub fn annotation_menu(menu: MenuDesc<AppState>) -> MenuDesc<AppState> {
+    let item = |key: &'static str, title: &'static str, annotate| {
+        MenuItem::new(
+            LocalizedString::new(key).with_placeholder(title),
+            ANNOTATE.with(annotate),
+        )
+    };
+    let ratings = [
+        ("gallery-rating-0", "No Stars"),
+        ("gallery-rating-1", "★"),
+        ("gallery-rating-2", "★★"),
+        ("gallery-rating-3", "★★★"),
+        ("gallery-rating-4", "★★★★"),
+        ("gallery-rating-5", "★★★★★"),
+    ];
+    let labels = [
+        ("gallery-label-red", ColorLabel::Red),
+        ("gallery-label-yellow", ColorLabel::Yellow),
+        ("gallery-label-green", ColorLabel::Green),
+        ("gallery-label-blue", ColorLabel::Blue),
+        ("gallery-label-purple", ColorLabel::Purple),
+    ];
+
+    let mut rating_menu = MenuDesc::new(
+        LocalizedString::new("gallery-rating").with_placeholder("Rating"),
+    );
+    for (stars, (key, title)) in ratings.iter().enumerate() {
+        rating_menu =
+            rating_menu.append(item(key, title, Annotate::Rating(stars as u8)));
+    }
+    let mut label_menu = MenuDesc::new(
+        LocalizedString::new("gallery-label").with_placeholder("Color Label"),
+    );
+    for (key, label) in labels.iter() {
+        label_menu =
+            label_menu.append(item(key, label.name(), Annotate::Label(*label)));
+    }
+
+    menu.append_separator()
+        .append(rating_menu)
+        .append(label_menu)
+        .append(item("gallery-pick", "Pick", Annotate::Flag(Flag::Pick)))
+        .append(item(
+            "gallery-reject",
+            "Reject",
+            Annotate::Flag(Flag::Reject),
+        ))
+        .append(item(
+            "gallery-unflag",
+            "Unflag",
+            Annotate::Flag(Flag::Unflagged),
+        ))
+        .append(MenuItem::new(
+            LocalizedString::new("gallery-edit-tags")
+                .with_placeholder("Edit Tags…"),
+            EDIT_TAGS,
+        ))
+}
+
+/// The command of an annotation's keyboard shortcut.
+fn shortcut(key: &KeyEvent) -> Option<Command> {
+    if key.mods.ctrl() || key.mods.alt() || key.mods.meta() {
+        return None;
+    }
+    let annotate = match &key.key {
+        KbKey::Character(c) => match c.to_lowercase().as_str() {
+            "6" => Annotate::Label(ColorLabel::Red),
+            "7" => Annotate::Label(ColorLabel::Yellow),
+            "8" => Annotate::Label(ColorLabel::Green),
+            "9" => Annotate::Label(ColorLabel::Blue),
+            "p" => Annotate::Flag(Flag::Pick),
+            "x" => Annotate::Flag(Flag::Reject),
+            "u" => Annotate::Flag(Flag::Unflagged),
+            "t" => return Some(EDIT_TAGS.into()),
+            digit => Annotate::Rating(digit.parse().ok().filter(|s| *s <= 5)?),
+        },
+        _ => return None,
+    };
+    Some(ANNOTATE.with(annotate))
+}
+
+struct AnnotationsController {
+    tags_box: WidgetId,
+    writer: SidecarWriter,
+}
+
+impl AnnotationsController {
+    fn start_editing_tags(
+        &self,
+        ctx: &mut EventCtx,
+        data: &mut FolderGalleryState,
+    ) {
+        let tags: Vec<String> = shared_tags(data).into_iter().collect();
+        data.new_tags = tags.join(", ");
+        data.editing_tags = true;
+        ctx.submit_command(Command::new(
+            TAKE_FOCUS,
+            (),
+            Target::Widget(self.tags_box),
+        ));
+    }
+
+    fn stop_editing_tags(
+        &self,
+        ctx: &mut EventCtx,
+        data: &mut FolderGalleryState,
+    ) {
+        data.editing_tags = false;
+        ctx.submit_command(Command::new(TAKE_FOCUS, (), Target::Auto));
+    }
+
+    /// Keeps the new annotations of the images right away and has their
+    /// sidecars written on another thread, so large selections don't hold up
+    /// the UI.
+    fn set_annotations(
+        &self,
+        ctx: &mut EventCtx,
+        data: &mut FolderGalleryState,
+        changes: Vec<(Arc<PathBuf>, Annotations)>,
+    ) {
+        let changes: Vec<(Arc<PathBuf>, Annotations)> = changes
+            .into_iter()
+            .filter(|(path, annotations)| {
+                &data.annotations_of(path) != annotations
+            })
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        for (path, annotations) in &changes {
+            keep_annotations(data, path.clone(), annotations.clone());
+        }
+        self.writer.write_annotations(ctx, changes);
+    }
+}
+
+impl<W: Widget<FolderGalleryState>> Controller<FolderGalleryState, W>
+    for AnnotationsController
+{
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut FolderGalleryState,
+        env: &Env,
+    ) {
+        // the slideshow has keys of its own
+        let has_annotations = !data.paths.is_empty()
+            && data.current_view() != &FolderView::Slideshow;
+        match event {
+            Event::KeyDown(key) if has_annotations && !data.is_editing() => {
+                if let Some(command) = shortcut(key) {
+                    ctx.submit_command(command);
+                    ctx.set_handled();
+                    return;
+                }
+            }
+            Event::Command(cmd) if cmd.is(ANNOTATE) && has_annotations => {
+                let changes = annotate(data, *cmd.get_unchecked(ANNOTATE));
+                self.set_annotations(ctx, data, changes);
+            }
+            Event::Command(cmd) if cmd.is(EDIT_TAGS) && has_annotations => {
+                self.start_editing_tags(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(CONFIRM_TAGS) => {
+                self.stop_editing_tags(ctx, data);
+                let changes = typed_tags(data);
+                self.set_annotations(ctx, data, changes);
+            }
+            Event::Command(cmd) if cmd.is(CANCEL_TAGS) => {
+                self.stop_editing_tags(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(LOADED_ANNOTATIONS) => {
+                let (folder, loaded) = cmd.get_unchecked(LOADED_ANNOTATIONS);
+                if folder == &data.name {
+                    // annotations changed while the sidecars were read are
+                    // newer than what was read
+                    data.annotations =
+                        data.annotations.clone().union(loaded.clone());
+                }
+                return;
+            }
+            Event::Command(cmd) if cmd.is(UNSAVED_ANNOTATIONS) => {
+                for (path, annotations) in
+                    cmd.get_unchecked(UNSAVED_ANNOTATIONS)
+                {
+                    if data.paths.contains(path) {
+                        keep_annotations(
+                            data,
+                            path.clone(),
+                            annotations.clone(),
+                        );
+                    }
+                }
+                return;
+            }
+            _ => (),
+        }
+        child.event(ctx, event, data, env)
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &FolderGalleryState,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            load_annotations(data, ctx.get_external_handle(), ctx.widget_id());
+        }
+        child.lifecycle(ctx, event, data, env)
+    }
+
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &FolderGalleryState,
+        data: &FolderGalleryState,
+        env: &Env,
+    ) {
+        if old_data.name != data.name {
+            load_annotations(data, ctx.get_external_handle(), ctx.widget_id());
+        }
+        child.update(ctx, old_data, data, env)
+    }
+}
+
+/// Reads the sidecars of the folder's images on another thread, and sends
+/// the annotations found to `widget`.
+fn load_annotations(
+    data: &FolderGalleryState,
+    handle: ExtEventSink,
+    widget: WidgetId,
+) {
+    if data.paths.is_empty() {
+        return;
+    }
+    let folder = data.name.clone();
+    let paths = data.paths.clone();
+    thread::spawn(move || {
+        let mut loaded: HashMap<Arc<PathBuf>, Annotations> = HashMap::new();
+        for path in paths.iter() {
+            match read_annotations(path) {
+                Ok(annotations) if annotations != Annotations::default() => {
+                    loaded.insert(path.clone(), annotations);
+                }
+                Ok(_) => (),
+                Err(err) => warn!(
+                    "Couldn't read the sidecar of {}: {}",
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        // this only fails when the app is closing
+        let _ = handle.submit_command(
+            LOADED_ANNOTATIONS,
+            (folder, loaded),
+            Target::Widget(widget),
+        );
+    });
+}
+
+/// The new annotations of the selected images.
+fn annotate(
+    data: &FolderGalleryState,
+    annotate: Annotate,
+) -> Vec<(Arc<PathBuf>, Annotations)> {
+    let paths = data.selected_paths();
+    let remove_label = match annotate {
+        Annotate::Label(label) => paths
+            .iter()
+            .all(|path| data.annotations_of(path).label == Some(label)),
+        _ => false,
+    };
+    let mut changes = Vec::new();
+    for path in paths {
+        let mut annotations = data.annotations_of(&path);
+        // XMP keeps rejects as a rating of -1, so rejected images have no
+        // stars
+        match annotate {
+            Annotate::Rating(rating) => {
+                annotations.rating = rating;
+                if annotations.flag == Flag::Reject {
+                    annotations.flag = Flag::Unflagged;
+                }
+            }
+            Annotate::Label(_) if remove_label => annotations.label = None,
+            Annotate::Label(label) => annotations.label = Some(label),
+            Annotate::Flag(flag) => {
+                annotations.flag = flag;
+                if flag == Flag::Reject {
+                    annotations.rating = 0;
+                }
+            }
+        }
+        changes.push((path, annotations));
+    }
+    changes
+}
+
+/// The tags all the selected images have.
+fn shared_tags(data: &FolderGalleryState) -> Vector<String> {
+    let paths = data.selected_paths();
+    let mut tags = match paths.first() {
+        Some(path) => data.annotations_of(path).tags,
+        None => return Vector::new(),
+    };
+    for path in &paths[1..] {
+        let other = data.annotations_of(path).tags;
+        tags.retain(|tag| other.contains(tag));
+    }
+    tags
+}
+
+/// The annotations of the selected images with the tags typed into the tags
+/// bar. The bar only showed the tags they all have, so their other tags are
+/// kept.
+fn typed_tags(data: &FolderGalleryState) -> Vec<(Arc<PathBuf>, Annotations)> {
+    let mut typed: Vector<String> = Vector::new();
+    for tag in data.new_tags.split(',').map(str::trim) {
+        if !tag.is_empty() && !typed.contains(&tag.to_string()) {
+            typed.push_back(tag.to_string());
+        }
+    }
+    let shared = shared_tags(data);
+    let mut changes = Vec::new();
+    for path in data.selected_paths() {
+        let mut annotations = data.annotations_of(&path);
+        let mut tags: Vector<String> = annotations
+            .tags
+            .iter()
+            .filter(|tag| !shared.contains(tag) && !typed.contains(tag))
+            .cloned()
+            .collect();
+        tags.append(typed.clone());
+        annotations.tags = tags;
+        changes.push((path, annotations));
+    }
+    changes
+}
+
+fn keep_annotations(
+    data: &mut FolderGalleryState,
+    path: Arc<PathBuf>,
+    annotations: Annotations,
+) {
+    if annotations == Annotations::default() {
+        data.annotations.remove(&path);
+    } else {
+        data.annotations.insert(path, annotations);
+    }
+}
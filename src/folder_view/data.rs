@@ -1,60 +1,586 @@
 use std::{
+    collections::{HashMap as StdHashMap, HashSet as StdHashSet},
+    ops::Range,
     path::PathBuf,
     sync::{
-        mpsc::{sync_channel, Receiver, SyncSender},
+        mpsc::{channel, Receiver, Sender},
         Arc,
     },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use druid::{
-    im::{vector, Vector},
+    im::{vector, HashMap, HashSet, Vector},
     piet::ImageFormat,
     widget::{Container, Controller, Image, ScopeTransfer},
-    Data, Env, Event, ExtEventSink, ImageBuf, Lens, LifeCycle, LifeCycleCtx,
-    UpdateCtx, Widget, WidgetId,
+    Command, Data, Env, Event, ExtEventSink, ImageBuf, KbKey, Lens, LifeCycle,
+    LifeCycleCtx, Size, Target, TimerToken, UpdateCtx, Widget, WidgetId,
+    WindowHandle, WindowState,
 };
-use druid_gridview::GridIter;
 use druid_navigator::navigator::{View, ViewController};
 use image::RgbImage;
+use log::{error, warn};
 
 use crate::{
     app_commands::{
-        CREATED_THUMBNAIL, FINISHED_READING_IMAGE, POP_FOLDER_VIEW,
-        PUSH_VIEW_WITH_SELECTED_IMAGE,
+        CHANGED_IMAGE, CREATED_THUMBNAIL, FINISHED_READING_IMAGE,
+        POP_FOLDER_VIEW, PUSH_VIEW_WITH_SELECTED_IMAGE, ROTATED_IMAGES,
+        SELECT_WITH_CLICK, SHOW_ERROR, SHOW_IMAGE_MENU, SORTED_IMAGES,
+        START_SLIDESHOW, TAKE_FOCUS,
     },
-    app_data::{AppState, Thumbnail},
+    app_data::{AppState, Thumbnail, ThumbnailState},
+    config::{JpegRotation, SlideshowSettings},
+    edits::{self, Adjustments, EditStack},
+    error::{report_error, GalleryError},
+    image_cache::{self, CachedImage},
+    main_view::AppView,
+    metadata::ImageMetadata,
+    orientation,
+    sidecar::Annotations,
+    sort::{sort_paths, SortMode},
+    widgets::{fit_image, Crossfade, GridItems, GridSelection, ZoomImage},
+    workers::{self, CancelToken, Priority},
 };
+
+use super::queue_thumbnail;
+
+/// How many images before and after the viewport keep their thumbnails.
+const KEEP_THUMBNAILS: usize = 300;
+
 #[derive(Debug, Clone, Data, Lens)]
 pub struct FolderGalleryState {
     pub name: Arc<PathBuf>,
     pub images: Vector<Thumbnail>,
     pub selected_folder: Option<usize>,
     pub selected_image: usize,
+    /// The images selected in the grid with Ctrl and Shift clicks, Ctrl+A or
+    /// a rubber band, which file operations apply to. `selected_image` is
+    /// the focused image, which doesn't have to be one of them.
+    pub selection: HashSet<Arc<PathBuf>>,
     pub views: Vector<FolderView>,
     pub paths: Vector<Arc<PathBuf>>,
+    /// Metadata of the images that have been opened, kept for sorting.
+    pub metadata: HashMap<Arc<PathBuf>, Arc<ImageMetadata>>,
+    /// Whether the metadata panel is shown in the single image view.
+    pub show_info: bool,
+    pub sort: SortMode,
+    pub sort_ascending: bool,
+    pub slideshow: SlideshowSettings,
+    pub slideshow_playing: bool,
+    /// The order the slideshow shows the images in, shuffled if enabled.
+    pub slideshow_order: Vector<usize>,
+    pub jpeg_rotation: JpegRotation,
+    /// Cancels the thumbnails that are still queued when the folder is left.
+    pub jobs: CancelToken,
+    /// The range of images whose thumbnails are in or near the viewport of
+    /// the grid, which are the only ones that are loaded.
+    pub visible_images: (usize, usize),
+    /// Whether the rename bar is shown for the selected image.
+    pub renaming: bool,
+    /// The name typed into the rename bar.
+    pub new_name: String,
+    /// Ratings, labels, flags and tags read from the images' sidecars.
+    /// Images without any aren't in here.
+    pub annotations: HashMap<Arc<PathBuf>, Annotations>,
+    /// Whether the tags bar is shown for the selected images.
+    pub editing_tags: bool,
+    /// The tags typed into the tags bar, separated by commas.
+    pub new_tags: String,
+    /// The edit stacks read from the images' sidecars. Images without edits
+    /// aren't in here.
+    pub edits: HashMap<Arc<PathBuf>, EditStack>,
+    /// Whether the edit panel is shown in the single image view.
+    pub show_edits: bool,
+    /// Whether the folder is an album, whose images are put in order by
+    /// dragging them around the grid.
+    pub album: bool,
+    /// The names of the library's albums, which images can be added to.
+    pub albums: Vector<Arc<PathBuf>>,
+    /// Whether this is the folder of an image opened on its own, rather than
+    /// a folder of the library.
+    pub image_view: bool,
 }
 
 impl FolderGalleryState {
     pub fn new(state: AppState) -> Self {
-        if let Some(idx) = state.selected_folder {
-            Self {
-                name: state.all_images[idx].name.clone(),
-                images: state.all_images[idx].thumbnails.clone(),
-                selected_folder: Some(idx),
-                selected_image: 0,
-                views: vector![FolderView::Folder],
-                paths: state.all_images[idx].paths.clone(),
+        let mut gallery = Self {
+            name: Arc::new(PathBuf::from("".to_string())),
+            images: Vector::new(),
+            selected_folder: None,
+            selected_image: 0,
+            selection: HashSet::new(),
+            views: vector![FolderView::Folder],
+            paths: Vector::new(),
+            metadata: HashMap::new(),
+            show_info: false,
+            sort: SortMode::Name,
+            sort_ascending: true,
+            slideshow: state.slideshow.clone(),
+            slideshow_playing: false,
+            slideshow_order: Vector::new(),
+            jpeg_rotation: state.jpeg_rotation,
+            jobs: state.folder_jobs.clone(),
+            visible_images: (0, 0),
+            renaming: false,
+            new_name: String::new(),
+            annotations: HashMap::new(),
+            editing_tags: false,
+            new_tags: String::new(),
+            edits: HashMap::new(),
+            show_edits: false,
+            album: false,
+            albums: album_names(&state),
+            image_view: state.current_view() == &AppView::ImageView,
+        };
+        if let Some(folder) = state.open_folder() {
+            gallery.name = folder.name.clone();
+            gallery.album = folder.album;
+            gallery.images = folder.thumbnails.clone();
+            gallery.selected_folder = state.selected_folder;
+            gallery.paths = folder.paths.clone();
+            gallery.metadata = folder.metadata.clone();
+            gallery.selected_image = folder.selected.unwrap_or(0);
+        }
+        gallery
+    }
+}
+
+impl FolderGalleryState {
+    /// The metadata of the selected image, if it has been read.
+    pub fn current_metadata(&self) -> Option<Arc<ImageMetadata>> {
+        let path = self.paths.get(self.selected_image)?;
+        self.metadata.get(path).cloned()
+    }
+
+    /// The images that file operations apply to: the selected images in the
+    /// grid, in the order they're shown, or otherwise the focused image.
+    pub fn selected_paths(&self) -> Vec<Arc<PathBuf>> {
+        if self.current_view() == &FolderView::Folder
+            && !self.selection.is_empty()
+        {
+            return self
+                .paths
+                .iter()
+                .filter(|path| self.selection.contains(*path))
+                .cloned()
+                .collect();
+        }
+        self.paths
+            .get(self.selected_image)
+            .cloned()
+            .into_iter()
+            .collect()
+    }
+
+    pub fn annotations_of(&self, path: &Arc<PathBuf>) -> Annotations {
+        self.annotations.get(path).cloned().unwrap_or_default()
+    }
+
+    /// The annotations of the focused image.
+    pub fn current_annotations(&self) -> Annotations {
+        self.paths
+            .get(self.selected_image)
+            .map(|path| self.annotations_of(path))
+            .unwrap_or_default()
+    }
+
+    pub fn edits_of(&self, path: &Arc<PathBuf>) -> EditStack {
+        self.edits.get(path).cloned().unwrap_or_default()
+    }
+
+    /// The edit stack of the focused image.
+    pub fn current_edits(&self) -> EditStack {
+        self.paths
+            .get(self.selected_image)
+            .map(|path| self.edits_of(path))
+            .unwrap_or_default()
+    }
+
+    /// Whether text is being typed into one of the bars, so keys shouldn't
+    /// act on the images.
+    pub fn is_editing(&self) -> bool {
+        self.renaming || self.editing_tags
+    }
+
+    pub fn is_selected(&self, index: usize) -> bool {
+        self.paths
+            .get(index)
+            .map_or(false, |path| self.selection.contains(path))
+    }
+
+    /// Adds the image at `index` to the selection or takes it out, and
+    /// focuses it.
+    pub fn toggle_selected(&mut self, index: usize) {
+        let path = match self.paths.get(index) {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        if self.selection.remove(&path).is_none() {
+            self.selection.insert(path);
+        }
+        self.selected_image = index;
+    }
+
+    /// Selects the images from the focused one to the one at `index`, in
+    /// addition to the selection if `extend` is set or instead of it.
+    pub fn select_range(&mut self, index: usize, extend: bool) {
+        if !extend {
+            self.selection.clear();
+        }
+        let start = index.min(self.selected_image);
+        let end = index.max(self.selected_image) + 1;
+        for path in self.paths.iter().skip(start).take(end - start) {
+            self.selection.insert(path.clone());
+        }
+    }
+
+    pub fn select_all(&mut self) {
+        self.selection = self.paths.iter().cloned().collect();
+    }
+
+    pub fn previous_image(&mut self) {
+        if self.paths.is_empty() || self.selected_image == 0 {
+            return;
+        }
+        self.selected_image -= 1;
+    }
+
+    pub fn next_image(&mut self) {
+        if self.paths.is_empty() || self.selected_image == self.paths.len() - 1
+        {
+            return;
+        }
+        self.selected_image += 1;
+    }
+
+    /// Replaces the contents of the folder after they changed on disk, keeping
+    /// the selected image if it's still there.
+    pub fn set_contents(
+        &mut self,
+        paths: Vector<Arc<PathBuf>>,
+        images: Vector<Thumbnail>,
+    ) {
+        if !paths.same(&self.paths) {
+            let selected = self.paths.get(self.selected_image).cloned();
+            self.selected_image =
+                selected.and_then(|path| paths.index_of(&path)).unwrap_or(0);
+            if !self.selection.is_empty() {
+                let listed: StdHashSet<&Arc<PathBuf>> = paths.iter().collect();
+                self.selection.retain(|path| listed.contains(path));
+            }
+            self.paths = paths;
+            if !self.slideshow_order.is_empty() {
+                self.update_slideshow_order();
+            }
+        }
+        self.images = images;
+    }
+
+    /// Drops the images at `removed` after they were moved out of the folder,
+    /// shifting the images after them back. The selection moves to the image
+    /// that took the place of the selected one if it was removed.
+    pub fn remove_images(&mut self, removed: &[Arc<PathBuf>]) {
+        let removing: StdHashSet<&Arc<PathBuf>> = removed.iter().collect();
+        let selected = self
+            .paths
+            .iter()
+            .take(self.selected_image)
+            .filter(|path| !removing.contains(path))
+            .count();
+        let kept: Vec<(Arc<PathBuf>, Thumbnail)> = self
+            .paths
+            .iter()
+            .cloned()
+            .zip(self.images.iter().cloned())
+            .filter(|(path, _)| !removing.contains(path))
+            .collect();
+        self.selected_image = selected.min(kept.len().saturating_sub(1));
+        self.images = kept
+            .iter()
+            .enumerate()
+            .map(|(index, (_, thumbnail))| Thumbnail {
+                index,
+                ..thumbnail.clone()
+            })
+            .collect();
+        self.paths = kept.into_iter().map(|(path, _)| path).collect();
+        for path in removed {
+            self.selection.remove(path);
+            self.annotations.remove(path);
+            self.edits.remove(path);
+            self.metadata.remove(path);
+            image_cache::remove(path);
+        }
+        if !self.slideshow_order.is_empty() {
+            self.update_slideshow_order();
+        }
+    }
+
+    /// Replaces `old` with `new` after the image was renamed, keeping its
+    /// place and its thumbnail.
+    pub fn rename_image(&mut self, old: &Arc<PathBuf>, new: Arc<PathBuf>) {
+        let index = match self.paths.index_of(old) {
+            Some(index) => index,
+            None => return,
+        };
+        if let Some(metadata) = self.metadata.remove(old) {
+            self.metadata.insert(new.clone(), metadata);
+        }
+        if self.selection.remove(old).is_some() {
+            self.selection.insert(new.clone());
+        }
+        if let Some(annotations) = self.annotations.remove(old) {
+            self.annotations.insert(new.clone(), annotations);
+        }
+        if let Some(edits) = self.edits.remove(old) {
+            self.edits.insert(new.clone(), edits);
+        }
+        image_cache::remove(old);
+        self.paths[index] = new;
+    }
+
+    /// Drops the thumbnails of images far from the viewport, so large
+    /// folders don't keep every thumbnail in memory. They're created again
+    /// once they scroll back into view.
+    fn evict_thumbnails(&mut self) {
+        let (start, end) = self.visible_images;
+        let keep = start.saturating_sub(KEEP_THUMBNAILS)..end + KEEP_THUMBNAILS;
+        // the images are shared with the library, so they're only copied if
+        // any are dropped
+        let evicted: Vec<usize> = self
+            .images
+            .iter()
+            .enumerate()
+            .filter(|(index, thumbnail)| {
+                !keep.contains(index)
+                    && thumbnail.state == ThumbnailState::Loaded
+            })
+            .map(|(index, _)| index)
+            .collect();
+        for index in evicted {
+            let format = self.images[index].format;
+            self.images[index] = Thumbnail {
+                format,
+                ..Thumbnail::empty(index)
+            };
+        }
+    }
+
+    /// Plays the slideshow from the selected image.
+    pub fn start_slideshow(&mut self) {
+        self.slideshow_playing = true;
+        self.update_slideshow_order();
+    }
+
+    /// Shuffles the slideshow or puts it back in order, depending on its
+    /// settings. A shuffled slideshow starts with the selected image, one in
+    /// order goes on from it.
+    pub fn update_slideshow_order(&mut self) {
+        let mut order: Vec<usize> = (0..self.paths.len()).collect();
+        if self.slideshow.shuffle {
+            shuffle(&mut order);
+            if let Some(position) =
+                order.iter().position(|&idx| idx == self.selected_image)
+            {
+                order.swap(0, position);
             }
+        }
+        self.slideshow_order = order.into_iter().collect();
+    }
+
+    fn slideshow_position(&self) -> usize {
+        self.slideshow_order
+            .index_of(&self.selected_image)
+            .unwrap_or(0)
+    }
+
+    /// Moves to the next image of the slideshow. Returns `false` if it's at
+    /// the end and doesn't loop.
+    pub fn next_slide(&mut self) -> bool {
+        let position = self.slideshow_position();
+        match self.slideshow_order.get(position + 1) {
+            Some(&next) => self.selected_image = next,
+            None if self.slideshow.looping => match self.slideshow_order.get(0)
+            {
+                Some(&first) => self.selected_image = first,
+                None => return false,
+            },
+            None => return false,
+        }
+        true
+    }
+
+    /// Moves the slideshow on once the shown image's interval is up, and
+    /// stops it at the end if it doesn't loop. Returns whether the same image
+    /// is still shown, as with a looping slideshow of a single image, in
+    /// which case nothing is read to start the next interval.
+    pub fn advance_slideshow(&mut self) -> bool {
+        if !self.slideshow_playing {
+            return false;
+        }
+        let shown = self.paths.get(self.selected_image).cloned();
+        if !self.next_slide() {
+            self.slideshow_playing = false;
+            return false;
+        }
+        self.paths.get(self.selected_image) == shown.as_ref()
+    }
+
+    pub fn previous_slide(&mut self) {
+        let position = self.slideshow_position();
+        if position > 0 {
+            self.selected_image = self.slideshow_order[position - 1];
+        } else if self.slideshow.looping {
+            if let Some(&last) = self.slideshow_order.last() {
+                self.selected_image = last;
+            }
+        }
+    }
+
+    /// Moves the image at `from`, along with the rest of the selection if
+    /// it's selected, so they come before the image at `to`, or last if `to`
+    /// is past the end.
+    pub fn move_images(&mut self, from: usize, to: usize) {
+        let moved: Vec<Arc<PathBuf>> = if self.is_selected(from) {
+            self.paths
+                .iter()
+                .filter(|path| self.selection.contains(*path))
+                .cloned()
+                .collect()
         } else {
-            Self {
-                name: Arc::new(PathBuf::from("".to_string())),
-                images: Vector::new(),
-                selected_folder: None,
-                selected_image: 0,
-                views: vector![FolderView::Folder],
-                paths: Vector::new(),
+            self.paths.get(from).cloned().into_iter().collect()
+        };
+        let moving: StdHashSet<&Arc<PathBuf>> = moved.iter().collect();
+        let before = self
+            .paths
+            .iter()
+            .take(to)
+            .filter(|path| !moving.contains(*path))
+            .count();
+        let mut paths: Vector<Arc<PathBuf>> = self
+            .paths
+            .iter()
+            .filter(|path| !moving.contains(*path))
+            .cloned()
+            .collect();
+        for (offset, path) in moved.iter().enumerate() {
+            paths.insert(before + offset, path.clone());
+        }
+        if paths != self.paths {
+            self.set_order(paths);
+        }
+    }
+
+    /// Puts the images in the order of `paths`. The selected image stays
+    /// selected. Orders worked out on another thread can be out of date by
+    /// the time they arrive, so paths that left the folder meanwhile are
+    /// skipped and images missing from `paths` keep their order at the end.
+    pub fn set_order(&mut self, paths: Vector<Arc<PathBuf>>) {
+        let selected = self.paths.get(self.selected_image).cloned();
+        let mut thumbnails: StdHashMap<_, _> = self
+            .paths
+            .iter()
+            .cloned()
+            .zip(self.images.iter().cloned())
+            .collect();
+        let mut ordered = Vec::with_capacity(self.paths.len());
+        for path in paths.iter().chain(self.paths.iter()) {
+            if let Some(thumbnail) = thumbnails.remove(path) {
+                ordered.push((path.clone(), thumbnail));
             }
         }
+        self.images = ordered
+            .iter()
+            .enumerate()
+            .map(|(index, (_, thumbnail))| Thumbnail {
+                index,
+                ..thumbnail.clone()
+            })
+            .collect();
+        self.paths = ordered.into_iter().map(|(path, _)| path).collect();
+        self.selected_image = selected
+            .and_then(|path| self.paths.index_of(&path))
+            .unwrap_or(0);
+    }
+}
+
+/// Shuffles `items` with a Fisher-Yates shuffle. The random numbers come from
+/// a xorshift generator seeded with the time, which is plenty for picking an
+/// order to show images in.
+fn shuffle(items: &mut [usize]) {
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or(0)
+        | 1;
+    for i in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let j = (state % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// The order of a folder's images, computed on another thread by
+/// `sort_images`.
+#[derive(Debug, Clone)]
+pub struct SortedImages {
+    pub folder: Arc<PathBuf>,
+    pub sort: SortMode,
+    pub ascending: bool,
+    pub paths: Vector<Arc<PathBuf>>,
+    /// Metadata that was read to sort the images.
+    pub metadata: HashMap<Arc<PathBuf>, Arc<ImageMetadata>>,
+}
+
+/// Sorts the images of the folder by its current sort mode on another thread,
+/// which sends the result back with `SORTED_IMAGES`.
+pub fn sort_images(handle: ExtEventSink, data: &FolderGalleryState) {
+    let folder = data.name.clone();
+    let sort = data.sort;
+    let ascending = data.sort_ascending;
+    let mut paths: Vec<_> = data.paths.iter().cloned().collect();
+    let metadata = data.metadata.clone();
+    thread::spawn(move || {
+        let metadata = sort_paths(&mut paths, sort, ascending, &metadata);
+        let sorted = SortedImages {
+            folder,
+            sort,
+            ascending,
+            paths: paths.into_iter().collect(),
+            metadata,
+        };
+        // this only fails when the app is closing
+        let _ = handle.submit_command(SORTED_IMAGES, sorted, Target::Auto);
+    });
+}
+
+impl GridSelection for FolderGalleryState {
+    fn item_count(&self) -> usize {
+        self.images.len()
+    }
+
+    fn selected_item(&self) -> Option<usize> {
+        if self.images.is_empty() {
+            None
+        } else {
+            Some(self.selected_image)
+        }
+    }
+
+    fn select_item(&mut self, position: usize) {
+        self.selected_image = position;
+    }
+
+    fn is_top_view(&self) -> bool {
+        self.views.len() == 1
+    }
+
+    fn set_visible_items(&mut self, items: Range<usize>) {
+        self.visible_images = (items.start, items.end);
+        self.evict_thumbnails();
     }
 }
 
@@ -84,10 +610,61 @@ impl ViewController<FolderView> for FolderGalleryState {
 pub enum FolderView {
     Folder,
     SingleImage,
+    /// The selected image over a strip of the thumbnails around it, as an
+    /// alternative to the grid of `Folder`.
+    Filmstrip,
+    Slideshow,
 }
 impl View for FolderView {}
 
-pub struct FolderViewController;
+/// Moves between the views of a folder, changes the selection of the grid and
+/// creates the thumbnails of the images that scroll into view.
+pub struct FolderViewController {
+    /// The thumbnails that are queued, so they aren't queued twice and can
+    /// be cancelled if they scroll out of view before they're created.
+    requested: StdHashMap<Arc<PathBuf>, CancelToken>,
+}
+
+impl FolderViewController {
+    pub fn new() -> Self {
+        FolderViewController {
+            requested: StdHashMap::new(),
+        }
+    }
+
+    fn request_thumbnails(
+        &mut self,
+        data: &FolderGalleryState,
+        handle: ExtEventSink,
+    ) {
+        let (start, end) = data.visible_images;
+        let wanted: StdHashMap<&Arc<PathBuf>, usize> = (start..end)
+            .filter(|index| {
+                data.images.get(*index).map(|thumbnail| &thumbnail.state)
+                    == Some(&ThumbnailState::Empty)
+            })
+            .filter_map(|index| Some((data.paths.get(index)?, index)))
+            .collect();
+        self.requested.retain(|path, cancel| {
+            if wanted.contains_key(path) {
+                true
+            } else {
+                // it scrolled out of view or was created in the meantime
+                cancel.cancel();
+                false
+            }
+        });
+        for (path, index) in wanted {
+            if self.requested.contains_key(path) {
+                continue;
+            }
+            // leaving the folder cancels these along with its other jobs
+            let cancel = data.jobs.child();
+            queue_thumbnail(index, path.clone(), &cancel, handle.clone());
+            self.requested.insert(path.clone(), cancel);
+        }
+    }
+}
 
 impl Controller<FolderGalleryState, Container<FolderGalleryState>>
     for FolderViewController
@@ -106,55 +683,159 @@ impl Controller<FolderGalleryState, Container<FolderGalleryState>>
             {
                 let (view, idx) =
                     selector.get_unchecked(PUSH_VIEW_WITH_SELECTED_IMAGE);
-                data.add_view(view.clone());
-                data.selected_image = *idx;
+                // the index could be from before images were removed
+                if *idx < data.paths.len() {
+                    data.add_view(view.clone());
+                    data.selected_image = *idx;
+                }
+            }
+            Event::Command(cmd) if cmd.is(START_SLIDESHOW) => {
+                if !data.paths.is_empty() {
+                    data.start_slideshow();
+                    data.add_view(FolderView::Slideshow);
+                }
             }
             Event::Command(cmd) if cmd.is(CREATED_THUMBNAIL) => {
-                let thumbnail = cmd.get_unchecked(CREATED_THUMBNAIL);
-                // TODO: this isn't workign correctly because this can be out of bounds
-                data.images[thumbnail.index] = thumbnail.clone();
+                let (path, thumbnail) = cmd.get_unchecked(CREATED_THUMBNAIL);
+                // the images could have been sorted since the thumbnail was
+                // requested, so its index is only a hint
+                let index = if data.paths.get(thumbnail.index) == Some(path) {
+                    Some(thumbnail.index)
+                } else {
+                    data.paths.index_of(path)
+                };
+                if let Some(index) = index {
+                    data.images[index] = Thumbnail {
+                        index,
+                        ..thumbnail.clone()
+                    };
+                }
+            }
+            Event::Command(cmd) if cmd.is(SORTED_IMAGES) => {
+                let sorted = cmd.get_unchecked(SORTED_IMAGES);
+                // ignore orders of another folder or of a sort mode that was
+                // changed again before this one finished
+                if sorted.folder == data.name
+                    && sorted.sort == data.sort
+                    && sorted.ascending == data.sort_ascending
+                {
+                    for (path, metadata) in sorted.metadata.iter() {
+                        data.metadata.insert(path.clone(), metadata.clone());
+                    }
+                    data.set_order(sorted.paths.clone());
+                }
             }
             Event::Command(selector) if selector.is(POP_FOLDER_VIEW) => {
                 // let view = selector.get_unchecked(POP_FOLDER_VIEW);
                 data.pop_view();
             }
+            Event::Command(cmd) if cmd.is(SELECT_WITH_CLICK) => {
+                let (index, mods) = *cmd.get_unchecked(SELECT_WITH_CLICK);
+                if mods.shift() {
+                    data.select_range(index, mods.ctrl());
+                } else {
+                    data.toggle_selected(index);
+                }
+            }
+            Event::KeyDown(key) => match &key.key {
+                KbKey::Character(c)
+                    if key.mods.ctrl() && c.eq_ignore_ascii_case("a") =>
+                {
+                    data.select_all();
+                    ctx.set_handled();
+                    return;
+                }
+                // Escape clears the selection before it goes back
+                KbKey::Escape if !data.selection.is_empty() => {
+                    data.selection.clear();
+                    ctx.set_handled();
+                    return;
+                }
+                _ => (),
+            },
             _ => (),
         }
         child.event(ctx, event, data, env)
     }
-}
 
-impl GridIter<(Thumbnail, usize)> for FolderGalleryState {
-    fn for_each(&self, mut cb: impl FnMut(&(Thumbnail, usize), usize)) {
-        for (i, thumbnail) in self.images.iter().enumerate() {
-            cb(&(thumbnail.clone(), i), i);
-        }
-    }
-
-    fn for_each_mut(
+    fn update(
         &mut self,
-        mut cb: impl FnMut(&mut (Thumbnail, usize), usize),
+        child: &mut Container<FolderGalleryState>,
+        ctx: &mut UpdateCtx,
+        old_data: &FolderGalleryState,
+        data: &FolderGalleryState,
+        env: &Env,
     ) {
-        for (i, thumbnail) in self.images.iter_mut().enumerate() {
-            cb(&mut (thumbnail.clone(), i), i);
+        if !data.jobs.same(&old_data.jobs) {
+            self.requested.clear();
         }
+        if data.visible_images != old_data.visible_images
+            || !data.images.same(&old_data.images)
+            || !data.jobs.same(&old_data.jobs)
+        {
+            self.request_thumbnails(data, ctx.get_external_handle());
+        }
+        child.update(ctx, old_data, data, env)
     }
+}
+
+/// The data of a cell of the folder grid.
+#[derive(Debug, Clone, Data, Lens)]
+pub struct ThumbnailCell {
+    pub thumbnail: Thumbnail,
+    pub index: usize,
+    pub selected: bool,
+    pub annotations: Annotations,
+}
+
+/// The names of the albums of the library.
+fn album_names(state: &AppState) -> Vector<Arc<PathBuf>> {
+    state
+        .all_images
+        .iter()
+        .filter(|folder| folder.album)
+        .map(|album| album.name.clone())
+        .collect()
+}
 
-    fn data_len(&self) -> usize {
+impl GridItems<ThumbnailCell> for FolderGalleryState {
+    fn items_len(&self) -> usize {
         self.images.len()
     }
 
-    fn child_data(&self) -> Option<(Thumbnail, usize)> {
-        match self.images.iter().next() {
-            Some(thumbnail) => Some((thumbnail.clone(), 0)),
-            None => {
-                let thumbnail = Thumbnail {
-                    index: 0,
-                    image: ImageBuf::empty(),
-                };
-                Some((thumbnail, 0))
-            }
-        }
+    fn item(&self, index: usize) -> Option<ThumbnailCell> {
+        let thumbnail = self.images.get(index)?.clone();
+        let annotations = self
+            .paths
+            .get(index)
+            .map(|path| self.annotations_of(path))
+            .unwrap_or_default();
+        Some(ThumbnailCell {
+            thumbnail,
+            index,
+            selected: self.is_selected(index),
+            annotations,
+        })
+    }
+
+    fn visible_items(&self) -> Range<usize> {
+        let (start, end) = self.visible_images;
+        start..end
+    }
+
+    fn select_items(&mut self, items: &[usize]) {
+        self.selection = items
+            .iter()
+            .filter_map(|index| self.paths.get(*index).cloned())
+            .collect();
+    }
+
+    fn can_move_items(&self) -> bool {
+        self.album
+    }
+
+    fn move_item(&mut self, from: usize, to: usize) {
+        self.move_images(from, to);
     }
 }
 
@@ -166,75 +847,202 @@ impl ScopeTransfer for GalleryTransfer {
     type State = FolderGalleryState;
 
     fn read_input(&self, state: &mut Self::State, inner: &Self::In) {
-        match inner.selected_folder {
-            Some(idx) => {
-                if let Some(current_idx) = state.selected_folder {
-                    if idx != current_idx {
-                        let folder = &inner.all_images[idx];
-                        dbg!("Change Folder", &folder.name);
-                        state.selected_folder = Some(idx);
-                        state.name = folder.name.clone();
-                        state.images = folder.thumbnails.clone();
-                        state.paths = folder.paths.clone();
-                    }
-                } else {
-                    let folder = &inner.all_images[idx];
-                    dbg!("None", &folder.name);
-                    state.selected_folder = Some(idx);
-                    state.name = folder.name.clone();
-                    state.images = folder.thumbnails.clone();
-                    state.paths = folder.paths.clone();
-                }
-            }
-            None => {
-                dbg!("Nothing should be read or maybe it should");
+        if !state.jobs.same(&inner.folder_jobs) {
+            state.jobs = inner.folder_jobs.clone();
+        }
+        let albums = album_names(inner);
+        if albums != state.albums {
+            state.albums = albums;
+        }
+        let folder = match inner.open_folder() {
+            Some(folder) => folder,
+            None => return,
+        };
+        if state.selected_folder == inner.selected_folder
+            && state.name == folder.name
+        {
+            // everything the folder view changes is written back, so a
+            // difference means `Delegate` updated the folder after it changed
+            // on disk
+            if !folder.paths.same(&state.paths)
+                || !folder.thumbnails.same(&state.images)
+            {
+                state.set_contents(
+                    folder.paths.clone(),
+                    folder.thumbnails.clone(),
+                );
+                state.metadata = folder.metadata.clone();
             }
+        } else {
+            state.selected_folder = inner.selected_folder;
+            state.selection.clear();
+            state.annotations.clear();
+            state.editing_tags = false;
+            state.edits.clear();
+            state.name = folder.name.clone();
+            state.album = folder.album;
+            state.images = folder.thumbnails.clone();
+            state.paths = folder.paths.clone();
+            state.metadata = folder.metadata.clone();
         }
     }
 
     fn write_back_input(&self, state: &Self::State, inner: &mut Self::In) {
-        if let Some(idx) = state.selected_folder {
-            inner.all_images[idx].name = state.name.clone();
-            inner.all_images[idx].thumbnails = state.images.clone();
-        } else {
-            dbg!("This should do nothing because there is no state to write back.");
+        // the folder could have been removed from the library while it was
+        // open, after its last image was moved out of it
+        if state.selected_folder != inner.selected_folder {
+            return;
+        }
+        inner.slideshow = state.slideshow.clone();
+        inner.jpeg_rotation = state.jpeg_rotation;
+        match inner.open_folder_mut() {
+            Some(folder) if folder.name == state.name => {
+                folder.thumbnails = state.images.clone();
+                folder.paths = state.paths.clone();
+                folder.metadata = state.metadata.clone();
+            }
+            _ => {
+                dbg!("This should do nothing because there is no state to write back.");
+            }
         }
     }
 }
+/// The generation of the read, the path of the image and the image itself.
+type ReadImage = (u64, Arc<PathBuf>, Result<CachedImage, GalleryError>);
+
 pub struct DisplayImageController {
-    sender: SyncSender<RgbImage>,
-    receiver: Receiver<RgbImage>,
+    sender: Sender<ReadImage>,
+    receiver: Receiver<ReadImage>,
+    /// Counts the reads, so images that finish after the user moved on to
+    /// another one are discarded instead of shown.
+    generation: u64,
+    cancel: CancelToken,
+    /// Cancels the images that are read ahead once the user moves on.
+    prefetch: CancelToken,
+    /// The image being shown, whose zoom is kept when it's shown again.
+    shown: Option<Arc<PathBuf>>,
 }
 impl DisplayImageController {
     pub fn new() -> Self {
-        let (sender, receiver) = sync_channel(3);
+        let (sender, receiver) = channel();
 
-        DisplayImageController { sender, receiver }
+        DisplayImageController {
+            sender,
+            receiver,
+            generation: 0,
+            cancel: CancelToken::new(),
+            prefetch: CancelToken::new(),
+            shown: None,
+        }
     }
 
+    /// Reads the image at `path` and applies its edits, which are also
+    /// applied again whenever they change.
     fn read_image(
-        &self,
+        &mut self,
         sink: ExtEventSink,
-        path: PathBuf,
+        path: Arc<PathBuf>,
+        adjustments: Adjustments,
         widget_id: WidgetId,
     ) {
-        let sender = self.sender.clone();
-        std::thread::spawn(move || {
-            let image = image::io::Reader::open(path)
-                .unwrap()
-                .decode()
-                .unwrap()
-                .into_rgb8();
-            sender.send(image).unwrap();
-            sink.submit_command(FINISHED_READING_IMAGE, (), widget_id)
+        // the previous image isn't needed anymore if it hasn't started yet
+        self.cancel.cancel();
+        self.cancel = CancelToken::new();
+        self.generation += 1;
+        let generation = self.generation;
+
+        // images that were read ahead are shown without another decode
+        let cached = image_cache::get(&path);
+        if let (Some(image), false) = (&cached, adjustments.changes_image()) {
+            self.sender
+                .send((generation, path, Ok(image.clone())))
                 .unwrap();
+            // this only fails when the app is closing
+            let _ = sink.submit_command(FINISHED_READING_IMAGE, (), widget_id);
+            return;
+        }
+
+        let sender = self.sender.clone();
+        workers::spawn(Priority::Selected, &self.cancel, move || {
+            let image = match cached {
+                Some(image) => Ok(image),
+                None => image_cache::load(&path),
+            };
+            let image = image.map(|image| render_edits(image, &adjustments));
+            // the view could have been closed while this was read
+            if sender.send((generation, path, image)).is_err() {
+                return;
+            }
+            // this only fails when the app is closing
+            let _ = sink.submit_command(FINISHED_READING_IMAGE, (), widget_id);
         });
     }
+
+    /// Reads the images around the selected one into the cache, nearest
+    /// first, after the selected image itself.
+    fn prefetch(&mut self, data: &FolderGalleryState) {
+        self.prefetch.cancel();
+        self.prefetch = CancelToken::new();
+        let selected = data.selected_image;
+        for distance in 1..=image_cache::prefetch_distance() {
+            let next = data.paths.get(selected + distance);
+            let previous = selected
+                .checked_sub(distance)
+                .and_then(|index| data.paths.get(index));
+            for path in next.into_iter().chain(previous) {
+                if image_cache::contains(path) {
+                    continue;
+                }
+                let path = path.clone();
+                workers::spawn(
+                    Priority::Background,
+                    &self.prefetch,
+                    move || {
+                        // it could have been read since this was queued
+                        if image_cache::contains(&path) {
+                            return;
+                        }
+                        if let Err(err) = image_cache::load(&path) {
+                            // reported when the image is shown
+                            warn!("Error reading ahead: {}", err);
+                        }
+                    },
+                );
+            }
+        }
+    }
 }
-impl Controller<FolderGalleryState, Image> for DisplayImageController {
+
+/// `image` with the edits applied, leaving the cached image as it was read.
+fn render_edits(image: CachedImage, adjustments: &Adjustments) -> CachedImage {
+    if !adjustments.changes_image() {
+        return image;
+    }
+    let pixels = RgbImage::from_raw(
+        image.image.width() as u32,
+        image.image.height() as u32,
+        image.image.raw_pixels().to_vec(),
+    );
+    let edited = match pixels {
+        Some(pixels) => edits::render(pixels, adjustments),
+        None => return image,
+    };
+    let (width, height) = edited.dimensions();
+    CachedImage {
+        image: ImageBuf::from_raw(
+            edited.into_raw(),
+            ImageFormat::Rgb,
+            width as usize,
+            height as usize,
+        ),
+        metadata: image.metadata,
+    }
+}
+
+impl Controller<FolderGalleryState, ZoomImage> for DisplayImageController {
     fn event(
         &mut self,
-        child: &mut Image,
+        child: &mut ZoomImage,
         ctx: &mut druid::EventCtx,
         event: &Event,
         data: &mut FolderGalleryState,
@@ -244,18 +1052,64 @@ impl Controller<FolderGalleryState, Image> for DisplayImageController {
             Event::Command(image_selector)
                 if image_selector.is(FINISHED_READING_IMAGE) =>
             {
-                let image = self.receiver.recv().unwrap();
-                let (width, height) = image.dimensions();
-                let image = ImageBuf::from_raw(
-                    image.into_raw(),
-                    ImageFormat::Rgb,
-                    width as usize,
-                    height as usize,
-                );
+                // each read is sent before its command, so this doesn't
+                // block the UI thread waiting for one
+                let (generation, path, image) = match self.receiver.try_recv() {
+                    Ok(read) => read,
+                    Err(err) => {
+                        warn!("No image was read for the image view: {}", err);
+                        return;
+                    }
+                };
+                if generation != self.generation {
+                    return;
+                }
+                let image = match image {
+                    Ok(image) => {
+                        data.metadata.insert(path.clone(), image.metadata);
+                        image.image
+                    }
+                    Err(err) => {
+                        // the previous image isn't left up as if it were
+                        // this one
+                        error!("{}", err);
+                        child.set_image_data(ImageBuf::empty());
+                        self.shown = None;
+                        ctx.submit_command(SHOW_ERROR.with(err.to_string()));
+                        ctx.request_paint();
+                        return;
+                    }
+                };
+                // another image of the same size starts out fitted too
+                if self.shown.as_ref() != Some(&path) {
+                    child.reset_zoom();
+                    self.shown = Some(path);
+                }
                 child.set_image_data(image);
                 ctx.request_layout();
                 ctx.request_paint();
             }
+            Event::Command(cmd)
+                if cmd.is(ROTATED_IMAGES) || cmd.is(CHANGED_IMAGE) =>
+            {
+                // `Delegate` already dropped the images from the cache
+                if let Some(path) = data.paths.get(data.selected_image) {
+                    let changed = match cmd.get(ROTATED_IMAGES) {
+                        Some(paths) => paths.contains(path),
+                        None => cmd.get(CHANGED_IMAGE) == Some(path),
+                    };
+                    if changed {
+                        let sink = ctx.get_external_handle();
+                        let adjustments = data.edits_of(path).adjustments();
+                        self.read_image(
+                            sink,
+                            path.clone(),
+                            adjustments,
+                            ctx.widget_id(),
+                        );
+                    }
+                }
+            }
             _ => (),
         }
         child.event(ctx, event, data, env)
@@ -263,31 +1117,43 @@ impl Controller<FolderGalleryState, Image> for DisplayImageController {
 
     fn update(
         &mut self,
-        child: &mut Image,
+        child: &mut ZoomImage,
         ctx: &mut UpdateCtx,
         old_data: &FolderGalleryState,
         data: &FolderGalleryState,
         env: &Env,
     ) {
-        if data.paths.is_empty() {
-            return;
-        }
-        if data.selected_image != old_data.selected_image {
-            let path = data.paths[data.selected_image].as_ref().clone();
+        let path = match data.paths.get(data.selected_image) {
+            Some(path) => path.clone(),
+            None => return child.update(ctx, old_data, data, env),
+        };
+        // images moved out of the folder leave the same index on another
+        // image, so this compares the paths
+        if Some(&path) != old_data.paths.get(old_data.selected_image) {
             let sink = ctx.get_external_handle();
+            let adjustments = data.edits_of(&path).adjustments();
             // only need to send this payload back to itself
             // after it finishes reading the image on a separate thread
             // only DisplayImageController needs to see this payload
-            self.read_image(sink, path, ctx.widget_id());
+            self.read_image(sink, path, adjustments, ctx.widget_id());
+            self.prefetch(data);
             ctx.request_layout();
             ctx.request_paint();
+        } else if data.current_edits().adjustments()
+            != old_data.current_edits().adjustments()
+        {
+            // the edit panel changed the edits, so the image is shown with
+            // them again, without reading it ahead again
+            let sink = ctx.get_external_handle();
+            let adjustments = data.edits_of(&path).adjustments();
+            self.read_image(sink, path, adjustments, ctx.widget_id());
         }
         child.update(ctx, old_data, data, env)
     }
 
     fn lifecycle(
         &mut self,
-        child: &mut Image,
+        child: &mut ZoomImage,
         ctx: &mut LifeCycleCtx,
         event: &LifeCycle,
         data: &FolderGalleryState,
@@ -297,25 +1163,322 @@ impl Controller<FolderGalleryState, Image> for DisplayImageController {
         // not problematic. Druid warns because this might send an event
         // back here, to read the image, before it gets laid out
         if let LifeCycle::WidgetAdded = event {
-            let path = data.paths[data.selected_image].as_ref().clone();
+            // albums and search results can be emptied before the view is
+            // built
+            let path = match data.paths.get(data.selected_image) {
+                Some(path) => path.clone(),
+                None => return child.lifecycle(ctx, event, data, env),
+            };
             let sink = ctx.get_external_handle();
+            let adjustments = data.edits_of(&path).adjustments();
             // only need to send this payload back to itself
             // after it finishes reading the image on a separate thread
             // only DisplayImage needs to see this payload
-            self.read_image(sink, path, ctx.widget_id());
+            self.read_image(sink, path, adjustments, ctx.widget_id());
+            self.prefetch(data);
+        }
+        child.lifecycle(ctx, event, data, env)
+    }
+}
+
+/// The slideshow interval can't be set shorter than this.
+const MIN_SLIDESHOW_INTERVAL: f64 = 1.;
+
+/// The generation of the read, along with the image if it could be read.
+type ReadSlide = (u64, Option<RgbImage>);
+
+/// Loads the images of the slideshow and advances it on a timer.
+///
+/// Space pauses and resumes, the left and right arrows move between images,
+/// the up and down arrows change the interval, `l` toggles looping, `s`
+/// toggles shuffling and Escape ends the slideshow.
+pub struct SlideshowController {
+    sender: Sender<ReadSlide>,
+    receiver: Receiver<ReadSlide>,
+    /// Counts the reads, so slides that were skipped past while they loaded
+    /// aren't shown.
+    generation: u64,
+    cancel: CancelToken,
+    timer: TimerToken,
+    /// The window the slideshow maximized and the state it was in before,
+    /// which it's put back in once the slideshow is left.
+    window: Option<(WindowHandle, WindowState)>,
+}
+
+impl SlideshowController {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+
+        SlideshowController {
+            sender,
+            receiver,
+            generation: 0,
+            cancel: CancelToken::new(),
+            timer: TimerToken::INVALID,
+            window: None,
+        }
+    }
+
+    /// Reads the image at `index` on the worker pool, scaled down to fit
+    /// `size`, in device pixels, so the crossfade doesn't have to blend more
+    /// pixels than shown.
+    fn read_slide(
+        &mut self,
+        sink: ExtEventSink,
+        data: &FolderGalleryState,
+        size: Size,
+        widget_id: WidgetId,
+    ) {
+        let index = data.selected_image;
+        let path = match data.paths.get(index) {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        self.cancel.cancel();
+        self.cancel = CancelToken::new();
+        self.generation += 1;
+        let generation = self.generation;
+        let sender = self.sender.clone();
+        workers::spawn(Priority::Selected, &self.cancel, move || {
+            let image = orientation::open_image(&path)
+                .map(|image| edits::apply_saved(&path, image));
+            let image = match image {
+                Ok(image) if size.width < 1. || size.height < 1. => Some(image),
+                Ok(image) => Some(fit_image(&image, size)),
+                Err(err) => {
+                    report_error(&sink, &GalleryError::image(&path, err));
+                    None
+                }
+            };
+            if sender.send((generation, image)).is_err() {
+                return;
+            }
+            // this only fails when the app is closing
+            let _ = sink.submit_command(FINISHED_READING_IMAGE, (), widget_id);
+        });
+    }
+
+    fn exit(
+        &mut self,
+        ctx: &mut druid::EventCtx,
+        data: &mut FolderGalleryState,
+    ) {
+        data.slideshow_playing = false;
+        self.timer = TimerToken::INVALID;
+        self.restore_window();
+        ctx.submit_command(Command::new(POP_FOLDER_VIEW, (), Target::Auto));
+    }
+
+    /// Shows the title bar again and puts the window back in its state from
+    /// before the slideshow.
+    fn restore_window(&mut self) {
+        if let Some((window, state)) = self.window.take() {
+            window.show_titlebar(true);
+            window.set_window_state(state);
+        }
+    }
+}
+
+/// The slideshow can also be left without Escape, when its view is popped or
+/// replaced or the folder view is left, all of which drop it.
+impl Drop for SlideshowController {
+    fn drop(&mut self) {
+        self.restore_window();
+    }
+}
+
+impl Controller<FolderGalleryState, Crossfade> for SlideshowController {
+    fn event(
+        &mut self,
+        child: &mut Crossfade,
+        ctx: &mut druid::EventCtx,
+        event: &Event,
+        data: &mut FolderGalleryState,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(TAKE_FOCUS) => {
+                ctx.request_focus();
+                // the slideshow is a maximized presentation without the
+                // title bar, as druid has no fullscreen window state, so the
+                // taskbar and any decorations of the window manager stay up.
+                // The window's state from before is only kept the first
+                // time, so it isn't lost if this arrives again
+                if self.window.is_none() {
+                    let window = ctx.window().clone();
+                    let state = window.get_window_state();
+                    self.window = Some((window, state));
+                }
+                ctx.window().show_titlebar(false);
+                ctx.window().set_window_state(WindowState::MAXIMIZED);
+                ctx.set_handled();
+                return;
+            }
+            Event::Command(cmd) if cmd.is(FINISHED_READING_IMAGE) => {
+                let (generation, image) = match self.receiver.try_recv() {
+                    Ok(read) => read,
+                    Err(err) => {
+                        warn!("No image was read for the slideshow: {}", err);
+                        ctx.set_handled();
+                        return;
+                    }
+                };
+                // images that were skipped past while loading aren't shown
+                if generation == self.generation {
+                    // an image that couldn't be read is skipped over, with
+                    // the previous one left up until the next is shown
+                    if let Some(image) = image {
+                        let transition =
+                            Duration::from_secs_f64(data.slideshow.transition);
+                        child.set_image(image, transition);
+                        if child.is_fading() {
+                            ctx.request_anim_frame();
+                        }
+                        ctx.request_paint();
+                    }
+                    // the interval starts once the image is shown, so slow
+                    // images aren't skipped
+                    if data.slideshow_playing {
+                        self.timer = ctx.request_timer(
+                            Duration::from_secs_f64(data.slideshow.interval),
+                        );
+                    }
+                }
+                ctx.set_handled();
+                return;
+            }
+            Event::Timer(token) if *token == self.timer => {
+                if data.advance_slideshow() {
+                    self.timer = ctx.request_timer(Duration::from_secs_f64(
+                        data.slideshow.interval,
+                    ));
+                }
+            }
+            Event::KeyDown(key) => {
+                let interval = data.slideshow.interval;
+                match &key.key {
+                    KbKey::Character(c) if c == " " => {
+                        data.slideshow_playing = !data.slideshow_playing;
+                    }
+                    KbKey::ArrowLeft => data.previous_slide(),
+                    KbKey::ArrowRight => {
+                        data.next_slide();
+                    }
+                    KbKey::ArrowUp => data.slideshow.interval += 1.,
+                    KbKey::ArrowDown => {
+                        data.slideshow.interval =
+                            (interval - 1.).max(MIN_SLIDESHOW_INTERVAL);
+                    }
+                    KbKey::Character(c) if c == "l" => {
+                        data.slideshow.looping = !data.slideshow.looping;
+                    }
+                    KbKey::Character(c) if c == "s" => {
+                        data.slideshow.shuffle = !data.slideshow.shuffle;
+                        data.update_slideshow_order();
+                    }
+                    KbKey::Escape => self.exit(ctx, data),
+                    _ => return child.event(ctx, event, data, env),
+                }
+                // resuming or changing the interval restarts the countdown
+                // of the current image
+                if data.slideshow_playing {
+                    self.timer = ctx.request_timer(Duration::from_secs_f64(
+                        data.slideshow.interval,
+                    ));
+                } else {
+                    self.timer = TimerToken::INVALID;
+                }
+                ctx.set_handled();
+                return;
+            }
+            _ => (),
+        }
+        child.event(ctx, event, data, env)
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut Crossfade,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &FolderGalleryState,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.register_for_focus();
+            ctx.submit_command(Command::new(
+                TAKE_FOCUS,
+                (),
+                Target::Widget(ctx.widget_id()),
+            ));
+            // the widget hasn't been laid out yet, so the first image is read
+            // at its full size
+            let sink = ctx.get_external_handle();
+            self.read_slide(sink, data, Size::ZERO, ctx.widget_id());
         }
         child.lifecycle(ctx, event, data, env)
     }
+
+    fn update(
+        &mut self,
+        child: &mut Crossfade,
+        ctx: &mut UpdateCtx,
+        old_data: &FolderGalleryState,
+        data: &FolderGalleryState,
+        env: &Env,
+    ) {
+        if data.selected_image != old_data.selected_image {
+            let sink = ctx.get_external_handle();
+            self.read_slide(sink, data, child.pixel_size(), ctx.widget_id());
+        }
+        child.update(ctx, old_data, data, env)
+    }
+}
+
+/// Changes the selection when a thumbnail is clicked with Ctrl or Shift, and
+/// opens its context menu when it's right clicked.
+pub struct ThumbnailClickController;
+
+impl<W: Widget<ThumbnailCell>> Controller<ThumbnailCell, W>
+    for ThumbnailClickController
+{
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut druid::EventCtx,
+        event: &Event,
+        data: &mut ThumbnailCell,
+        env: &Env,
+    ) {
+        if let Event::MouseDown(mouse) = event {
+            if mouse.button.is_right() {
+                ctx.submit_command(
+                    SHOW_IMAGE_MENU.with((data.index, mouse.window_pos)),
+                );
+                ctx.set_handled();
+                return;
+            }
+            // these don't reach the click handler, so the image isn't opened
+            if mouse.mods.ctrl() || mouse.mods.shift() {
+                ctx.submit_command(
+                    SELECT_WITH_CLICK.with((data.index, mouse.mods)),
+                );
+                ctx.set_handled();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env)
+    }
 }
 
 pub struct FolderThumbnailController;
-impl Controller<(Thumbnail, usize), Image> for FolderThumbnailController {
+impl Controller<ThumbnailCell, Image> for FolderThumbnailController {
     fn event(
         &mut self,
         child: &mut Image,
         ctx: &mut druid::EventCtx,
         event: &Event,
-        data: &mut (Thumbnail, usize),
+        data: &mut ThumbnailCell,
         env: &Env,
     ) {
         child.event(ctx, event, data, env)
@@ -325,11 +1488,11 @@ impl Controller<(Thumbnail, usize), Image> for FolderThumbnailController {
         child: &mut Image,
         ctx: &mut LifeCycleCtx,
         event: &LifeCycle,
-        data: &(Thumbnail, usize),
+        data: &ThumbnailCell,
         env: &Env,
     ) {
         if let LifeCycle::WidgetAdded = event {
-            child.set_image_data(data.0.image.clone());
+            child.set_image_data(data.thumbnail.image.clone());
             ctx.request_layout();
             ctx.request_paint();
         }
@@ -340,15 +1503,91 @@ impl Controller<(Thumbnail, usize), Image> for FolderThumbnailController {
         &mut self,
         child: &mut Image,
         ctx: &mut UpdateCtx,
-        old_data: &(Thumbnail, usize),
-        data: &(Thumbnail, usize),
+        old_data: &ThumbnailCell,
+        data: &ThumbnailCell,
         env: &Env,
     ) {
         if !data.same(old_data) {
-            child.set_image_data(data.0.image.clone());
+            child.set_image_data(data.thumbnail.image.clone());
             ctx.request_layout();
             ctx.request_paint();
         }
         child.update(ctx, old_data, data, env)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, sort::FolderSort};
+
+    fn gallery(paths: &[&str], looping: bool) -> FolderGalleryState {
+        let state = AppState {
+            roots: HashSet::new(),
+            folder_paths: HashSet::new(),
+            current_image_idx: 0,
+            views: vector![AppView::MainView],
+            all_images: Vector::new(),
+            selected_folder: None,
+            focused_folder: None,
+            image_formats: Config::default().image_formats(),
+            folder_sort: FolderSort::Name,
+            folder_sort_ascending: true,
+            folder_filter: String::new(),
+            visible_folders: Vector::new(),
+            slideshow: SlideshowSettings {
+                looping,
+                ..SlideshowSettings::default()
+            },
+            jpeg_rotation: JpegRotation::default(),
+            toasts: Vector::new(),
+            folder_jobs: CancelToken::new(),
+            search_query: String::new(),
+            searching: false,
+            search_jobs: CancelToken::new(),
+            search_results: None,
+            saved_searches: Vector::new(),
+            naming_search: false,
+            search_name: String::new(),
+            naming_album: false,
+            album_name: String::new(),
+            image_view_folder: None,
+            pending_folder: None,
+        };
+        let mut gallery = FolderGalleryState::new(state);
+        gallery.paths = paths
+            .iter()
+            .map(|path| Arc::new(PathBuf::from(path)))
+            .collect();
+        gallery.start_slideshow();
+        gallery
+    }
+
+    #[test]
+    fn loops_a_single_image() {
+        let mut gallery = gallery(&["a.jpg"], true);
+        for _ in 0..3 {
+            // nothing is read again, so the next interval starts right away
+            assert!(gallery.advance_slideshow());
+            assert!(gallery.slideshow_playing);
+            assert_eq!(gallery.selected_image, 0);
+        }
+    }
+
+    #[test]
+    fn advances_the_slideshow() {
+        let mut gallery = gallery(&["a.jpg", "b.jpg"], true);
+        assert!(!gallery.advance_slideshow());
+        assert_eq!(gallery.selected_image, 1);
+        assert!(!gallery.advance_slideshow());
+        assert_eq!(gallery.selected_image, 0);
+        assert!(gallery.slideshow_playing);
+
+        let mut gallery = gallery(&["a.jpg"], false);
+        assert!(!gallery.advance_slideshow());
+        assert!(!gallery.slideshow_playing);
+
+        gallery.slideshow_playing = false;
+        assert!(!gallery.advance_slideshow());
+    }
+}
@@ -0,0 +1,94 @@
+//! A bar with a text box, shown above a view while a name or tags are typed
+//! in.
+
+use druid::{
+    widget::{Controller, Flex, Label, TextBox},
+    Color, Command, Data, Env, Event, EventCtx, KbKey, Lens, Selector, Target,
+    Widget, WidgetExt, WidgetId,
+};
+
+use crate::{app_commands::TAKE_FOCUS, widgets::Button};
+
+/// A bar editing the text at `text`, whose box has the id `text_box` so it
+/// can be sent `TAKE_FOCUS`. Enter and the confirm button send `confirm`,
+/// Escape and the cancel button send `cancel`.
+pub fn edit_bar<T: Data>(
+    label: &str,
+    confirm_label: &str,
+    text: impl Lens<T, String> + 'static,
+    text_box: WidgetId,
+    confirm: Selector<()>,
+    cancel: Selector<()>,
+) -> impl Widget<T> {
+    let button = |text: &str, command: Selector<()>| {
+        Button::new(
+            text,
+            Color::BLACK,
+            Color::rgb8(0xff, 0xff, 0xff),
+            Color::rgb8(0xcc, 0xcc, 0xcc),
+            Color::rgb8(0x90, 0x90, 0x90),
+            14.,
+        )
+        .on_click(move |ctx, _data: &mut T, _env| {
+            ctx.submit_command(Command::new(command, (), Target::Auto));
+        })
+        .fix_height(30.)
+    };
+
+    let text_box = TextBox::new()
+        .controller(EditBoxController { confirm, cancel })
+        .with_id(text_box)
+        .lens(text)
+        .expand_width();
+
+    Flex::row()
+        .with_child(Label::new(label).with_text_color(Color::BLACK))
+        .with_spacer(10.)
+        .with_flex_child(text_box, 1.0)
+        .with_spacer(10.)
+        .with_child(button(confirm_label, confirm))
+        .with_child(button("Cancel", cancel))
+        .padding(5.)
+        .background(Color::rgb8(0xee, 0xee, 0xee))
+}
+
+/// Takes focus when the bar is shown, and confirms or cancels with Enter and
+/// Escape.
+struct EditBoxController {
+    confirm: Selector<()>,
+    cancel: Selector<()>,
+}
+
+impl<W: Widget<String>> Controller<String, W> for EditBoxController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut String,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(TAKE_FOCUS) => {
+                ctx.request_focus();
+                return;
+            }
+            Event::KeyDown(key) if key.key == KbKey::Enter => {
+                ctx.submit_command(Command::new(
+                    self.confirm,
+                    (),
+                    Target::Auto,
+                ));
+                ctx.set_handled();
+                return;
+            }
+            Event::KeyDown(key) if key.key == KbKey::Escape => {
+                ctx.submit_command(Command::new(self.cancel, (), Target::Auto));
+                ctx.set_handled();
+                return;
+            }
+            _ => (),
+        }
+        child.event(ctx, event, data, env)
+    }
+}
@@ -0,0 +1,542 @@
+//! Non-destructive editing of the images of a folder: the edit panel of the
+//! single image view, and resetting the edits of the selected images or
+//! exporting edited copies of them from their context menu.
+//!
+//! Changes are saved to the image's sidecar on another thread, those made by
+//! dragging a slider once it's let go of, and the image itself is never
+//! written to. Edited copies are exported next to the
+//! images, named like `IMG_0001-edited.jpg`.
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use druid::{
+    im::HashMap,
+    widget::{Controller, CrossAxisAlignment, Flex, Label, Slider},
+    Color, Env, Event, EventCtx, ExtEventSink, Lens, LensExt, LifeCycle,
+    LifeCycleCtx, LocalizedString, MenuDesc, MenuItem, Target, UpdateCtx,
+    Widget, WidgetExt, WidgetId,
+};
+use image::{codecs::jpeg::JpegEncoder, ColorType, ImageFormat};
+use log::warn;
+
+use crate::{
+    app_commands::{
+        EXPORT_EDITED, LOADED_EDITS, RESET_EDITS, ROTATED_EDITS, UNSAVED_EDITS,
+    },
+    app_data::AppState,
+    edits::{self, Adjustments, Aspect, Crop, EditStack, StackAdjustments},
+    error::{report_error, GalleryError},
+    orientation,
+    sidecar::read_edits,
+    widgets::Button,
+};
+
+use super::{
+    rotation::to_io_error, FolderGalleryState, FolderView, SidecarWriter,
+};
+
+/// The quality edited copies of JPEGs are exported with.
+const EXPORT_QUALITY: u8 = 92;
+
+/// Lenses the edit stack of the focused image.
+pub struct CurrentEdits;
+
+impl Lens<FolderGalleryState, EditStack> for CurrentEdits {
+    fn with<V, F: FnOnce(&EditStack) -> V>(
+        &self,
+        data: &FolderGalleryState,
+        f: F,
+    ) -> V {
+        f(&data.current_edits())
+    }
+
+    fn with_mut<V, F: FnOnce(&mut EditStack) -> V>(
+        &self,
+        data: &mut FolderGalleryState,
+        f: F,
+    ) -> V {
+        let path = match data.paths.get(data.selected_image) {
+            Some(path) => path.clone(),
+            None => return f(&mut EditStack::default()),
+        };
+        let mut edits = data.edits_of(&path);
+        let value = f(&mut edits);
+        if edits != data.edits_of(&path) {
+            keep_edits(data, path, edits);
+        }
+        value
+    }
+}
+
+/// Loads the edit stacks of the folder's images, has `writer` save the edits
+/// made in `content` and handles resetting and exporting them.
+pub fn with_editing(
+    content: impl Widget<FolderGalleryState> + 'static,
+    writer: SidecarWriter,
+) -> impl Widget<FolderGalleryState> {
+    content.controller(EditingController {
+        writer,
+        unsaved: None,
+    })
+}
+
+/// Adds the items resetting and exporting the edits of the selected images to
+/// the end of `menu`, if any of them have edits.
+pub fn editing_menu(
+    menu: MenuDesc<AppState>,
+    data: &FolderGalleryState,
+) -> MenuDesc<AppState> {
+    let edited = data
+        .selected_paths()
+        .iter()
+        .any(|path| data.edits.contains_key(path));
+    if !edited {
+        return menu;
+    }
+    menu.append_separator()
+        .append(MenuItem::new(
+            LocalizedString::new("gallery-export-edited")
+                .with_placeholder("Export Edited Copy"),
+            EXPORT_EDITED,
+        ))
+        .append(MenuItem::new(
+            LocalizedString::new("gallery-reset-edits")
+                .with_placeholder("Reset Edits"),
+            RESET_EDITS,
+        ))
+}
+
+/// The collapsible panel of the single image view that edits the selected
+/// image.
+pub fn edit_panel() -> impl Widget<FolderGalleryState> {
+    let crop_label = Label::dynamic(|data: &FolderGalleryState, _env| {
+        let aspect = data.current_edits().adjustments().crop.aspect;
+        format!("Crop: {}", aspect.name())
+    })
+    .with_text_color(Color::grey8(0x70))
+    .with_text_size(12.);
+    let mut crop_buttons = Flex::row();
+    for aspect in Aspect::ALL.iter().copied() {
+        crop_buttons.add_flex_child(
+            panel_button(aspect.name(), move |_ctx, data| {
+                // the crop starts out as large as it can be, and is then
+                // moved and resized with the sliders below
+                let dimensions = data
+                    .current_metadata()
+                    .and_then(|metadata| metadata.dimensions);
+                CurrentEdits.then(StackAdjustments).with_mut(
+                    data,
+                    |adjustments| {
+                        adjustments.crop = Crop::largest(aspect, dimensions)
+                    },
+                );
+            }),
+            1.0,
+        );
+    }
+    let crop = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(crop_label)
+        .with_spacer(5.)
+        .with_child(crop_buttons)
+        .padding((10., 5.));
+
+    let buttons = Flex::row()
+        .with_flex_child(
+            panel_button("Undo", |_ctx, data| {
+                CurrentEdits.with_mut(data, |edits| edits.undo());
+            }),
+            1.0,
+        )
+        .with_flex_child(
+            panel_button("Reset", |ctx, _data| {
+                ctx.submit_command(RESET_EDITS);
+            }),
+            1.0,
+        )
+        .padding((10., 5.));
+    let export_button = panel_button("Export Edited Copy", |ctx, _data| {
+        ctx.submit_command(EXPORT_EDITED);
+    })
+    .padding((10., 5.));
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Fill)
+        .with_child(crop)
+        .with_child(adjustment_row(
+            "Crop Size",
+            Adjustments::crop.map(Crop::size, |crop: &mut Crop, size| {
+                *crop = crop.with_size(size)
+            }),
+            Crop::MIN_SIZE,
+            1.,
+        ))
+        .with_child(adjustment_row(
+            "Crop Horizontal",
+            Adjustments::crop.map(Crop::horizontal, |crop: &mut Crop, x| {
+                *crop = crop.with_horizontal(x)
+            }),
+            0.,
+            1.,
+        ))
+        .with_child(adjustment_row(
+            "Crop Vertical",
+            Adjustments::crop.map(Crop::vertical, |crop: &mut Crop, y| {
+                *crop = crop.with_vertical(y)
+            }),
+            0.,
+            1.,
+        ))
+        .with_child(adjustment_row(
+            "Straighten",
+            Adjustments::straighten,
+            -15.,
+            15.,
+        ))
+        .with_child(adjustment_row("Exposure", Adjustments::exposure, -3., 3.))
+        .with_child(adjustment_row("Contrast", Adjustments::contrast, -1., 1.))
+        .with_child(adjustment_row(
+            "Temperature",
+            Adjustments::temperature,
+            -1.,
+            1.,
+        ))
+        .with_child(adjustment_row("Tint", Adjustments::tint, -1., 1.))
+        .with_child(adjustment_row(
+            "Saturation",
+            Adjustments::saturation,
+            -1.,
+            1.,
+        ))
+        .with_child(adjustment_row("Sharpen", Adjustments::sharpen, 0., 2.))
+        .with_child(buttons)
+        .with_child(export_button)
+        .fix_width(240.)
+        .expand_height()
+        .background(Color::rgb8(0xf5, 0xf5, 0xf5))
+}
+
+/// A row of the edit panel, with a slider changing the adjustment from `min`
+/// to `max`.
+fn adjustment_row(
+    name: &str,
+    adjustment: impl Lens<Adjustments, f64> + 'static,
+    min: f64,
+    max: f64,
+) -> impl Widget<FolderGalleryState> {
+    let value = Label::dynamic(|value: &f64, _env| format!("{:+.2}", value))
+        .with_text_color(Color::BLACK)
+        .with_text_size(12.);
+    let title = Flex::row()
+        .with_child(
+            Label::new(name)
+                .with_text_color(Color::grey8(0x70))
+                .with_text_size(12.),
+        )
+        .with_flex_spacer(1.0)
+        .with_child(value);
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Fill)
+        .with_child(title)
+        .with_child(Slider::new().with_range(min, max).expand_width())
+        .lens(CurrentEdits.then(StackAdjustments).then(adjustment))
+        .padding((10., 5.))
+}
+
+fn panel_button(
+    text: &str,
+    on_click: impl Fn(&mut EventCtx, &mut FolderGalleryState) + 'static,
+) -> impl Widget<FolderGalleryState> {
+    Button::new(
+        text,
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xcc, 0xcc, 0xcc),
+        Color::rgb8(0x90, 0x90, 0x90),
+        13.,
+    )
+    .on_click(move |ctx, data: &mut FolderGalleryState, _env| {
+        on_click(ctx, data)
+    })
+    .fix_height(30.)
+}
+
+struct EditingController {
+    writer: SidecarWriter,
+    /// The image whose edits were changed by a slider that's still dragged,
+    /// and aren't saved yet.
+    unsaved: Option<Arc<PathBuf>>,
+}
+
+impl EditingController {
+    /// Saves the edits of the image a slider was dragged for, if it's still
+    /// in the folder.
+    fn save_dragged(&mut self, ctx: &EventCtx, data: &FolderGalleryState) {
+        if let Some(path) = self.unsaved.take() {
+            if data.paths.contains(&path) {
+                let edits = data.edits_of(&path);
+                self.writer.write_edits(ctx, vec![(path, edits)]);
+            }
+        }
+    }
+}
+
+impl<W: Widget<FolderGalleryState>> Controller<FolderGalleryState, W>
+    for EditingController
+{
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut FolderGalleryState,
+        env: &Env,
+    ) {
+        let can_edit = !data.paths.is_empty()
+            && data.current_view() != &FolderView::Slideshow;
+        match event {
+            Event::Command(cmd) if cmd.is(RESET_EDITS) && can_edit => {
+                let reset: Vec<(Arc<PathBuf>, EditStack)> = data
+                    .selected_paths()
+                    .into_iter()
+                    .filter(|path| data.edits.contains_key(path))
+                    .map(|path| (path, EditStack::default()))
+                    .collect();
+                if !reset.is_empty() {
+                    for (path, edits) in &reset {
+                        keep_edits(data, path.clone(), edits.clone());
+                    }
+                    self.writer.write_edits(ctx, reset);
+                }
+                return;
+            }
+            Event::Command(cmd) if cmd.is(EXPORT_EDITED) && can_edit => {
+                let edited = data
+                    .selected_paths()
+                    .into_iter()
+                    .map(|path| {
+                        let adjustments = data.edits_of(&path).adjustments();
+                        (path, adjustments)
+                    })
+                    .filter(|(_, adjustments)| adjustments.changes_image())
+                    .collect();
+                export_edited(edited, ctx.get_external_handle());
+                return;
+            }
+            Event::Command(cmd) if cmd.is(ROTATED_EDITS) => {
+                // the sidecars are already written
+                for (path, edits) in cmd.get_unchecked(ROTATED_EDITS) {
+                    if data.paths.contains(path) {
+                        keep_edits(data, path.clone(), edits.clone());
+                    }
+                }
+                return;
+            }
+            Event::Command(cmd) if cmd.is(UNSAVED_EDITS) => {
+                for (path, edits) in cmd.get_unchecked(UNSAVED_EDITS) {
+                    if data.paths.contains(path) {
+                        keep_edits(data, path.clone(), edits.clone());
+                    }
+                }
+                return;
+            }
+            Event::Command(cmd) if cmd.is(LOADED_EDITS) => {
+                let (folder, loaded) = cmd.get_unchecked(LOADED_EDITS);
+                if folder == &data.name {
+                    // edits made while the sidecars were read are newer than
+                    // what was read
+                    data.edits = data.edits.clone().union(loaded.clone());
+                }
+                return;
+            }
+            _ => (),
+        }
+
+        // the edit panel changes the edits of the focused image as the
+        // sliders move, which keep them in memory while they're dragged
+        let path = data.paths.get(data.selected_image).cloned();
+        let old_edits = path.as_ref().map(|path| data.edits_of(path));
+        child.event(ctx, event, data, env);
+        if let (Some(path), Some(old_edits)) = (path, old_edits) {
+            // renaming or moving the image takes its edits along
+            if data.edits_of(&path) != old_edits && data.paths.contains(&path) {
+                if self.unsaved.as_ref() != Some(&path) {
+                    self.save_dragged(ctx, data);
+                }
+                self.unsaved = Some(path);
+            }
+        }
+        if !matches!(event, Event::MouseDown(_) | Event::MouseMove(_)) {
+            self.save_dragged(ctx, data);
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &FolderGalleryState,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            load_edits(data, ctx.get_external_handle(), ctx.widget_id());
+        }
+        child.lifecycle(ctx, event, data, env)
+    }
+
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &FolderGalleryState,
+        data: &FolderGalleryState,
+        env: &Env,
+    ) {
+        if old_data.name != data.name {
+            load_edits(data, ctx.get_external_handle(), ctx.widget_id());
+        }
+        child.update(ctx, old_data, data, env)
+    }
+}
+
+/// Reads the sidecars of the folder's images on another thread, and sends
+/// the edit stacks found to `widget`.
+fn load_edits(
+    data: &FolderGalleryState,
+    handle: ExtEventSink,
+    widget: WidgetId,
+) {
+    if data.paths.is_empty() {
+        return;
+    }
+    let folder = data.name.clone();
+    let paths = data.paths.clone();
+    thread::spawn(move || {
+        let mut loaded: HashMap<Arc<PathBuf>, EditStack> = HashMap::new();
+        for path in paths.iter() {
+            match read_edits(path) {
+                Ok(edits) if !edits.edits.is_empty() => {
+                    loaded.insert(path.clone(), edits);
+                }
+                Ok(_) => (),
+                Err(err) => warn!(
+                    "Couldn't read the sidecar of {}: {}",
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        // this only fails when the app is closing
+        let _ = handle.submit_command(
+            LOADED_EDITS,
+            (folder, loaded),
+            Target::Widget(widget),
+        );
+    });
+}
+
+/// Keeps `edits` as the edits of the image, without saving them.
+fn keep_edits(
+    data: &mut FolderGalleryState,
+    path: Arc<PathBuf>,
+    edits: EditStack,
+) {
+    if edits.edits.is_empty() {
+        data.edits.remove(&path);
+    } else {
+        data.edits.insert(path, edits);
+    }
+}
+
+/// Exports the edited copies on another thread, as the images have to be
+/// decoded and encoded again. The watcher adds the copies to the folder.
+fn export_edited(
+    edited: Vec<(Arc<PathBuf>, Adjustments)>,
+    handle: ExtEventSink,
+) {
+    thread::spawn(move || {
+        for (path, adjustments) in edited {
+            if let Err(err) = export_file(&path, &adjustments) {
+                let err = GalleryError::file_operation("export", &path, err);
+                report_error(&handle, &err);
+            }
+        }
+    });
+}
+
+fn export_file(path: &Path, adjustments: &Adjustments) -> io::Result<()> {
+    let format = ImageFormat::from_path(path).map_err(to_io_error)?;
+    let image = orientation::open_image(path).map_err(to_io_error)?;
+    let image = edits::render(image, adjustments);
+    let (width, height) = image.dimensions();
+
+    // formats the image crate can't write, or not as RGB, become PNGs
+    let (format, extension) = match format {
+        ImageFormat::Jpeg
+        | ImageFormat::Png
+        | ImageFormat::Tiff
+        | ImageFormat::Bmp => (
+            format,
+            path.extension()
+                .map_or_else(String::new, |e| e.to_string_lossy().to_string()),
+        ),
+        _ => (ImageFormat::Png, "png".to_string()),
+    };
+    let destination = export_path(path, &extension)?;
+    // written under another name first, so the watcher never sees half of it
+    let name = destination
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let temp_path = destination.with_file_name(format!(".{}.exporting", name));
+    let result = if format == ImageFormat::Jpeg {
+        fs::File::create(&temp_path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            JpegEncoder::new_with_quality(&mut writer, EXPORT_QUALITY)
+                .encode(image.as_raw(), width, height, ColorType::Rgb8)
+                .map_err(to_io_error)?;
+            writer.flush()
+        })
+    } else {
+        image
+            .save_with_format(&temp_path, format)
+            .map_err(to_io_error)
+    };
+    let result = result.and_then(|_| fs::rename(&temp_path, &destination));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Where the edited copy of the image at `path` goes, next to it and named
+/// like `IMG_0001-edited.jpg`, or `IMG_0001-edited-2.jpg` if that's taken.
+fn export_path(path: &Path, extension: &str) -> io::Result<PathBuf> {
+    let stem = path.file_stem().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "not a file")
+    })?;
+    let stem = stem.to_string_lossy();
+    let mut number = 1;
+    loop {
+        let mut name = if number == 1 {
+            format!("{}-edited", stem)
+        } else {
+            format!("{}-edited-{}", stem, number)
+        };
+        if !extension.is_empty() {
+            name = format!("{}.{}", name, extension);
+        }
+        let destination = path.with_file_name(name);
+        if !destination.exists() {
+            return Ok(destination);
+        }
+        number += 1;
+    }
+}
@@ -0,0 +1,473 @@
+//! Renaming, copying, moving and trashing the images of a folder, from the
+//! context menu of an image or the keyboard.
+//!
+//! F2 renames the focused image, Delete moves the selected images to the
+//! trash, Ctrl+Z brings the images trashed last back, and Ctrl+Shift+C and
+//! Ctrl+Shift+M copy and move them to another folder.
+
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use druid::{
+    commands::SHOW_OPEN_PANEL,
+    widget::{Controller, CrossAxisAlignment, Either, Flex, SizedBox},
+    Command, ContextMenu, Env, Event, EventCtx, ExtEventSink,
+    FileDialogOptions, FileInfo, KbKey, KeyEvent, LocalizedString, MenuDesc,
+    MenuItem, Selector, Target, Widget, WidgetExt, WidgetId,
+};
+use druid_navigator::navigator::ViewController;
+
+use crate::{
+    app_commands::{
+        CANCEL_RENAME, CONFIRM_RENAME, COPY_IMAGES, COPY_IMAGES_TO,
+        MOVED_IMAGES, MOVE_IMAGES, MOVE_IMAGES_TO, POP_VIEW, REFRESH_COVER,
+        RENAME_IMAGES, SHOW_IMAGE_MENU, TAKE_FOCUS, TRASHED_IMAGES,
+        TRASH_IMAGES, UNDO_TRASH,
+    },
+    app_data::AppState,
+    error::{report_error, show_error, GalleryError},
+    sidecar::sidecar_path,
+    trash::{self, copy_file, move_file},
+};
+
+use super::{
+    album_menu, annotation_menu, edit_bar, editing_menu, rotation_menu,
+    FolderGalleryState, FolderView,
+};
+
+/// Adds the rename bar above `content` and handles the file operations of
+/// the folder's views.
+pub fn with_file_operations(
+    content: impl Widget<FolderGalleryState> + 'static,
+) -> impl Widget<FolderGalleryState> {
+    let rename_box = WidgetId::next();
+    let rename_bar = Either::new(
+        |data: &FolderGalleryState, _env| data.renaming,
+        edit_bar(
+            "Rename to",
+            "Rename",
+            FolderGalleryState::new_name,
+            rename_box,
+            CONFIRM_RENAME,
+            CANCEL_RENAME,
+        ),
+        SizedBox::empty(),
+    );
+
+    Flex::column()
+        .with_child(rename_bar)
+        .with_flex_child(content, 1.0)
+        .cross_axis_alignment(CrossAxisAlignment::Fill)
+        .controller(FileOperationsController { rename_box })
+}
+
+fn image_menu(data: &FolderGalleryState) -> MenuDesc<AppState> {
+    let item = |key: &'static str, title: &'static str, command| {
+        MenuItem::new(
+            LocalizedString::new(key).with_placeholder(title),
+            command,
+        )
+    };
+    let menu = MenuDesc::empty()
+        .append(item("gallery-rename", "Rename…", RENAME_IMAGES))
+        .append(item("gallery-copy-to", "Copy To…", COPY_IMAGES))
+        .append(item("gallery-move-to", "Move To…", MOVE_IMAGES))
+        .append_separator()
+        .append(item("gallery-trash", "Move to Trash", TRASH_IMAGES))
+        .append(item("gallery-undo-trash", "Undo Move to Trash", UNDO_TRASH));
+    annotation_menu(album_menu(
+        editing_menu(rotation_menu(menu, data), data),
+        data,
+    ))
+}
+
+/// The command of a file operation's keyboard shortcut.
+fn shortcut(key: &KeyEvent) -> Option<Selector<()>> {
+    match &key.key {
+        KbKey::F2 => Some(RENAME_IMAGES),
+        KbKey::Delete => Some(TRASH_IMAGES),
+        KbKey::Character(c) if key.mods.ctrl() => {
+            match c.to_lowercase().as_str() {
+                "z" => Some(UNDO_TRASH),
+                "c" if key.mods.shift() => Some(COPY_IMAGES),
+                "m" if key.mods.shift() => Some(MOVE_IMAGES),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+struct FileOperationsController {
+    rename_box: WidgetId,
+}
+
+impl FileOperationsController {
+    fn start_renaming(
+        &self,
+        ctx: &mut EventCtx,
+        data: &mut FolderGalleryState,
+    ) {
+        let path = match data.paths.get(data.selected_image) {
+            Some(path) => path,
+            None => return,
+        };
+        data.new_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        data.renaming = true;
+        ctx.submit_command(Command::new(
+            TAKE_FOCUS,
+            (),
+            Target::Widget(self.rename_box),
+        ));
+    }
+
+    fn stop_renaming(&self, ctx: &mut EventCtx, data: &mut FolderGalleryState) {
+        data.renaming = false;
+        // the rename bar is hidden by the time this arrives, so it gives the
+        // focus back to the grid or the image
+        ctx.submit_command(Command::new(TAKE_FOCUS, (), Target::Auto));
+    }
+}
+
+impl<W: Widget<FolderGalleryState>> Controller<FolderGalleryState, W>
+    for FileOperationsController
+{
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut FolderGalleryState,
+        env: &Env,
+    ) {
+        // the slideshow has keys of its own and no menu
+        let has_operations = !data.paths.is_empty()
+            && data.current_view() != &FolderView::Slideshow;
+        match event {
+            Event::MouseDown(mouse)
+                if mouse.button.is_right()
+                    && has_operations
+                    && matches!(
+                        data.current_view(),
+                        FolderView::SingleImage | FolderView::Filmstrip
+                    ) =>
+            {
+                ctx.show_context_menu(ContextMenu::new(
+                    image_menu(data),
+                    mouse.window_pos,
+                ));
+                ctx.set_handled();
+                return;
+            }
+            Event::KeyDown(key) if has_operations && !data.is_editing() => {
+                if let Some(command) = shortcut(key) {
+                    ctx.submit_command(Command::new(command, (), Target::Auto));
+                    ctx.set_handled();
+                    return;
+                }
+            }
+            Event::Command(cmd) if cmd.is(SHOW_IMAGE_MENU) => {
+                let (index, position) = *cmd.get_unchecked(SHOW_IMAGE_MENU);
+                // the menu applies to the selection if the image is in it,
+                // and to the image alone otherwise
+                if !data.is_selected(index) {
+                    data.selection.clear();
+                }
+                data.selected_image = index;
+                ctx.show_context_menu(ContextMenu::new(
+                    image_menu(data),
+                    position,
+                ));
+                return;
+            }
+            Event::Command(cmd) if cmd.is(RENAME_IMAGES) && has_operations => {
+                self.start_renaming(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(CONFIRM_RENAME) => {
+                self.stop_renaming(ctx, data);
+                rename_image(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(CANCEL_RENAME) => {
+                self.stop_renaming(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(COPY_IMAGES) && has_operations => {
+                ask_for_folder(ctx, "Copy To", COPY_IMAGES_TO);
+            }
+            Event::Command(cmd) if cmd.is(MOVE_IMAGES) && has_operations => {
+                ask_for_folder(ctx, "Move To", MOVE_IMAGES_TO);
+            }
+            Event::Command(cmd) if cmd.is(COPY_IMAGES_TO) => {
+                let folder = cmd.get_unchecked(COPY_IMAGES_TO).path();
+                copy_images(
+                    data.selected_paths(),
+                    folder.to_path_buf(),
+                    ctx.get_external_handle(),
+                );
+            }
+            Event::Command(cmd) if cmd.is(MOVE_IMAGES_TO) => {
+                let folder = cmd.get_unchecked(MOVE_IMAGES_TO).path();
+                move_images(
+                    data.selected_paths(),
+                    folder.to_path_buf(),
+                    ctx.get_external_handle(),
+                );
+            }
+            Event::Command(cmd) if cmd.is(MOVED_IMAGES) => {
+                apply_moves(ctx, data, cmd.get_unchecked(MOVED_IMAGES));
+            }
+            Event::Command(cmd) if cmd.is(TRASH_IMAGES) && has_operations => {
+                trash_images(data.selected_paths(), ctx.get_external_handle());
+            }
+            Event::Command(cmd) if cmd.is(TRASHED_IMAGES) => {
+                let (removed, _) = cmd.get_unchecked(TRASHED_IMAGES);
+                remove_images(ctx, data, removed);
+            }
+            _ => (),
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+/// Where `path` goes in `folder`, which mustn't have a file of that name
+/// already. This only fails early, as the file can still be created before
+/// the image gets there, which `move_file` and `copy_file` refuse to replace.
+fn destination(path: &Path, folder: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "not a file")
+    })?;
+    let destination = folder.join(name);
+    if destination.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", destination.display()),
+        ));
+    }
+    Ok(destination)
+}
+
+fn ask_for_folder(ctx: &mut EventCtx, title: &str, accept: Selector<FileInfo>) {
+    let options = FileDialogOptions::new()
+        .select_directories()
+        .title(title)
+        .button_text(title)
+        .accept_command(accept);
+    ctx.submit_command(SHOW_OPEN_PANEL.with(options));
+}
+
+/// Sends the folder to the main view if its last image was moved out of it,
+/// and has its cover created again if its first image changed.
+fn finish_changes(
+    ctx: &mut EventCtx,
+    data: &FolderGalleryState,
+    first: Option<Arc<PathBuf>>,
+) {
+    // albums are kept when they're emptied, and their covers are taken care
+    // of along with saving them
+    if data.album {
+        return;
+    }
+    if data.paths.is_empty() {
+        // the watcher removes the folder from the library
+        ctx.submit_command(Command::new(POP_VIEW, (), Target::Auto));
+    } else if data.paths.get(0) != first.as_ref() {
+        ctx.submit_command(REFRESH_COVER.with(data.name.clone()));
+    }
+}
+
+fn rename_image(ctx: &mut EventCtx, data: &mut FolderGalleryState) {
+    let old = match data.paths.get(data.selected_image) {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    let name = data.new_name.trim();
+    if name.is_empty() || old.file_name() == Some(OsStr::new(name)) {
+        return;
+    }
+    let new = old.with_file_name(name);
+    let result = if name.contains(std::path::is_separator) {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "names can't contain a path separator",
+        ))
+    } else {
+        // this also changes only the case of the name on file systems that
+        // ignore it, where the new name is taken by the image itself
+        move_file(old.as_path(), &new)
+    };
+    match result {
+        Ok(()) => {
+            if let Err(err) = move_sidecar(&old, &new) {
+                let err = GalleryError::file_operation("rename", &old, err);
+                show_error(ctx, err);
+            }
+            let first = data.paths.get(0).cloned();
+            let new = Arc::new(new);
+            data.rename_image(&old, new.clone());
+            // albums holding the image are pointed at its new name
+            ctx.submit_command(MOVED_IMAGES.with(vec![(old, new)]));
+            finish_changes(ctx, data, first);
+        }
+        Err(err) => {
+            show_error(ctx, GalleryError::file_operation("rename", &old, err))
+        }
+    }
+}
+
+/// Copies the images on another thread, as they can be large. The watcher
+/// adds the copies to the library, if the folder is in it.
+fn copy_images(
+    paths: Vec<Arc<PathBuf>>,
+    folder: PathBuf,
+    handle: ExtEventSink,
+) {
+    thread::spawn(move || {
+        for path in paths {
+            let result = destination(&path, &folder).and_then(|destination| {
+                copy_file(path.as_path(), &destination)?;
+                copy_sidecar(&path, &destination)
+            });
+            if let Err(err) = result {
+                let err = GalleryError::file_operation("copy", &path, err);
+                report_error(&handle, &err);
+            }
+        }
+    });
+}
+
+/// Moves the images on another thread, as moving them to another file
+/// system copies them. The folder view drops them, or keeps them at their new
+/// place in albums, once they're moved, so it doesn't show images that are
+/// gone until the watcher catches up.
+fn move_images(
+    paths: Vec<Arc<PathBuf>>,
+    folder: PathBuf,
+    handle: ExtEventSink,
+) {
+    thread::spawn(move || {
+        let mut moved = Vec::new();
+        for path in paths {
+            let result = destination(&path, &folder).and_then(|destination| {
+                move_file(&path, &destination)?;
+                Ok(destination)
+            });
+            match result {
+                Ok(destination) => {
+                    if let Err(err) = move_sidecar(&path, &destination) {
+                        let err =
+                            GalleryError::file_operation("move", &path, err);
+                        report_error(&handle, &err);
+                    }
+                    moved.push((path, Arc::new(destination)));
+                }
+                Err(err) => {
+                    let err = GalleryError::file_operation("move", &path, err);
+                    report_error(&handle, &err);
+                }
+            }
+        }
+        if !moved.is_empty() {
+            // this only fails when the app is closing
+            let _ = handle.submit_command(MOVED_IMAGES, moved, Target::Auto);
+        }
+    });
+}
+
+fn apply_moves(
+    ctx: &mut EventCtx,
+    data: &mut FolderGalleryState,
+    moved: &[(Arc<PathBuf>, Arc<PathBuf>)],
+) {
+    if data.album {
+        for (old, new) in moved {
+            data.rename_image(old, new.clone());
+        }
+    } else {
+        let removed: Vec<Arc<PathBuf>> =
+            moved.iter().map(|(old, _)| old.clone()).collect();
+        remove_images(ctx, data, &removed);
+    }
+}
+
+/// Drops the images at `removed` that are in the folder after they were
+/// moved out of it.
+fn remove_images(
+    ctx: &mut EventCtx,
+    data: &mut FolderGalleryState,
+    removed: &[Arc<PathBuf>],
+) {
+    // the folder can have been left and another one opened meanwhile
+    if !removed.iter().any(|path| data.paths.contains(path)) {
+        return;
+    }
+    let first = data.paths.get(0).cloned();
+    data.remove_images(removed);
+    finish_changes(ctx, data, first);
+}
+
+/// Moves the images to the trash on another thread, as trashing them can
+/// copy them to another file system. `Delegate` keeps what's needed to
+/// restore them, so they can be brought back from any view.
+fn trash_images(paths: Vec<Arc<PathBuf>>, handle: ExtEventSink) {
+    thread::spawn(move || {
+        let mut trashed = Vec::new();
+        let mut removed = Vec::new();
+        for path in paths {
+            match trash::trash_file(&path) {
+                Ok(file) => trashed.push(file),
+                Err(err) => {
+                    let err = GalleryError::file_operation("trash", &path, err);
+                    report_error(&handle, &err);
+                    continue;
+                }
+            }
+            // the sidecar goes along, so undoing brings it back too
+            let sidecar = sidecar_path(&path);
+            if sidecar.exists() {
+                match trash::trash_file(&sidecar) {
+                    Ok(file) => trashed.push(file),
+                    Err(err) => {
+                        let err = GalleryError::file_operation(
+                            "trash", &sidecar, err,
+                        );
+                        report_error(&handle, &err);
+                    }
+                }
+            }
+            removed.push(path);
+        }
+        if !removed.is_empty() {
+            // this only fails when the app is closing
+            let _ = handle.submit_command(
+                TRASHED_IMAGES,
+                (removed, trashed),
+                Target::Auto,
+            );
+        }
+    });
+}
+
+/// Moves the sidecar of the image at `from` along with it, if it has one.
+fn move_sidecar(from: &Path, to: &Path) -> io::Result<()> {
+    let sidecar = sidecar_path(from);
+    if !sidecar.exists() {
+        return Ok(());
+    }
+    move_file(&sidecar, &sidecar_path(to))
+}
+
+/// Copies the sidecar of the image at `from` along with it, if it has one.
+fn copy_sidecar(from: &Path, to: &Path) -> io::Result<()> {
+    let sidecar = sidecar_path(from);
+    if !sidecar.exists() {
+        return Ok(());
+    }
+    copy_file(&sidecar, &sidecar_path(to))
+}
@@ -1,5 +1,19 @@
+mod albums;
+mod annotations;
 mod data;
+mod edit_bar;
+mod editing;
+mod file_ops;
+mod rotation;
+mod sidecar_writer;
 mod views;
 
+pub use albums::*;
+pub use annotations::*;
 pub use data::*;
+pub use edit_bar::*;
+pub use editing::*;
+pub use file_ops::*;
+pub use rotation::*;
+pub use sidecar_writer::*;
 pub use views::*;
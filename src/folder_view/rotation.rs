@@ -0,0 +1,378 @@
+//! Rotating and flipping the selected images, from the context menu of an
+//! image or the keyboard.
+//!
+//! Ctrl+R turns the selected images clockwise and Ctrl+Shift+R turns them
+//! counterclockwise. JPEGs are turned without losing any quality, either by
+//! rearranging the compressed image or by changing only their EXIF
+//! orientation. Which one starts out as `jpeg_rotation` in the config and can
+//! be switched from the context menu. Other images are decoded, turned and
+//! saved again.
+//!
+//! Saving an image again keeps only its pixels. Whatever else the file holds
+//! is lost, like the text, color profile and EXIF chunks of PNGs, the pages
+//! of a TIFF after the first and the exact palette of a GIF. Animated GIFs,
+//! which would be left with their first frame, are refused, as are the
+//! formats the `image` crate can read but not write, like WebP, HDR and DDS,
+//! and icons, which can hold more than one image.
+
+use std::{
+    fs,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use druid::{
+    widget::Controller, Env, Event, EventCtx, ExtEventSink, KbKey, KeyEvent,
+    LocalizedString, MenuDesc, MenuItem, Target, Widget, WidgetExt,
+};
+use image::{
+    codecs::gif::GifDecoder, io::Reader, AnimationDecoder, ImageFormat,
+};
+use log::warn;
+
+use crate::{
+    app_commands::{
+        ROTATED_EDITS, ROTATED_IMAGES, ROTATE_IMAGES, SET_JPEG_ROTATION,
+    },
+    app_data::AppState,
+    config::JpegRotation,
+    edits::EditStack,
+    error::{report_error, show_error, GalleryError},
+    jpeg::{self, JpegError},
+    orientation,
+    sidecar::{read_edits, write_edits},
+    thumbnail_cache,
+};
+
+use super::{FolderGalleryState, FolderView};
+
+/// A rotation or flip of the selected images, as they're shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rotate {
+    Clockwise,
+    CounterClockwise,
+    HalfTurn,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl Rotate {
+    /// The orientation whose transform this is.
+    fn orientation(self) -> u32 {
+        match self {
+            Rotate::Clockwise => 6,
+            Rotate::CounterClockwise => 8,
+            Rotate::HalfTurn => 3,
+            Rotate::FlipHorizontal => 2,
+            Rotate::FlipVertical => 4,
+        }
+    }
+}
+
+/// Handles rotating and flipping the images of `content`.
+pub fn with_rotation(
+    content: impl Widget<FolderGalleryState> + 'static,
+) -> impl Widget<FolderGalleryState> {
+    content.controller(RotationController)
+}
+
+/// Adds the items rotating and flipping the selected images to the end of
+/// `menu`, if any of them can be rotated, along with the switch between the
+/// two ways of rotating JPEGs if any of them are.
+pub fn rotation_menu(
+    menu: MenuDesc<AppState>,
+    data: &FolderGalleryState,
+) -> MenuDesc<AppState> {
+    let paths = data.selected_paths();
+    if !paths.iter().any(|path| rotatable(path)) {
+        return menu;
+    }
+    let item = |key: &'static str, title: &'static str, rotate| {
+        MenuItem::new(
+            LocalizedString::new(key).with_placeholder(title),
+            ROTATE_IMAGES.with(rotate),
+        )
+    };
+    let menu = menu
+        .append_separator()
+        .append(item(
+            "gallery-rotate-clockwise",
+            "Rotate Clockwise",
+            Rotate::Clockwise,
+        ))
+        .append(item(
+            "gallery-rotate-counterclockwise",
+            "Rotate Counterclockwise",
+            Rotate::CounterClockwise,
+        ))
+        .append(item("gallery-rotate-180", "Rotate 180°", Rotate::HalfTurn))
+        .append(item(
+            "gallery-flip-horizontal",
+            "Flip Horizontally",
+            Rotate::FlipHorizontal,
+        ))
+        .append(item(
+            "gallery-flip-vertical",
+            "Flip Vertically",
+            Rotate::FlipVertical,
+        ));
+    let jpegs = paths.iter().any(|path| {
+        matches!(
+            ImageFormat::from_path(path.as_path()),
+            Ok(ImageFormat::Jpeg)
+        )
+    });
+    if !jpegs {
+        return menu;
+    }
+    let lossless = data.jpeg_rotation == JpegRotation::Lossless;
+    let toggled = if lossless {
+        JpegRotation::Orientation
+    } else {
+        JpegRotation::Lossless
+    };
+    menu.append(
+        MenuItem::new(
+            LocalizedString::new("gallery-rotate-jpeg-pixels")
+                .with_placeholder("Rotate JPEG Pixels"),
+            SET_JPEG_ROTATION.with(toggled),
+        )
+        .selected(lossless),
+    )
+}
+
+/// Whether the format of the image at `path` can be rotated, which it can't
+/// if the image couldn't be saved again.
+fn rotatable(path: &Path) -> bool {
+    matches!(
+        ImageFormat::from_path(path),
+        Ok(ImageFormat::Jpeg)
+            | Ok(ImageFormat::Png)
+            | Ok(ImageFormat::Gif)
+            | Ok(ImageFormat::Bmp)
+            | Ok(ImageFormat::Tiff)
+            | Ok(ImageFormat::Tga)
+    )
+}
+
+/// The rotation of a keyboard shortcut.
+fn shortcut(key: &KeyEvent) -> Option<Rotate> {
+    match &key.key {
+        KbKey::Character(c) if key.mods.ctrl() && c.to_lowercase() == "r" => {
+            if key.mods.shift() {
+                Some(Rotate::CounterClockwise)
+            } else {
+                Some(Rotate::Clockwise)
+            }
+        }
+        _ => None,
+    }
+}
+
+struct RotationController;
+
+impl<W: Widget<FolderGalleryState>> Controller<FolderGalleryState, W>
+    for RotationController
+{
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut FolderGalleryState,
+        env: &Env,
+    ) {
+        // the slideshow has keys of its own
+        let can_rotate = !data.paths.is_empty()
+            && data.current_view() != &FolderView::Slideshow;
+        match event {
+            Event::KeyDown(key) if can_rotate && !data.is_editing() => {
+                if let Some(rotate) = shortcut(key) {
+                    ctx.submit_command(ROTATE_IMAGES.with(rotate));
+                    ctx.set_handled();
+                    return;
+                }
+            }
+            Event::Command(cmd) if cmd.is(ROTATE_IMAGES) && can_rotate => {
+                // images that can't be saved again are refused before any
+                // of them are touched
+                let (paths, refused): (Vec<_>, Vec<_>) = data
+                    .selected_paths()
+                    .into_iter()
+                    .partition(|path| rotatable(path));
+                for path in refused {
+                    let err = io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "images of its format can't be saved again",
+                    );
+                    show_error(
+                        ctx,
+                        GalleryError::file_operation("rotate", &path, err),
+                    );
+                }
+                rotate_images(
+                    paths,
+                    *cmd.get_unchecked(ROTATE_IMAGES),
+                    data.jpeg_rotation,
+                    ctx.get_external_handle(),
+                );
+            }
+            Event::Command(cmd) if cmd.is(SET_JPEG_ROTATION) => {
+                data.jpeg_rotation = *cmd.get_unchecked(SET_JPEG_ROTATION);
+            }
+            _ => (),
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+/// Rotates the images on another thread, as they can be large, along with
+/// their crops. `Delegate` has their thumbnails created again once they're
+/// done.
+fn rotate_images(
+    paths: Vec<Arc<PathBuf>>,
+    rotate: Rotate,
+    jpeg_rotation: JpegRotation,
+    handle: ExtEventSink,
+) {
+    thread::spawn(move || {
+        let mut rotated = Vec::new();
+        let mut rotated_edits = Vec::new();
+        for path in paths {
+            if let Err(err) = rotate_file(&path, rotate, jpeg_rotation) {
+                let err = GalleryError::file_operation("rotate", &path, err);
+                report_error(&handle, &err);
+                continue;
+            }
+            thumbnail_cache::remove(&path);
+            match rotate_edits(&path, rotate) {
+                Ok(Some(edits)) => rotated_edits.push((path.clone(), edits)),
+                Ok(None) => (),
+                Err(err) => {
+                    let err = GalleryError::file_operation(
+                        "write the sidecar of",
+                        &path,
+                        err,
+                    );
+                    report_error(&handle, &err);
+                }
+            }
+            rotated.push(path);
+        }
+        // the edits are sent first, so the image view shows the image again
+        // with them
+        if !rotated_edits.is_empty() {
+            let _ = handle.submit_command(
+                ROTATED_EDITS,
+                rotated_edits,
+                Target::Auto,
+            );
+        }
+        if !rotated.is_empty() {
+            // this only fails when the app is closing
+            let _ =
+                handle.submit_command(ROTATED_IMAGES, rotated, Target::Auto);
+        }
+    });
+}
+
+/// Turns the saved edits of the image at `path` along with it, returning
+/// them if it has any.
+fn rotate_edits(path: &Path, rotate: Rotate) -> io::Result<Option<EditStack>> {
+    let edits = read_edits(path)?;
+    if edits.edits.is_empty() {
+        return Ok(None);
+    }
+    let edits = edits.oriented(rotate.orientation());
+    write_edits(path, &edits)?;
+    Ok(Some(edits))
+}
+
+fn rotate_file(
+    path: &Path,
+    rotate: Rotate,
+    jpeg_rotation: JpegRotation,
+) -> io::Result<()> {
+    let format = ImageFormat::from_path(path).map_err(to_io_error)?;
+    // the image is turned as it's shown, which is with its orientation
+    let orientation =
+        orientation::compose(orientation::read(path), rotate.orientation());
+    if format == ImageFormat::Jpeg {
+        let data = fs::read(path)?;
+        let data = match jpeg_rotation {
+            JpegRotation::Lossless => match jpeg::transform(&data, orientation)
+            {
+                Err(JpegError::Unsupported(reason)) => {
+                    warn!(
+                        "Rotating {} by its orientation, as {}",
+                        path.display(),
+                        reason
+                    );
+                    jpeg::set_orientation(&data, orientation)?
+                }
+                result => result?,
+            },
+            JpegRotation::Orientation => {
+                jpeg::set_orientation(&data, orientation)?
+            }
+        };
+        replace_file(path, |temp_path| fs::write(temp_path, &data))
+    } else {
+        if format == ImageFormat::Gif && is_animated(path)? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "animated GIFs would lose all but their first frame",
+            ));
+        }
+        // the orientation isn't saved along with the image, so the image
+        // itself has to end up upright
+        let image = Reader::open(path)?
+            .with_guessed_format()?
+            .decode()
+            .map_err(to_io_error)?;
+        let image = orientation::apply_dynamic(image, orientation);
+        replace_file(path, |temp_path| {
+            image
+                .save_with_format(temp_path, format)
+                .map_err(to_io_error)
+        })
+    }
+}
+
+/// Whether the GIF at `path` has more than one frame.
+fn is_animated(path: &Path) -> io::Result<bool> {
+    let file = BufReader::new(fs::File::open(path)?);
+    let decoder = GifDecoder::new(file).map_err(to_io_error)?;
+    Ok(decoder.into_frames().take(2).count() > 1)
+}
+
+/// Has `write` write the new image next to `path` and moves it over the
+/// file, so the image is never left half written.
+fn replace_file(
+    path: &Path,
+    write: impl FnOnce(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "not a file")
+    })?;
+    let temp_path =
+        path.with_file_name(format!(".{}.rotating", name.to_string_lossy()));
+    let result = write(&temp_path)
+        .and_then(|_| fs::metadata(path))
+        .and_then(|metadata| {
+            fs::set_permissions(&temp_path, metadata.permissions())
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+pub(super) fn to_io_error(
+    err: impl std::error::Error + Send + Sync + 'static,
+) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
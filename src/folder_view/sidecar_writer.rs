@@ -0,0 +1,150 @@
+//! The thread writing the sidecars of a folder's images, so rating, tagging
+//! and editing never wait for the disk.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use druid::{EventCtx, ExtEventSink, Target, WidgetId};
+use log::error;
+use once_cell::sync::Lazy;
+
+use crate::{
+    app_commands::{EDITED_IMAGE, UNSAVED_ANNOTATIONS, UNSAVED_EDITS},
+    edits::EditStack,
+    error::{report_error, GalleryError},
+    sidecar::{
+        read_annotations, read_edits, write_annotations, write_edits,
+        Annotations,
+    },
+};
+
+/// The writer of the app, shared by the folder views, which are built again
+/// every time a folder or an image is opened, so only one thread writes the
+/// sidecars.
+static WRITER: Lazy<Mutex<SidecarWriter>> =
+    Lazy::new(|| Mutex::new(SidecarWriter::start()));
+
+/// Writes sidecars on a thread of its own, in the order they're sent, so the
+/// last change to a sidecar is the one that stays.
+///
+/// Annotations and edits are written by the same thread, as they share the
+/// sidecar. Sidecars that couldn't be written are read again and sent back
+/// to the widget that sent them, so their images show what's on disk.
+#[derive(Clone)]
+pub struct SidecarWriter {
+    sender: Sender<SidecarWrites>,
+}
+
+/// Changes to write to the sidecars of their images.
+enum Changes {
+    Annotations(Vec<(Arc<PathBuf>, Annotations)>),
+    Edits(Vec<(Arc<PathBuf>, EditStack)>),
+}
+
+/// A batch of changes, and where to send the ones that couldn't be written.
+struct SidecarWrites {
+    changes: Changes,
+    handle: ExtEventSink,
+    widget: WidgetId,
+}
+
+impl SidecarWriter {
+    pub fn shared() -> Self {
+        WRITER.lock().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+
+    fn start() -> Self {
+        let (sender, writes) = channel();
+        thread::spawn(move || write_sidecars(writes));
+        SidecarWriter { sender }
+    }
+
+    pub fn write_annotations(
+        &self,
+        ctx: &EventCtx,
+        annotations: Vec<(Arc<PathBuf>, Annotations)>,
+    ) {
+        self.send(ctx, Changes::Annotations(annotations));
+    }
+
+    /// Writes the edit stacks, and has the thumbnails of their images made
+    /// again with `EDITED_IMAGE` once they're written.
+    pub fn write_edits(
+        &self,
+        ctx: &EventCtx,
+        edits: Vec<(Arc<PathBuf>, EditStack)>,
+    ) {
+        self.send(ctx, Changes::Edits(edits));
+    }
+
+    fn send(&self, ctx: &EventCtx, changes: Changes) {
+        let writes = SidecarWrites {
+            changes,
+            handle: ctx.get_external_handle(),
+            widget: ctx.widget_id(),
+        };
+        if self.sender.send(writes).is_err() {
+            error!("The sidecar writer stopped, changes won't be saved");
+        }
+    }
+}
+
+fn write_sidecars(writes: Receiver<SidecarWrites>) {
+    for batch in writes {
+        let handle = &batch.handle;
+        let target = Target::Widget(batch.widget);
+        // submitting commands only fails when the app is closing
+        match batch.changes {
+            Changes::Annotations(annotations) => {
+                let mut unsaved = Vec::new();
+                for (path, annotations) in annotations {
+                    if let Err(err) = write_annotations(&path, &annotations) {
+                        report_unsaved(handle, &path, err);
+                        let on_disk =
+                            read_annotations(&path).unwrap_or_default();
+                        unsaved.push((path, on_disk));
+                    }
+                }
+                if !unsaved.is_empty() {
+                    let _ = handle.submit_command(
+                        UNSAVED_ANNOTATIONS,
+                        unsaved,
+                        target,
+                    );
+                }
+            }
+            Changes::Edits(edits) => {
+                let mut unsaved = Vec::new();
+                for (path, edits) in edits {
+                    if let Err(err) = write_edits(&path, &edits) {
+                        report_unsaved(handle, &path, err);
+                        let on_disk = read_edits(&path).unwrap_or_default();
+                        unsaved.push((path, on_disk));
+                    } else {
+                        let _ = handle.submit_command(
+                            EDITED_IMAGE,
+                            path,
+                            Target::Auto,
+                        );
+                    }
+                }
+                if !unsaved.is_empty() {
+                    let _ =
+                        handle.submit_command(UNSAVED_EDITS, unsaved, target);
+                }
+            }
+        }
+    }
+}
+
+fn report_unsaved(handle: &ExtEventSink, path: &Path, err: io::Error) {
+    let err = GalleryError::file_operation("write the sidecar of", path, err);
+    report_error(handle, &err);
+}
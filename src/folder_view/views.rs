@@ -1,42 +1,73 @@
-use std::{path::Path, thread};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use druid::{
-    piet::{ImageFormat, InterpolationMode},
+    kurbo::Circle,
+    piet::{
+        ImageFormat, InterpolationMode, Text, TextLayout, TextLayoutBuilder,
+    },
     widget::{
-        Container, Controller, CrossAxisAlignment, FillStrat, Flex, FlexParams,
-        Image, Label, MainAxisAlignment, Painter, Scope,
+        Container, Controller, CrossAxisAlignment, Either, Flex, FlexParams,
+        Image, Label, LineBreaking, MainAxisAlignment, Painter, Scope,
+        SizedBox,
     },
-    Color, Command, Env, Event, ImageBuf, LensExt, RenderContext, Target,
-    Widget, WidgetExt,
+    Color, Command, Env, Event, ExtEventSink, FontFamily, ImageBuf, KbKey,
+    LensExt, PaintCtx, Point, Rect, RenderContext, Size, Target, UnitPoint,
+    Vec2, Widget, WidgetExt,
 };
-use druid_gridview::GridView;
-use druid_navigator::navigator::Navigator;
+use druid_navigator::navigator::{Navigator, ViewController};
 use druid_widget_nursery::DynamicSizedBox;
-use image::{imageops::thumbnail, io::Reader, ImageError, RgbImage};
+use image::{imageops::thumbnail, RgbImage};
 
 use crate::{
     app_commands::{
-        CREATED_THUMBNAIL, POP_FOLDER_VIEW, POP_VIEW,
-        PUSH_VIEW_WITH_SELECTED_IMAGE, SELECT_IMAGE_SELECTOR,
+        CREATED_THUMBNAIL, OPEN_IMAGE_FOLDER, POP_FOLDER_VIEW, POP_VIEW,
+        PUSH_VIEW_WITH_SELECTED_IMAGE, SELECT_IMAGE_SELECTOR, SET_ZOOM,
+        START_SLIDESHOW,
     },
-    app_data::{AppState, Thumbnail},
+    app_data::{paint_failed_thumbnail, AppState, Thumbnail, ThumbnailState},
+    config::{format_label, guess_format},
+    edits,
+    error::{report_error, GalleryError},
     folder_view::{
-        DisplayImageController, FolderGalleryState, FolderView,
-        FolderViewController, GalleryTransfer,
+        edit_panel, sort_images, with_albums, with_annotations, with_editing,
+        with_file_operations, with_rotation, DisplayImageController,
+        FolderGalleryState, FolderView, FolderViewController, GalleryTransfer,
+        SidecarWriter, SlideshowController, ThumbnailCell,
+        ThumbnailClickController,
     },
-    widgets::{Button, Scroll},
+    metadata::ImageMetadata,
+    sidecar::{Annotations, Flag},
+    thumbnail_cache,
+    widgets::{
+        Button, Crossfade, Filmstrip, Foreground, GridNavigator, GridSelection,
+        Scroll, VirtualGrid, ZoomImage, ZoomPreset, SELECTED_CELL,
+    },
+    workers::{self, CancelToken, Priority},
 };
 
 use super::FolderThumbnailController;
 
 pub fn folder_navigator() -> Box<dyn Widget<AppState>> {
     let navigator = Navigator::new(FolderView::Folder, folder_view_main)
-        .with_view_builder(FolderView::SingleImage, image_view_builder);
+        .with_view_builder(FolderView::SingleImage, image_view_builder)
+        .with_view_builder(FolderView::Filmstrip, filmstrip_view_builder)
+        .with_view_builder(FolderView::Slideshow, slideshow_view_builder);
 
+    // annotations and edits share the sidecars, so one thread writes both
+    let writer = SidecarWriter::shared();
     let scope = Scope::from_function(
         FolderGalleryState::new,
         GalleryTransfer,
-        navigator,
+        with_albums(with_editing(
+            with_rotation(with_annotations(
+                with_file_operations(navigator),
+                writer.clone(),
+            )),
+            writer,
+        )),
     );
 
     Box::new(scope)
@@ -68,74 +99,173 @@ pub fn folder_view_main() -> Box<dyn Widget<FolderGalleryState>> {
             |_path, _data_path| (),
         ));
 
+    let sort_button = Button::new(
+        |data: &FolderGalleryState, _env: &Env| {
+            format!("Sort: {}", data.sort.label())
+        },
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xcc, 0xcc, 0xcc),
+        Color::rgb8(0x90, 0x90, 0x90),
+        16.,
+    )
+    .on_click(|ctx, data: &mut FolderGalleryState, _env| {
+        data.sort = data.sort.next();
+        sort_images(ctx.get_external_handle(), data);
+    });
+
+    let order_button = Button::new(
+        |data: &FolderGalleryState, _env: &Env| {
+            let arrow = if data.sort_ascending { "↑" } else { "↓" };
+            arrow.to_string()
+        },
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xcc, 0xcc, 0xcc),
+        Color::rgb8(0x90, 0x90, 0x90),
+        16.,
+    )
+    .on_click(|ctx, data: &mut FolderGalleryState, _env| {
+        data.sort_ascending = !data.sort_ascending;
+        sort_images(ctx.get_external_handle(), data);
+    });
+
+    let slideshow_button = Button::new(
+        "▶ Slideshow",
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xcc, 0xcc, 0xcc),
+        Color::rgb8(0x90, 0x90, 0x90),
+        16.,
+    )
+    .on_click(|ctx, _data: &mut FolderGalleryState, _env| {
+        ctx.submit_command(START_SLIDESHOW);
+    });
+
+    let filmstrip_button = Button::new(
+        "Filmstrip",
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xcc, 0xcc, 0xcc),
+        Color::rgb8(0x90, 0x90, 0x90),
+        16.,
+    )
+    .on_click(|ctx, data: &mut FolderGalleryState, _env| {
+        ctx.submit_command(Command::new(
+            PUSH_VIEW_WITH_SELECTED_IMAGE,
+            (FolderView::Filmstrip, data.selected_image),
+            Target::Auto,
+        ));
+    });
+
     let header = Flex::row()
         .with_child(back_button)
         .with_spacer(10.)
         .with_flex_child(title, 1.0)
+        .with_child(sort_button)
+        .with_child(order_button)
+        .with_spacer(10.)
+        .with_child(filmstrip_button)
+        .with_child(slideshow_button)
         .main_axis_alignment(MainAxisAlignment::Start);
 
-    let gallery = GridView::new(|| {
-        Image::new(ImageBuf::empty())
-            .interpolation_mode(InterpolationMode::NearestNeighbor)
-            .controller(FolderThumbnailController)
-            .fix_size(150., 150.)
-            .padding(5.)
-            .background(Painter::new(|ctx, (_thumbnail, _selected), _env| {
-                let is_hot = ctx.is_hot();
-                let is_active = ctx.is_active();
-                let background_color = if is_active {
-                    Color::rgb8(0x90, 0x90, 0x90)
-                } else if is_hot {
-                    Color::rgb8(0xcc, 0xcc, 0xcc)
-                } else {
-                    Color::rgb8(0xff, 0xff, 0xff)
-                };
-                let rect = ctx.size().to_rect();
-                ctx.stroke(rect, &background_color, 0.0);
-                ctx.fill(rect, &background_color);
-            }))
-            .on_click(|ctx, data, _env| {
-                ctx.submit_command(Command::new(
-                    PUSH_VIEW_WITH_SELECTED_IMAGE,
-                    (FolderView::SingleImage, data.1),
-                    Target::Auto,
-                ));
-            })
-    })
-    .wrap();
-
-    let gallery = gallery.align_left();
+    // the cells are only created once they scroll into view, so large
+    // folders don't create thousands of widgets
     let gallery =
-        DynamicSizedBox::new(Scroll::new(gallery).vertical().expand_width())
-            .with_width(0.95);
+        VirtualGrid::new(folder_thumbnail_builder, Size::new(160., 160.));
+
+    let gallery = GridNavigator::new(
+        Scroll::new(gallery).vertical(),
+        Size::new(160., 160.),
+    )
+    .on_open(|ctx, data: &mut FolderGalleryState, _env| {
+        ctx.submit_command(Command::new(
+            PUSH_VIEW_WITH_SELECTED_IMAGE,
+            (FolderView::SingleImage, data.selected_image),
+            Target::Auto,
+        ));
+    })
+    .on_back(|ctx, _data, _env| {
+        ctx.submit_command(Command::new(POP_VIEW, (), Target::Auto));
+    });
+    let gallery = DynamicSizedBox::new(gallery.expand_width()).with_width(0.95);
 
     let layout = Flex::column()
         .with_child(header)
         .with_flex_child(gallery, 1.0)
         .expand_width()
         .background(Color::WHITE)
-        .controller(FolderViewController)
-        // TODO: this shouldn't do any work if this folder was loaded previously
+        .controller(FolderViewController::new())
         .on_added(|_self, ctx, data, _env| {
-            let handle = ctx.get_external_handle();
-            let image_paths = data.paths.clone();
-            thread::spawn(move || {
-                for (i, path) in image_paths.iter().enumerate() {
-                    let thumbnail =
-                        create_thumbnail_from_path(&path, i).unwrap();
-                    handle
-                        .submit_command(
-                            CREATED_THUMBNAIL,
-                            thumbnail,
-                            Target::Auto,
-                        )
-                        .unwrap();
-                }
-            });
+            // the sort mode is kept between folders, so a folder that was
+            // sorted differently before has to be sorted again, except for
+            // albums which keep the order they were arranged in
+            if !data.album {
+                sort_images(ctx.get_external_handle(), data);
+            }
         });
     Box::new(layout)
 }
 
+fn folder_thumbnail_builder() -> impl Widget<ThumbnailCell> {
+    thumbnail_cell()
+        .on_click(|ctx, data, _env| {
+            ctx.submit_command(Command::new(
+                PUSH_VIEW_WITH_SELECTED_IMAGE,
+                (FolderView::SingleImage, data.index),
+                Target::Auto,
+            ));
+        })
+        .controller(ThumbnailClickController)
+}
+
+fn filmstrip_thumbnail_builder() -> impl Widget<ThumbnailCell> {
+    thumbnail_cell().on_click(|ctx, data, _env| {
+        ctx.submit_command(Command::new(
+            SELECT_IMAGE_SELECTOR,
+            data.index,
+            Target::Auto,
+        ));
+    })
+}
+
+/// A thumbnail of the grid or the filmstrip, with its overlays, highlighted
+/// while it's selected or hovered and ringed if it's the focused cell.
+fn thumbnail_cell() -> impl Widget<ThumbnailCell> {
+    let thumbnail = Image::new(ImageBuf::empty())
+        .interpolation_mode(InterpolationMode::NearestNeighbor)
+        .controller(FolderThumbnailController);
+    Foreground::new(thumbnail, paint_thumbnail_overlay)
+        .fix_size(150., 150.)
+        .padding(5.)
+        .background(Painter::new(|ctx, cell: &ThumbnailCell, env| {
+            let is_hot = ctx.is_hot();
+            let is_active = ctx.is_active();
+            let is_focused = env.get(SELECTED_CELL) == cell.index as u64;
+            let background_color = if is_active {
+                Color::rgb8(0x90, 0x90, 0x90)
+            } else if cell.selected && is_hot {
+                Color::rgb8(0x9c, 0xc3, 0xf5)
+            } else if cell.selected {
+                Color::rgb8(0xb8, 0xd5, 0xfa)
+            } else if is_hot {
+                Color::rgb8(0xcc, 0xcc, 0xcc)
+            } else {
+                Color::rgb8(0xff, 0xff, 0xff)
+            };
+            let rect = ctx.size().to_rect();
+            ctx.stroke(rect, &background_color, 0.0);
+            ctx.fill(rect, &background_color);
+            if is_focused {
+                ctx.stroke(
+                    rect.inset(-1.5),
+                    &Color::rgb8(0x16, 0x69, 0xdd),
+                    3.,
+                );
+            }
+        }))
+}
+
 pub fn image_view_builder() -> Box<dyn Widget<FolderGalleryState>> {
     let button_width = 50.0;
     let back_button = Button::new(
@@ -166,11 +296,7 @@ pub fn image_view_builder() -> Box<dyn Widget<FolderGalleryState>> {
         16.,
     )
     .on_click(|_ctx, data: &mut FolderGalleryState, _env| {
-        if data.paths.is_empty() || data.selected_image == 0 {
-            return;
-        }
-
-        data.selected_image -= 1;
+        data.previous_image();
     })
     .fix_width(button_width)
     .expand_height();
@@ -178,21 +304,97 @@ pub fn image_view_builder() -> Box<dyn Widget<FolderGalleryState>> {
     let right_button =
         Button::new("❯", font_color, bg_color, hover_color, active_color, 16.)
             .on_click(|_ctx, data: &mut FolderGalleryState, _env| {
-                if data.paths.is_empty()
-                    || data.selected_image == data.paths.len() - 1
-                {
-                    return;
-                }
-                data.selected_image += 1;
+                data.next_image();
             })
             .fix_width(button_width)
             .expand_height();
 
-    let image = Image::new(ImageBuf::empty())
-        .interpolation_mode(InterpolationMode::Bilinear)
-        .fill_mode(FillStrat::Contain)
+    let image = ZoomImage::new(ImageBuf::empty())
         .controller(DisplayImageController::new());
 
+    let zoom_button = |text: &str, preset: ZoomPreset| {
+        Button::new(
+            text,
+            Color::BLACK,
+            Color::rgb8(0xff, 0xff, 0xff),
+            Color::rgb8(0xcc, 0xcc, 0xcc),
+            Color::rgb8(0x90, 0x90, 0x90),
+            14.,
+        )
+        .on_click(move |ctx, _data: &mut FolderGalleryState, _env| {
+            ctx.submit_command(Command::new(SET_ZOOM, preset, Target::Auto));
+        })
+        .fix_height(40.)
+    };
+    // an image opened on its own can be shown along with the rest of its
+    // folder in the library
+    let open_folder_button = Either::new(
+        |data: &FolderGalleryState, _env| data.image_view,
+        Button::new(
+            "Open Folder",
+            Color::BLACK,
+            Color::rgb8(0xff, 0xff, 0xff),
+            Color::rgb8(0xcc, 0xcc, 0xcc),
+            Color::rgb8(0x90, 0x90, 0x90),
+            14.,
+        )
+        .on_click(|ctx, data: &mut FolderGalleryState, _env| {
+            if let Some(path) = data.paths.get(data.selected_image) {
+                ctx.submit_command(OPEN_IMAGE_FOLDER.with(path.clone()));
+            }
+        })
+        .fix_height(40.),
+        SizedBox::empty(),
+    );
+    let zoom_buttons = Flex::row()
+        .with_child(open_folder_button)
+        .with_flex_spacer(1.0)
+        .with_child(zoom_button("Fit", ZoomPreset::Fit))
+        .with_child(zoom_button("Fill", ZoomPreset::Fill))
+        .with_child(zoom_button("1:1", ZoomPreset::ActualSize))
+        .with_spacer(10.)
+        .with_child(
+            Button::new(
+                "Info",
+                Color::BLACK,
+                Color::rgb8(0xff, 0xff, 0xff),
+                Color::rgb8(0xcc, 0xcc, 0xcc),
+                Color::rgb8(0x90, 0x90, 0x90),
+                14.,
+            )
+            .on_click(|_ctx, data: &mut FolderGalleryState, _env| {
+                data.show_info = !data.show_info;
+            })
+            .fix_height(40.),
+        )
+        .with_child(
+            Button::new(
+                "Edit",
+                Color::BLACK,
+                Color::rgb8(0xff, 0xff, 0xff),
+                Color::rgb8(0xcc, 0xcc, 0xcc),
+                Color::rgb8(0x90, 0x90, 0x90),
+                14.,
+            )
+            .on_click(|_ctx, data: &mut FolderGalleryState, _env| {
+                data.show_edits = !data.show_edits;
+            })
+            .fix_height(40.),
+        )
+        .main_axis_alignment(MainAxisAlignment::End)
+        .must_fill_main_axis(true);
+
+    let info_panel = Either::new(
+        |data: &FolderGalleryState, _env| data.show_info,
+        info_panel(),
+        SizedBox::empty(),
+    );
+    let edit_panel = Either::new(
+        |data: &FolderGalleryState, _env| data.show_edits,
+        edit_panel(),
+        SizedBox::empty(),
+    );
+
     let left_side_buttons = Flex::column()
         .with_child(back_button)
         .with_flex_child(left_button, 1.0);
@@ -201,11 +403,14 @@ pub fn image_view_builder() -> Box<dyn Widget<FolderGalleryState>> {
         .with_child(left_side_buttons)
         .with_flex_child(image, FlexParams::new(1.0, None))
         .with_child(right_button)
+        .with_child(info_panel)
+        .with_child(edit_panel)
         .cross_axis_alignment(CrossAxisAlignment::Center)
         .main_axis_alignment(MainAxisAlignment::SpaceBetween);
 
     let layout = Flex::column()
         .must_fill_main_axis(true)
+        .with_child(zoom_buttons)
         .with_flex_child(image_view, FlexParams::new(1.0, None));
 
     let container = Container::new(layout)
@@ -215,137 +420,319 @@ pub fn image_view_builder() -> Box<dyn Widget<FolderGalleryState>> {
     Box::new(container)
 }
 
-// TODO: this will eventually be an alternative view for the folder view
-// pub fn filmstrip_view_builder() -> Box<dyn Widget<AppState>> {
-//     let button_width = 50.0;
-//     let font_color = Color::rgb8(0, 0, 0);
-//     let bg_color = Color::rgb8(0xff, 0xff, 0xff);
-//     let hover_color = Color::rgb8(0xcc, 0xcc, 0xcc);
-//     let active_color = Color::rgb8(0x90, 0x90, 0x90);
-
-//     let left_button = crate::widget::Button::new(
-//         "❮",
-//         font_color.clone(),
-//         bg_color.clone(),
-//         hover_color.clone(),
-//         active_color.clone(),
-//     )
-//     .on_click(|_ctx, data: &mut AppState, _env| {
-//         if data.images.is_empty() || data.current_image_idx == 0 {
-//             return;
-//         }
-
-//         data.current_image_idx -= 1;
-//     })
-//     .fix_width(button_width)
-//     .expand_height();
-
-//     let right_button = crate::widget::Button::new(
-//         "❯",
-//         font_color,
-//         bg_color,
-//         hover_color,
-//         active_color,
-//     )
-//     .on_click(|_ctx, data: &mut AppState, _env| {
-//         if data.images.is_empty()
-//             || data.current_image_idx == data.images.len() - 1
-//         {
-//             return;
-//         }
-//         data.current_image_idx += 1;
-//     })
-//     .fix_width(button_width)
-//     .expand_height();
-
-//     let image = Image::new(ImageBuf::empty())
-//         .interpolation_mode(InterpolationMode::Bilinear)
-//         .fill_mode(FillStrat::Contain);
-//     let image =
-//         DisplayImage::new(image).padding(Insets::new(0.0, 5.0, 0.0, 5.0));
-
-//     let image_view = Flex::row()
-//         .must_fill_main_axis(true)
-//         .with_child(left_button)
-//         .with_flex_child(image, FlexParams::new(1.0, None))
-//         .with_child(right_button)
-//         .cross_axis_alignment(CrossAxisAlignment::Center)
-//         .main_axis_alignment(MainAxisAlignment::SpaceBetween);
-
-//     let film_strip_list = List::new(|| {
-//         Image::new(ImageBuf::empty())
-//             .interpolation_mode(InterpolationMode::NearestNeighbor)
-//             .controller(ThumbnailController {})
-//             .fix_size(150.0, 150.0)
-//             .padding(15.0)
-//             .background(Painter::new(
-//                 |ctx, (current_image, data): &(usize, Thumbnail), _env| {
-//                     let is_hot = ctx.is_hot();
-//                     let is_active = ctx.is_active();
-//                     let is_selected = *current_image == data.index;
-
-//                     let background_color = if is_selected {
-//                         Color::rgb8(0x9e, 0x9e, 0x9e)
-//                     } else if is_active {
-//                         Color::rgb8(0x87, 0x87, 0x87)
-//                     } else if is_hot {
-//                         Color::rgb8(0xc4, 0xc4, 0xc4)
-//                     } else {
-//                         Color::rgb8(0xee, 0xee, 0xee)
-//                     };
-
-//                     let rect = ctx.size().to_rect();
-//                     ctx.stroke(rect, &background_color, 0.0);
-//                     ctx.fill(rect, &background_color);
-//                 },
-//             ))
-//             .on_click(
-//                 |event: &mut EventCtx,
-//                  (_current_image, data): &mut (usize, Thumbnail),
-//                  _env| {
-//                     let select_image =
-//                         Selector::new("select_thumbnail").with(data.index);
-//                     event.submit_command(select_image);
-//                 },
-//             )
-//     })
-//     .horizontal();
-
-//     let film_strip_view = Scroll::new(film_strip_list)
-//         .horizontal()
-//         .background(Color::rgb8(0xee, 0xee, 0xee))
-//         .expand_width();
-
-//     let layout = Flex::column()
-//         .must_fill_main_axis(true)
-//         .with_flex_child(image_view, FlexParams::new(1.0, None))
-//         .with_child(film_strip_view);
-
-//     Box::new(
-//         Container::new(layout)
-//             .background(druid::Color::rgb8(255, 255, 255))
-//             .controller(AppStateController {}),
-//     )
-// }
-//         .background(Color::rgb8(0xee, 0xee, 0xee))
-//         .expand_width();
-
-//     let layout = Flex::column()
-//         .must_fill_main_axis(true)
-//         .with_flex_child(image_view, FlexParams::new(1.0, None))
-//         .with_child(film_strip_view);
-
-//     Box::new(
-//         Container::new(layout)
-//             .background(druid::Color::rgb8(255, 255, 255))
-//             .controller(AppStateController {}),
-//     )
-// }
+pub fn slideshow_view_builder() -> Box<dyn Widget<FolderGalleryState>> {
+    let slideshow = Crossfade::new().controller(SlideshowController::new());
+    Box::new(Foreground::new(slideshow, paint_slideshow_status))
+}
+
+/// Shows the slideshow's settings and keys at the bottom while it's paused,
+/// so they stay out of the way of the presentation.
+fn paint_slideshow_status(
+    ctx: &mut PaintCtx,
+    data: &FolderGalleryState,
+    _env: &Env,
+) {
+    if data.slideshow_playing {
+        return;
+    }
+    let settings = &data.slideshow;
+    let status = format!(
+        "Paused · {}/{} · {} s{}{}    \
+         Space play · ←/→ previous/next · ↑/↓ interval · L loop · S shuffle \
+         · Esc exit",
+        data.slideshow_order
+            .index_of(&data.selected_image)
+            .map(|position| position + 1)
+            .unwrap_or(0),
+        data.slideshow_order.len(),
+        settings.interval,
+        if settings.looping { " · loop" } else { "" },
+        if settings.shuffle { " · shuffle" } else { "" },
+    );
+    let layout = ctx
+        .text()
+        .new_text_layout(status)
+        .font(FontFamily::SYSTEM_UI, 14.)
+        .text_color(Color::WHITE)
+        .build()
+        .unwrap();
+    let text_size = layout.size();
+    let (padding_x, padding_y) = (12., 6.);
+    let size = ctx.size();
+    let origin = Point::new(
+        (size.width - text_size.width) / 2. - padding_x,
+        size.height - text_size.height - padding_y * 2. - 20.,
+    );
+    let bar = Rect::from_origin_size(
+        origin,
+        Size::new(
+            text_size.width + padding_x * 2.,
+            text_size.height + padding_y * 2.,
+        ),
+    );
+    ctx.fill(bar.to_rounded_rect(6.), &Color::rgba8(0, 0, 0, 0xb0));
+    ctx.draw_text(&layout, origin + Vec2::new(padding_x, padding_y));
+}
+
+/// A row of the info panel, showing `name` over the value `value` gives.
+fn info_row(
+    name: &str,
+    value: impl Fn(&FolderGalleryState) -> Option<String> + 'static,
+) -> impl Widget<FolderGalleryState> {
+    let value = Label::dynamic(move |data: &FolderGalleryState, _env| {
+        value(data).unwrap_or_else(|| "—".to_string())
+    })
+    .with_text_color(Color::BLACK)
+    .with_line_break_mode(LineBreaking::WordWrap);
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Label::new(name)
+                .with_text_color(Color::grey8(0x70))
+                .with_text_size(12.),
+        )
+        .with_child(value)
+        .padding((10., 5.))
+}
+
+/// The collapsible panel listing the metadata and the annotations of the
+/// selected image.
+fn info_panel() -> impl Widget<FolderGalleryState> {
+    let row = |name: &str, value: fn(&ImageMetadata) -> Option<String>| {
+        info_row(name, move |data| {
+            data.current_metadata()
+                .and_then(|metadata| value(&metadata))
+        })
+    };
+    let annotation_row =
+        |name: &str, value: fn(&Annotations) -> Option<String>| {
+            info_row(name, move |data| value(&data.current_annotations()))
+        };
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(row("Camera", |metadata| metadata.camera.clone()))
+        .with_child(row("Lens", |metadata| metadata.lens.clone()))
+        .with_child(row("Exposure", |metadata| metadata.exposure_time.clone()))
+        .with_child(row("Aperture", |metadata| metadata.aperture.clone()))
+        .with_child(row("ISO", |metadata| {
+            metadata.iso.map(|iso| iso.to_string())
+        }))
+        .with_child(row("Focal length", |metadata| {
+            metadata.focal_length.clone()
+        }))
+        .with_child(row("Captured", |metadata| metadata.capture_date.clone()))
+        .with_child(row("Dimensions", |metadata| metadata.dimensions_text()))
+        .with_child(row("File size", |metadata| {
+            Some(metadata.file_size_text())
+        }))
+        .with_child(row("Location", |metadata| metadata.gps_text()))
+        .with_child(annotation_row("Rating", |annotations| {
+            Some(annotations.stars())
+        }))
+        .with_child(annotation_row("Label", |annotations| {
+            annotations.label.map(|label| label.name().to_string())
+        }))
+        .with_child(annotation_row("Flag", |annotations| {
+            match annotations.flag {
+                Flag::Pick => Some("Pick".to_string()),
+                Flag::Reject => Some("Reject".to_string()),
+                Flag::Unflagged => None,
+            }
+        }))
+        .with_child(annotation_row("Tags", |annotations| {
+            if annotations.tags.is_empty() {
+                return None;
+            }
+            let tags: Vec<&str> =
+                annotations.tags.iter().map(String::as_str).collect();
+            Some(tags.join(", "))
+        }))
+        .fix_width(240.)
+        .expand_height()
+        .background(Color::rgb8(0xf5, 0xf5, 0xf5))
+}
+
+/// An alternative layout of the folder, showing the selected image over a
+/// strip of the thumbnails around it.
+pub fn filmstrip_view_builder() -> Box<dyn Widget<FolderGalleryState>> {
+    let button_width = 50.0;
+    let grid_button = Button::new(
+        "Grid",
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xcc, 0xcc, 0xcc),
+        Color::rgb8(0x90, 0x90, 0x90),
+        16.,
+    )
+    .on_click(|ctx, _data, _env| {
+        ctx.submit_command(Command::new(POP_FOLDER_VIEW, (), Target::Auto));
+    });
+
+    let title = Label::dynamic(|data: &String, _env| data.clone())
+        .with_text_color(Color::BLACK)
+        .lens(FolderGalleryState::name.map(
+            |data| data.to_string_lossy().to_owned().to_string(),
+            |_path, _data_path| (),
+        ));
+
+    let header = Flex::row()
+        .with_child(grid_button)
+        .with_spacer(10.)
+        .with_flex_child(title, 1.0)
+        .main_axis_alignment(MainAxisAlignment::Start);
+
+    let font_color = Color::rgb8(0, 0, 0);
+    let bg_color = Color::rgb8(0xff, 0xff, 0xff);
+    let hover_color = Color::rgb8(0xcc, 0xcc, 0xcc);
+    let active_color = Color::rgb8(0x90, 0x90, 0x90);
+
+    let left_button = Button::new(
+        "❮",
+        font_color.clone(),
+        bg_color.clone(),
+        hover_color.clone(),
+        active_color.clone(),
+        16.,
+    )
+    .on_click(|_ctx, data: &mut FolderGalleryState, _env| {
+        data.previous_image();
+    })
+    .fix_width(button_width)
+    .expand_height();
+
+    let right_button =
+        Button::new("❯", font_color, bg_color, hover_color, active_color, 16.)
+            .on_click(|_ctx, data: &mut FolderGalleryState, _env| {
+                data.next_image();
+            })
+            .fix_width(button_width)
+            .expand_height();
+
+    let image = ZoomImage::new(ImageBuf::empty())
+        .controller(DisplayImageController::new());
+
+    let image_view = Flex::row()
+        .must_fill_main_axis(true)
+        .with_child(left_button)
+        .with_flex_child(image, FlexParams::new(1.0, None))
+        .with_child(right_button)
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .main_axis_alignment(MainAxisAlignment::SpaceBetween);
+
+    let film_strip =
+        Filmstrip::new(filmstrip_thumbnail_builder, Size::new(160., 160.))
+            .expand_width()
+            .background(Color::rgb8(0xee, 0xee, 0xee));
+
+    let layout = Flex::column()
+        .must_fill_main_axis(true)
+        .with_child(header)
+        .with_flex_child(image_view, FlexParams::new(1.0, None))
+        .with_child(film_strip);
+
+    let container = Container::new(layout)
+        .background(druid::Color::rgb8(255, 255, 255))
+        .controller(FilmstripController);
+
+    Box::new(container)
+}
+
+/// Paints a placeholder over thumbnails that failed, the annotations of the
+/// image and the format badge.
+fn paint_thumbnail_overlay(
+    ctx: &mut PaintCtx,
+    data: &ThumbnailCell,
+    env: &Env,
+) {
+    paint_failed_thumbnail(ctx, &data.thumbnail, env);
+    paint_annotations(ctx, data, env);
+    paint_format_badge(ctx, data, env);
+}
+
+/// Paints the format of the image in the top right corner of its thumbnail.
+fn paint_format_badge(ctx: &mut PaintCtx, cell: &ThumbnailCell, _env: &Env) {
+    if let Some(label) = cell.thumbnail.format {
+        paint_badge(ctx, label, Color::WHITE, UnitPoint::TOP_RIGHT);
+    }
+}
+
+/// Paints the flag in the top left corner, the stars in the bottom left one
+/// and the color label in the bottom right one. Rejected images are dimmed.
+fn paint_annotations(ctx: &mut PaintCtx, cell: &ThumbnailCell, _env: &Env) {
+    let annotations = &cell.annotations;
+    match annotations.flag {
+        Flag::Reject => {
+            let rect = ctx.size().to_rect();
+            ctx.fill(rect, &Color::rgba8(0xff, 0xff, 0xff, 0x90));
+            paint_badge(
+                ctx,
+                "✕",
+                Color::rgb8(0xff, 0x6b, 0x6b),
+                UnitPoint::TOP_LEFT,
+            );
+        }
+        Flag::Pick => paint_badge(
+            ctx,
+            "✓",
+            Color::rgb8(0x7d, 0xe0, 0x83),
+            UnitPoint::TOP_LEFT,
+        ),
+        Flag::Unflagged => (),
+    }
+    if annotations.rating > 0 {
+        paint_badge(
+            ctx,
+            &"★".repeat(annotations.rating as usize),
+            Color::rgb8(0xf2, 0xc0, 0x1e),
+            UnitPoint::BOTTOM_LEFT,
+        );
+    }
+    if let Some(label) = annotations.label {
+        let size = ctx.size();
+        let dot = Circle::new((size.width - 12., size.height - 12.), 6.);
+        ctx.fill(dot, &label.color());
+        ctx.stroke(dot, &Color::WHITE, 1.5);
+    }
+}
+
+/// Paints `text` on a dark badge in the corner of the thumbnail at `corner`.
+fn paint_badge(
+    ctx: &mut PaintCtx,
+    text: &str,
+    text_color: Color,
+    corner: UnitPoint,
+) {
+    let layout = ctx
+        .text()
+        .new_text_layout(text.to_string())
+        .font(FontFamily::SYSTEM_UI, 10.)
+        .text_color(text_color)
+        .build()
+        .unwrap();
+    let text_size = layout.size();
+    let (padding_x, padding_y) = (4., 2.);
+    let badge_size = Size::new(
+        text_size.width + padding_x * 2.,
+        text_size.height + padding_y * 2.,
+    );
+    // the corners badges can be placed in, 4 pixels in from the edges
+    let area = Rect::from_origin_size(
+        Point::new(4., 4.),
+        ctx.size() - badge_size - Size::new(8., 8.),
+    );
+    let origin = corner.resolve(area);
+    let badge = Rect::from_origin_size(origin, badge_size);
+    ctx.fill(badge.to_rounded_rect(3.), &Color::rgba8(0, 0, 0, 0xa0));
+    ctx.draw_text(&layout, origin + Vec2::new(padding_x, padding_y));
+}
+
+const THUMBNAIL_HEIGHT: u32 = 150;
 
 fn create_thumbnail(index: usize, image: RgbImage) -> Thumbnail {
     let (width, height) = image.dimensions();
     let (new_width, new_height) = {
-        let max_height = 150.0;
+        let max_height = THUMBNAIL_HEIGHT as f64;
         let scale = max_height / height as f64;
         let scaled_width = width as f64 * scale;
         let scaled_height = height as f64 * scale;
@@ -359,16 +746,128 @@ fn create_thumbnail(index: usize, image: RgbImage) -> Thumbnail {
         width as usize,
         height as usize,
     );
-    Thumbnail { index, image }
+    Thumbnail {
+        image,
+        state: ThumbnailState::Loaded,
+        ..Thumbnail::empty(index)
+    }
+}
+
+/// Creates the thumbnail of the image at `path`, along with its index in the
+/// folder, on the worker pool, sending it with `CREATED_THUMBNAIL`. An image
+/// that can't be read is sent as a failed thumbnail and reported.
+pub fn queue_thumbnail(
+    index: usize,
+    path: Arc<PathBuf>,
+    cancel: &CancelToken,
+    handle: ExtEventSink,
+) {
+    workers::spawn(Priority::Visible, cancel, move || {
+        let thumbnail = match create_thumbnail_from_path(&path, index) {
+            Ok(thumbnail) => thumbnail,
+            Err(err) => {
+                report_error(&handle, &err);
+                Thumbnail {
+                    format: guess_format(&path).and_then(format_label),
+                    ..Thumbnail::failed(index, &err.reason())
+                }
+            }
+        };
+        // this only fails when the app is closing
+        let _ = handle.submit_command(
+            CREATED_THUMBNAIL,
+            (path, thumbnail),
+            Target::Auto,
+        );
+    });
 }
 
 pub fn create_thumbnail_from_path(
     path: &Path,
     idx: usize,
-) -> Result<Thumbnail, ImageError> {
-    let image = Reader::open(path)?;
-    let image = image.decode()?.to_rgb8();
-    Ok(create_thumbnail(idx, image))
+) -> Result<Thumbnail, GalleryError> {
+    let image = thumbnail_cache::load_or_create(path, THUMBNAIL_HEIGHT)
+        .map_err(|err| GalleryError::image(path, err))?;
+    // the cache follows the freedesktop specification and is shared with
+    // other programs, so it keeps the images as they are without their edits
+    let image = edits::apply_saved(path, image);
+    let mut thumbnail = create_thumbnail(idx, image);
+    thumbnail.format = guess_format(path).and_then(format_label);
+    Ok(thumbnail)
+}
+
+/// Thumbnails on either side of the selected one that the filmstrip keeps,
+/// enough to fill a wide window.
+const FILMSTRIP_CELLS: usize = 15;
+
+/// Moves through the images of the filmstrip with the keyboard and the
+/// strip, and keeps the thumbnails around the selected image loaded.
+struct FilmstripController;
+
+impl Controller<FolderGalleryState, Container<FolderGalleryState>>
+    for FilmstripController
+{
+    fn event(
+        &mut self,
+        child: &mut Container<FolderGalleryState>,
+        ctx: &mut druid::EventCtx,
+        event: &Event,
+        data: &mut FolderGalleryState,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(SELECT_IMAGE_SELECTOR) => {
+                data.selected_image = *cmd.get_unchecked(SELECT_IMAGE_SELECTOR);
+            }
+            Event::KeyDown(key) => match key.key {
+                KbKey::ArrowLeft => {
+                    data.previous_image();
+                    ctx.set_handled();
+                }
+                KbKey::ArrowRight => {
+                    data.next_image();
+                    ctx.set_handled();
+                }
+                KbKey::Home => {
+                    data.selected_image = 0;
+                    ctx.set_handled();
+                }
+                KbKey::End => {
+                    data.selected_image = data.paths.len().saturating_sub(1);
+                    ctx.set_handled();
+                }
+                KbKey::Enter if !data.paths.is_empty() => {
+                    ctx.submit_command(Command::new(
+                        PUSH_VIEW_WITH_SELECTED_IMAGE,
+                        (FolderView::SingleImage, data.selected_image),
+                        Target::Auto,
+                    ));
+                    ctx.set_handled();
+                }
+                KbKey::Escape | KbKey::Backspace => {
+                    ctx.submit_command(Command::new(
+                        POP_FOLDER_VIEW,
+                        (),
+                        Target::Auto,
+                    ));
+                    ctx.set_handled();
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+        child.event(ctx, event, data, env);
+
+        // commands reach the views under the shown one as well
+        if data.current_view() == &FolderView::Filmstrip {
+            let start = data.selected_image.saturating_sub(FILMSTRIP_CELLS);
+            let end = (data.selected_image + FILMSTRIP_CELLS + 1)
+                .min(data.images.len());
+            if data.visible_images != (start, end) {
+                data.set_visible_items(start..end);
+            }
+        }
+    }
 }
 
 struct ImageViewController;
@@ -391,6 +890,29 @@ impl Controller<FolderGalleryState, Container<FolderGalleryState>>
                 let index = select_image.get_unchecked(SELECT_IMAGE_SELECTOR);
                 data.selected_image = *index;
             }
+            Event::KeyDown(key) => match key.key {
+                KbKey::ArrowLeft => {
+                    data.previous_image();
+                    ctx.set_handled();
+                }
+                KbKey::ArrowRight => {
+                    data.next_image();
+                    ctx.set_handled();
+                }
+                KbKey::Character(ref c) if c == "i" => {
+                    data.show_info = !data.show_info;
+                    ctx.set_handled();
+                }
+                KbKey::Escape | KbKey::Backspace => {
+                    ctx.submit_command(Command::new(
+                        POP_FOLDER_VIEW,
+                        (),
+                        Target::Auto,
+                    ));
+                    ctx.set_handled();
+                }
+                _ => (),
+            },
             _ => (),
         }
         child.event(ctx, event, data, env);
@@ -0,0 +1,144 @@
+//! Decoded images kept in memory, so moving between the images of a folder
+//! doesn't have to wait on decoding them again.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use druid::{piet::ImageFormat, ImageBuf};
+use once_cell::sync::Lazy;
+
+use crate::{
+    config::ImageCacheSettings,
+    error::GalleryError,
+    metadata::{read_metadata, ImageMetadata},
+    orientation,
+};
+
+static CACHE: Lazy<Mutex<ImageCache>> =
+    Lazy::new(|| Mutex::new(ImageCache::new(&ImageCacheSettings::default())));
+
+/// An image ready to be shown, along with its metadata.
+#[derive(Clone)]
+pub struct CachedImage {
+    pub image: ImageBuf,
+    pub metadata: Arc<ImageMetadata>,
+}
+
+struct Entry {
+    image: CachedImage,
+    /// When the image was last used, for evicting the least recently used
+    /// images first.
+    used: u64,
+}
+
+struct ImageCache {
+    entries: HashMap<Arc<PathBuf>, Entry>,
+    /// The bytes of pixels in `entries`.
+    size: usize,
+    budget: usize,
+    prefetch: usize,
+    clock: u64,
+}
+
+impl ImageCache {
+    fn new(settings: &ImageCacheSettings) -> Self {
+        ImageCache {
+            entries: HashMap::new(),
+            size: 0,
+            budget: settings.memory_mb * 1024 * 1024,
+            prefetch: settings.prefetch,
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, path: &Path) -> Option<CachedImage> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(path)?;
+        entry.used = self.clock;
+        Some(entry.image.clone())
+    }
+
+    fn insert(&mut self, path: Arc<PathBuf>, image: CachedImage) {
+        let size = image_size(&image.image);
+        // an image over the whole budget would only evict everything else
+        if size > self.budget {
+            return;
+        }
+        self.remove(&path);
+        while self.size + size > self.budget {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(path, _)| path.clone());
+            match oldest {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+        self.clock += 1;
+        self.size += size;
+        self.entries.insert(
+            path,
+            Entry {
+                image,
+                used: self.clock,
+            },
+        );
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.size -= image_size(&entry.image.image);
+        }
+    }
+}
+
+fn image_size(image: &ImageBuf) -> usize {
+    image.raw_pixels().len()
+}
+
+/// Sets the memory budget and the prefetch distance from the config.
+pub fn configure(settings: &ImageCacheSettings) {
+    *CACHE.lock().unwrap() = ImageCache::new(settings);
+}
+
+/// How many images before and after the selected one are read ahead.
+pub fn prefetch_distance() -> usize {
+    CACHE.lock().unwrap().prefetch
+}
+
+pub fn get(path: &Path) -> Option<CachedImage> {
+    CACHE.lock().unwrap().get(path)
+}
+
+pub fn contains(path: &Path) -> bool {
+    CACHE.lock().unwrap().entries.contains_key(path)
+}
+
+/// Forgets the image at `path`, after it changed on disk.
+pub fn remove(path: &Path) {
+    CACHE.lock().unwrap().remove(path);
+}
+
+/// Decodes the image at `path` and adds it to the cache.
+pub fn load(path: &Arc<PathBuf>) -> Result<CachedImage, GalleryError> {
+    let image = orientation::open_image(path)
+        .map_err(|err| GalleryError::image(path, err))?;
+    let (width, height) = image.dimensions();
+    let image = ImageBuf::from_raw(
+        image.into_raw(),
+        ImageFormat::Rgb,
+        width as usize,
+        height as usize,
+    );
+    let image = CachedImage {
+        image,
+        metadata: Arc::new(read_metadata(path)),
+    };
+    CACHE.lock().unwrap().insert(path.clone(), image.clone());
+    Ok(image)
+}
@@ -0,0 +1,1584 @@
+//! Rotating and flipping JPEGs without decoding their pixels, and rewriting
+//! their EXIF orientation.
+//!
+//! The transform moves the DCT coefficients of the image around instead, the
+//! same as `jpegtran` does, so nothing is lost to compressing the image
+//! again. Only Huffman coded sequential JPEGs with 8-bit samples can be
+//! transformed, and flipping an axis needs the image to be a whole number of
+//! MCUs along it, since the blocks padding the last MCU would otherwise end
+//! up at the start of the image. Other JPEGs fail with
+//! [`JpegError::Unsupported`] and can be turned by their orientation instead.
+//!
+//! See <https://www.w3.org/Graphics/JPEG/itu-t81.pdf> for the format.
+//!
+//! [`JpegError::Unsupported`]: enum.JpegError.html#variant.Unsupported
+
+use std::{error::Error, fmt, io};
+
+use crate::orientation;
+
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOF0: u8 = 0xc0;
+const SOF1: u8 = 0xc1;
+const DHT: u8 = 0xc4;
+const DAC: u8 = 0xcc;
+const SOS: u8 = 0xda;
+const DQT: u8 = 0xdb;
+const DNL: u8 = 0xdc;
+const DRI: u8 = 0xdd;
+const APP0: u8 = 0xe0;
+const APP1: u8 = 0xe1;
+const APP15: u8 = 0xef;
+const COM: u8 = 0xfe;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const ORIENTATION_TAG: u16 = 0x0112;
+/// The TIFF type of 16-bit unsigned integers.
+const SHORT: u16 = 3;
+
+/// The position in a block, in rows of 8, of each coefficient in the order
+/// they're coded in.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40,
+    48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61,
+    54, 47, 55, 62, 63,
+];
+
+#[derive(Debug)]
+pub enum JpegError {
+    /// The file isn't a JPEG, or it's damaged.
+    Invalid(&'static str),
+    /// The JPEG can be read, but not transformed without decoding it.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for JpegError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JpegError::Invalid(reason) => write!(f, "invalid JPEG: {}", reason),
+            JpegError::Unsupported(reason) => {
+                write!(f, "can't transform this JPEG losslessly: {}", reason)
+            }
+        }
+    }
+}
+
+impl Error for JpegError {}
+
+impl From<JpegError> for io::Error {
+    fn from(err: JpegError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Rotates or flips the JPEG in `data` the way [`orientation::apply`] would
+/// for `orientation`, and returns the new file.
+///
+/// Any EXIF orientation of the file is set to 1, so `orientation` should
+/// already include it for the image to come out upright.
+///
+/// [`orientation::apply`]: ../orientation/fn.apply.html
+pub fn transform(data: &[u8], orientation: u32) -> Result<Vec<u8>, JpegError> {
+    let mut jpeg = read(data)?;
+    let (transpose, flip_x, flip_y) = orientation::axes(orientation);
+    jpeg.frame = jpeg.frame.transform(transpose, flip_x, flip_y)?;
+    if transpose {
+        for table in jpeg.quantization.iter_mut().flatten() {
+            table.values = transpose_block(&table.values);
+        }
+    }
+    if let Some(index) = exif_segment(&jpeg.metadata) {
+        set_exif_orientation(&mut jpeg.metadata[index].1, 1)?;
+    }
+    jpeg.write()
+}
+
+/// Sets the EXIF orientation of the JPEG in `data` to `orientation`, adding
+/// EXIF data if it has none, and returns the new file. The image data itself
+/// is copied as it is.
+pub fn set_orientation(
+    data: &[u8],
+    orientation: u32,
+) -> Result<Vec<u8>, JpegError> {
+    if !data.starts_with(&[0xff, SOI]) {
+        return Err(JpegError::Invalid("missing start of image"));
+    }
+    let mut pos = 2;
+    let mut segments = Vec::new();
+    let start_of_scan = loop {
+        let start = pos;
+        let marker = next_marker(data, &mut pos)?;
+        if marker == SOS || marker == EOI {
+            break start;
+        }
+        segments.push((marker, segment(data, &mut pos)?.to_vec()));
+    };
+
+    let orientation = orientation as u16;
+    match exif_segment(&segments) {
+        Some(index) => {
+            set_exif_orientation(&mut segments[index].1, orientation)?
+        }
+        None => {
+            // the JFIF segment has to stay first
+            let index = segments
+                .iter()
+                .take_while(|(marker, _)| *marker == APP0)
+                .count();
+            segments.insert(index, (APP1, new_exif(orientation)));
+        }
+    }
+
+    let mut out = vec![0xff, SOI];
+    for (marker, contents) in segments.iter() {
+        write_segment(&mut out, *marker, contents)?;
+    }
+    out.extend_from_slice(&data[start_of_scan..]);
+    Ok(out)
+}
+
+/// Finds the marker at `pos`, skipping the fill bytes in front of it, and
+/// moves `pos` past it.
+fn next_marker(data: &[u8], pos: &mut usize) -> Result<u8, JpegError> {
+    if data.get(*pos) != Some(&0xff) {
+        return Err(JpegError::Invalid("expected a marker"));
+    }
+    while data.get(*pos) == Some(&0xff) {
+        *pos += 1;
+    }
+    let marker = *data
+        .get(*pos)
+        .ok_or(JpegError::Invalid("file ends early"))?;
+    *pos += 1;
+    Ok(marker)
+}
+
+/// The contents of the segment whose length is at `pos`, moving `pos` past
+/// them.
+fn segment<'a>(data: &'a [u8], pos: &mut usize) -> Result<&'a [u8], JpegError> {
+    let length = read_u16(data, *pos)? as usize;
+    if length < 2 {
+        return Err(JpegError::Invalid("bad segment length"));
+    }
+    let contents = data
+        .get(*pos + 2..*pos + length)
+        .ok_or(JpegError::Invalid("file ends early"))?;
+    *pos += length;
+    Ok(contents)
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<u16, JpegError> {
+    match data.get(pos..pos + 2) {
+        Some(&[high, low]) => Ok(u16::from_be_bytes([high, low])),
+        _ => Err(JpegError::Invalid("file ends early")),
+    }
+}
+
+fn write_segment(
+    out: &mut Vec<u8>,
+    marker: u8,
+    contents: &[u8],
+) -> Result<(), JpegError> {
+    let length = contents.len() + 2;
+    if length > u16::MAX as usize {
+        return Err(JpegError::Unsupported("segment too large"));
+    }
+    out.extend_from_slice(&[0xff, marker]);
+    out.extend_from_slice(&(length as u16).to_be_bytes());
+    out.extend_from_slice(contents);
+    Ok(())
+}
+
+/// A JPEG read down to the quantized DCT coefficients of its blocks.
+struct Jpeg {
+    /// The APPn and COM segments, which are written back as they are.
+    metadata: Vec<(u8, Vec<u8>)>,
+    quantization: [Option<QuantizationTable>; 4],
+    frame: Frame,
+}
+
+struct QuantizationTable {
+    /// Whether the values are 16-bit rather than 8-bit.
+    wide: bool,
+    /// The values in rows of 8, rather than the order they're written in.
+    values: [u16; 64],
+}
+
+struct Frame {
+    marker: u8,
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+}
+
+struct Component {
+    id: u8,
+    /// The horizontal and vertical sampling factors.
+    h: usize,
+    v: usize,
+    quantization: u8,
+    /// The number of blocks across and down, padded to whole MCUs.
+    blocks_across: usize,
+    blocks_down: usize,
+    /// The coefficients of each block, in rows of 8.
+    blocks: Vec<[i16; 64]>,
+}
+
+impl Frame {
+    fn max_sampling(&self) -> (usize, usize) {
+        let h = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        let v = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        (h, v)
+    }
+
+    /// The number of MCUs across and down.
+    fn mcus(&self) -> (usize, usize) {
+        let (h, v) = self.max_sampling();
+        (
+            (self.width + 8 * h - 1) / (8 * h),
+            (self.height + 8 * v - 1) / (8 * v),
+        )
+    }
+
+    /// The number of blocks of `component` that cover the image, without
+    /// the padding of the last MCUs, which is what a scan of the component
+    /// alone codes.
+    fn blocks_covering(&self, component: &Component) -> (usize, usize) {
+        let (h, v) = self.max_sampling();
+        let width = (self.width * component.h + h - 1) / h;
+        let height = (self.height * component.v + v - 1) / v;
+        ((width + 7) / 8, (height + 7) / 8)
+    }
+
+    /// Mirrors the blocks across the main diagonal if `transpose` is set,
+    /// and then flips them horizontally and vertically.
+    fn transform(
+        &self,
+        transpose: bool,
+        flip_x: bool,
+        flip_y: bool,
+    ) -> Result<Frame, JpegError> {
+        let (h, v) = self.max_sampling();
+        let (flips_width, flips_height) = if transpose {
+            (flip_y, flip_x)
+        } else {
+            (flip_x, flip_y)
+        };
+        if (flips_width && self.width % (8 * h) != 0)
+            || (flips_height && self.height % (8 * v) != 0)
+        {
+            return Err(JpegError::Unsupported(
+                "its size isn't a multiple of the MCU size",
+            ));
+        }
+
+        let components = self
+            .components
+            .iter()
+            .map(|component| {
+                let (across, down) = if transpose {
+                    (component.blocks_down, component.blocks_across)
+                } else {
+                    (component.blocks_across, component.blocks_down)
+                };
+                let mut blocks = Vec::with_capacity(across * down);
+                for y in 0..down {
+                    for x in 0..across {
+                        let x = if flip_x { across - 1 - x } else { x };
+                        let y = if flip_y { down - 1 - y } else { y };
+                        let (x, y) = if transpose { (y, x) } else { (x, y) };
+                        let block =
+                            &component.blocks[y * component.blocks_across + x];
+                        blocks.push(transform_block(
+                            block, transpose, flip_x, flip_y,
+                        ));
+                    }
+                }
+                let (h, v) = if transpose {
+                    (component.v, component.h)
+                } else {
+                    (component.h, component.v)
+                };
+                Component {
+                    h,
+                    v,
+                    blocks_across: across,
+                    blocks_down: down,
+                    blocks,
+                    ..*component
+                }
+            })
+            .collect();
+
+        let (width, height) = if transpose {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        Ok(Frame {
+            marker: self.marker,
+            width,
+            height,
+            components,
+        })
+    }
+}
+
+/// Transforms the coefficients of a block the way `Frame::transform` does
+/// its blocks. Flipping the block negates the coefficients of the odd
+/// frequencies along that axis.
+fn transform_block(
+    block: &[i16; 64],
+    transpose: bool,
+    flip_x: bool,
+    flip_y: bool,
+) -> [i16; 64] {
+    let mut out = if transpose {
+        transpose_block(block)
+    } else {
+        *block
+    };
+    for v in 0..8 {
+        for u in 0..8 {
+            let negate = (flip_x && u % 2 == 1) != (flip_y && v % 2 == 1);
+            if negate {
+                out[v * 8 + u] = -out[v * 8 + u];
+            }
+        }
+    }
+    out
+}
+
+fn transpose_block<T: Copy>(block: &[T; 64]) -> [T; 64] {
+    let mut out = *block;
+    for v in 0..8 {
+        for u in 0..8 {
+            out[v * 8 + u] = block[u * 8 + v];
+        }
+    }
+    out
+}
+
+fn read(data: &[u8]) -> Result<Jpeg, JpegError> {
+    if !data.starts_with(&[0xff, SOI]) {
+        return Err(JpegError::Invalid("missing start of image"));
+    }
+    let mut pos = 2;
+    let mut metadata = Vec::new();
+    let mut quantization = [None, None, None, None];
+    let mut huffman: [[Option<HuffmanTable>; 4]; 2] = Default::default();
+    let mut restart_interval = 0;
+    let mut frame: Option<Frame> = None;
+    let mut scanned = false;
+
+    loop {
+        // some encoders leave out the end of image marker
+        if pos >= data.len() && scanned {
+            break;
+        }
+        let marker = next_marker(data, &mut pos)?;
+        if marker == EOI {
+            break;
+        }
+        let contents = segment(data, &mut pos)?;
+        match marker {
+            SOS => {
+                let frame = frame
+                    .as_mut()
+                    .ok_or(JpegError::Invalid("scan before the frame"))?;
+                pos = read_scan(
+                    data,
+                    pos,
+                    contents,
+                    frame,
+                    &huffman,
+                    restart_interval,
+                )?;
+                scanned = true;
+            }
+            SOF0 | SOF1 => {
+                if frame.is_some() {
+                    return Err(JpegError::Unsupported("more than one frame"));
+                }
+                frame = Some(read_frame(marker, contents)?);
+            }
+            0xc2 | 0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+                return Err(JpegError::Unsupported(
+                    "it's progressive, lossless or arithmetic coded",
+                ));
+            }
+            DAC => return Err(JpegError::Unsupported("it's arithmetic coded")),
+            DNL => {
+                return Err(JpegError::Unsupported(
+                    "its height is given after the image data",
+                ))
+            }
+            DHT => read_huffman_tables(contents, &mut huffman)?,
+            DQT => {
+                // tables changed between scans would apply to some
+                // components and not others
+                if scanned {
+                    return Err(JpegError::Unsupported(
+                        "its quantization tables change between scans",
+                    ));
+                }
+                read_quantization_tables(contents, &mut quantization)?;
+            }
+            DRI => restart_interval = read_u16(contents, 0)? as usize,
+            APP0..=APP15 | COM => metadata.push((marker, contents.to_vec())),
+            _ => (),
+        }
+    }
+
+    let frame = frame.ok_or(JpegError::Invalid("missing frame"))?;
+    if !scanned {
+        return Err(JpegError::Invalid("missing image data"));
+    }
+    Ok(Jpeg {
+        metadata,
+        quantization,
+        frame,
+    })
+}
+
+fn read_frame(marker: u8, contents: &[u8]) -> Result<Frame, JpegError> {
+    let header = contents
+        .get(..6)
+        .ok_or(JpegError::Invalid("frame header too short"))?;
+    if header[0] != 8 {
+        return Err(JpegError::Unsupported("it has 12-bit samples"));
+    }
+    let height = u16::from_be_bytes([header[1], header[2]]) as usize;
+    let width = u16::from_be_bytes([header[3], header[4]]) as usize;
+    if width == 0 || height == 0 {
+        return Err(JpegError::Unsupported("its size is missing"));
+    }
+    let count = header[5] as usize;
+    let specs = contents
+        .get(6..6 + 3 * count)
+        .ok_or(JpegError::Invalid("frame header too short"))?;
+
+    let mut components = Vec::with_capacity(count);
+    for spec in specs.chunks_exact(3) {
+        let (h, v) = ((spec[1] >> 4) as usize, (spec[1] & 0xf) as usize);
+        if !(1..=4).contains(&h) || !(1..=4).contains(&v) || spec[2] > 3 {
+            return Err(JpegError::Invalid("bad component"));
+        }
+        components.push(Component {
+            id: spec[0],
+            h,
+            v,
+            quantization: spec[2],
+            blocks_across: 0,
+            blocks_down: 0,
+            blocks: Vec::new(),
+        });
+    }
+    if components.is_empty() {
+        return Err(JpegError::Invalid("no components"));
+    }
+
+    let mut frame = Frame {
+        marker,
+        width,
+        height,
+        components,
+    };
+    let (mcus_across, mcus_down) = frame.mcus();
+    for component in frame.components.iter_mut() {
+        component.blocks_across = mcus_across * component.h;
+        component.blocks_down = mcus_down * component.v;
+        component.blocks =
+            vec![[0; 64]; component.blocks_across * component.blocks_down];
+    }
+    Ok(frame)
+}
+
+fn read_quantization_tables(
+    mut contents: &[u8],
+    tables: &mut [Option<QuantizationTable>; 4],
+) -> Result<(), JpegError> {
+    while let Some((&spec, rest)) = contents.split_first() {
+        let wide = spec >> 4 == 1;
+        let id = (spec & 0xf) as usize;
+        let size = if wide { 128 } else { 64 };
+        if spec >> 4 > 1 || id > 3 || rest.len() < size {
+            return Err(JpegError::Invalid("bad quantization table"));
+        }
+        let mut values = [0; 64];
+        for (k, &position) in ZIGZAG.iter().enumerate() {
+            values[position] = if wide {
+                u16::from_be_bytes([rest[2 * k], rest[2 * k + 1]])
+            } else {
+                rest[k] as u16
+            };
+        }
+        tables[id] = Some(QuantizationTable { wide, values });
+        contents = &rest[size..];
+    }
+    Ok(())
+}
+
+fn read_huffman_tables(
+    mut contents: &[u8],
+    tables: &mut [[Option<HuffmanTable>; 4]; 2],
+) -> Result<(), JpegError> {
+    while let Some((&spec, rest)) = contents.split_first() {
+        let class = (spec >> 4) as usize;
+        let id = (spec & 0xf) as usize;
+        if class > 1 || id > 3 || rest.len() < 16 {
+            return Err(JpegError::Invalid("bad Huffman table"));
+        }
+        let mut counts = [0; 16];
+        counts.copy_from_slice(&rest[..16]);
+        let total: usize = counts.iter().map(|&count| count as usize).sum();
+        let values = rest
+            .get(16..16 + total)
+            .ok_or(JpegError::Invalid("bad Huffman table"))?;
+        tables[class][id] = Some(HuffmanTable::new(&counts, values)?);
+        contents = &rest[16 + total..];
+    }
+    Ok(())
+}
+
+/// Decodes the blocks of a scan whose header is `header` and whose image
+/// data starts at `pos`, and returns where the data ends.
+fn read_scan(
+    data: &[u8],
+    pos: usize,
+    header: &[u8],
+    frame: &mut Frame,
+    huffman: &[[Option<HuffmanTable>; 4]; 2],
+    restart_interval: usize,
+) -> Result<usize, JpegError> {
+    let count = *header.first().ok_or(JpegError::Invalid("bad scan"))? as usize;
+    let specs = header
+        .get(1..1 + 2 * count)
+        .ok_or(JpegError::Invalid("bad scan"))?;
+    if header.get(1 + 2 * count..4 + 2 * count) != Some(&[0, 63, 0][..]) {
+        return Err(JpegError::Unsupported("it's progressive"));
+    }
+
+    let mut components = Vec::with_capacity(count);
+    for spec in specs.chunks_exact(2) {
+        let index = frame
+            .components
+            .iter()
+            .position(|component| component.id == spec[0])
+            .ok_or(JpegError::Invalid("scan of a missing component"))?;
+        let table = |class: usize, id: u8| {
+            huffman[class]
+                .get(id as usize)
+                .and_then(Option::as_ref)
+                .ok_or(JpegError::Invalid("missing Huffman table"))
+        };
+        components.push((
+            index,
+            table(0, spec[1] >> 4)?,
+            table(1, spec[1] & 0xf)?,
+        ));
+    }
+
+    // the image data runs up to the next marker other than a restart
+    let mut end = pos;
+    while end + 1 < data.len()
+        && !(data[end] == 0xff
+            && data[end + 1] != 0
+            && !(0xd0..=0xd7).contains(&data[end + 1]))
+    {
+        end += 1;
+    }
+    let end = if end + 1 < data.len() {
+        end
+    } else {
+        data.len()
+    };
+    let mut reader = BitReader::new(&data[pos..end]);
+    let mut predictions = vec![0; components.len()];
+    // the predictions start over after each restart marker
+    let restart = |reader: &mut BitReader,
+                   predictions: &mut Vec<i32>,
+                   unit: usize|
+     -> Result<(), JpegError> {
+        if restart_interval > 0 && unit > 0 && unit % restart_interval == 0 {
+            reader.restart()?;
+            predictions
+                .iter_mut()
+                .for_each(|prediction| *prediction = 0);
+        }
+        Ok(())
+    };
+
+    if let &[(index, dc, ac)] = &components[..] {
+        // a scan of one component codes its blocks one at a time
+        let (across, down) = frame.blocks_covering(&frame.components[index]);
+        let component = &mut frame.components[index];
+        for y in 0..down {
+            for x in 0..across {
+                restart(&mut reader, &mut predictions, y * across + x)?;
+                let block =
+                    &mut component.blocks[y * component.blocks_across + x];
+                decode_block(&mut reader, dc, ac, &mut predictions[0], block)?;
+            }
+        }
+    } else {
+        let (mcus_across, mcus_down) = frame.mcus();
+        for mcu_y in 0..mcus_down {
+            for mcu_x in 0..mcus_across {
+                let mcu = mcu_y * mcus_across + mcu_x;
+                restart(&mut reader, &mut predictions, mcu)?;
+                for (i, &(index, dc, ac)) in components.iter().enumerate() {
+                    let component = &mut frame.components[index];
+                    for v in 0..component.v {
+                        for h in 0..component.h {
+                            let x = mcu_x * component.h + h;
+                            let y = mcu_y * component.v + v;
+                            let block = &mut component.blocks
+                                [y * component.blocks_across + x];
+                            decode_block(
+                                &mut reader,
+                                dc,
+                                ac,
+                                &mut predictions[i],
+                                block,
+                            )?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(end)
+}
+
+fn decode_block(
+    reader: &mut BitReader,
+    dc: &HuffmanTable,
+    ac: &HuffmanTable,
+    prediction: &mut i32,
+    block: &mut [i16; 64],
+) -> Result<(), JpegError> {
+    let size = dc.decode(reader)?;
+    if size > 11 {
+        return Err(JpegError::Invalid("bad DC coefficient"));
+    }
+    *prediction += extend(reader.bits(size)?, size);
+    if *prediction < i16::MIN as i32 || *prediction > i16::MAX as i32 {
+        return Err(JpegError::Invalid("bad DC coefficient"));
+    }
+    block[0] = *prediction as i16;
+
+    let mut k = 1;
+    while k < 64 {
+        let symbol = ac.decode(reader)?;
+        let (run, size) = (symbol >> 4, symbol & 0xf);
+        if size == 0 {
+            // 16 zeros, or the rest of the block
+            if run == 15 {
+                k += 16;
+                continue;
+            }
+            break;
+        }
+        k += run as usize;
+        if k > 63 {
+            return Err(JpegError::Invalid("bad AC coefficient"));
+        }
+        block[ZIGZAG[k]] = extend(reader.bits(size)?, size) as i16;
+        k += 1;
+    }
+    Ok(())
+}
+
+/// The value of the `size` bit magnitude category coded as `bits`.
+fn extend(bits: u32, size: u8) -> i32 {
+    if size == 0 {
+        0
+    } else if bits < 1 << (size - 1) {
+        bits as i32 - (1 << size) + 1
+    } else {
+        bits as i32
+    }
+}
+
+/// The magnitude category of `value` and the bits coding it in that
+/// category.
+fn magnitude(value: i32) -> (u8, u32) {
+    let size = (32 - value.abs().leading_zeros()) as u8;
+    let bits = if value < 0 { value - 1 } else { value };
+    (size, bits as u32 & ((1 << size) - 1))
+}
+
+struct HuffmanTable {
+    /// The largest code of each length, or -1 if there are none.
+    max_code: [i32; 17],
+    /// What to add to a code of each length to get the index of its value.
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8; 16], values: &[u8]) -> Result<Self, JpegError> {
+        let mut max_code = [-1; 17];
+        let mut offset = [0; 17];
+        let mut code = 0;
+        let mut index = 0;
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            if count > 0 {
+                offset[length] = index - code;
+                code += count;
+                index += count;
+                max_code[length] = code - 1;
+            }
+            if code > 1 << length {
+                return Err(JpegError::Invalid("bad Huffman table"));
+            }
+            code <<= 1;
+        }
+        Ok(HuffmanTable {
+            max_code,
+            offset,
+            values: values.to_vec(),
+        })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, JpegError> {
+        let mut code = 0;
+        for length in 1..=16 {
+            code = code << 1 | reader.bit()? as i32;
+            if code <= self.max_code[length] {
+                return Ok(self.values[(code + self.offset[length]) as usize]);
+            }
+        }
+        Err(JpegError::Invalid("bad Huffman code"))
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    byte: u8,
+    /// The bits of `byte` that haven't been read yet.
+    left: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            pos: 0,
+            byte: 0,
+            left: 0,
+        }
+    }
+
+    fn bit(&mut self) -> Result<u32, JpegError> {
+        if self.left == 0 {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or(JpegError::Invalid("image data ends early"))?;
+            self.pos += 1;
+            // a 0xff in the data is followed by a 0 so it isn't mistaken for
+            // a marker
+            if byte == 0xff {
+                if self.data.get(self.pos) != Some(&0) {
+                    return Err(JpegError::Invalid("image data ends early"));
+                }
+                self.pos += 1;
+            }
+            self.byte = byte;
+            self.left = 8;
+        }
+        self.left -= 1;
+        Ok((self.byte >> self.left) as u32 & 1)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u32, JpegError> {
+        let mut bits = 0;
+        for _ in 0..count {
+            bits = bits << 1 | self.bit()?;
+        }
+        Ok(bits)
+    }
+
+    /// Skips the padding before a restart marker and the marker itself.
+    fn restart(&mut self) -> Result<(), JpegError> {
+        self.left = 0;
+        match self.data.get(self.pos..self.pos + 2) {
+            Some(&[0xff, marker]) if (0xd0..=0xd7).contains(&marker) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err(JpegError::Invalid("missing restart marker")),
+        }
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    byte: u8,
+    /// The bits of `byte` that have been written.
+    filled: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u32, count: u8) {
+        for i in (0..count).rev() {
+            self.byte = self.byte << 1 | (bits >> i) as u8 & 1;
+            self.filled += 1;
+            if self.filled == 8 {
+                self.out.push(self.byte);
+                if self.byte == 0xff {
+                    self.out.push(0);
+                }
+                self.byte = 0;
+                self.filled = 0;
+            }
+        }
+    }
+
+    /// Pads the last byte with ones.
+    fn finish(mut self) -> Vec<u8> {
+        if self.filled > 0 {
+            let padding = 8 - self.filled;
+            self.write((1 << padding) - 1, padding);
+        }
+        self.out
+    }
+}
+
+/// A symbol of the Huffman coded data, along with the bits that follow it.
+struct Symbol {
+    ac: bool,
+    value: u8,
+    bits: u32,
+    size: u8,
+}
+
+/// Calls `emit` with the symbols coding `block`, whose DC coefficient is
+/// coded as the difference to `prediction`.
+fn block_symbols(
+    block: &[i16; 64],
+    prediction: &mut i32,
+    mut emit: impl FnMut(Symbol),
+) {
+    let dc = block[0] as i32;
+    let (size, bits) = magnitude(dc - *prediction);
+    *prediction = dc;
+    emit(Symbol {
+        ac: false,
+        value: size,
+        bits,
+        size,
+    });
+
+    let mut run = 0;
+    for &position in ZIGZAG[1..].iter() {
+        let coefficient = block[position];
+        if coefficient == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            emit(Symbol {
+                ac: true,
+                value: 0xf0,
+                bits: 0,
+                size: 0,
+            });
+            run -= 16;
+        }
+        let (size, bits) = magnitude(coefficient as i32);
+        emit(Symbol {
+            ac: true,
+            value: run << 4 | size,
+            bits,
+            size,
+        });
+        run = 0;
+    }
+    if run > 0 {
+        emit(Symbol {
+            ac: true,
+            value: 0,
+            bits: 0,
+            size: 0,
+        });
+    }
+}
+
+/// The code lengths of the optimal Huffman table for symbols occurring
+/// `frequencies` times, limited to 16 bits, as the counts of codes of each
+/// length and the symbols in order of their codes. This is the procedure of
+/// section K.2 of the specification.
+fn optimal_table(frequencies: &[u32; 256]) -> ([u8; 16], Vec<u8>) {
+    let mut frequencies: Vec<u64> =
+        frequencies.iter().map(|&count| count as u64).collect();
+    // a symbol that is never used takes the code of all ones, which isn't
+    // allowed
+    frequencies.push(1);
+    let mut code_size = vec![0usize; 257];
+    let mut others: Vec<Option<usize>> = vec![None; 257];
+
+    loop {
+        // the least frequent symbol, the largest one on ties, and the next
+        // least frequent one
+        let least = |skip: Option<usize>| {
+            let mut found: Option<usize> = None;
+            for (symbol, &frequency) in frequencies.iter().enumerate() {
+                if frequency > 0
+                    && Some(symbol) != skip
+                    && found.map_or(true, |f| frequency <= frequencies[f])
+                {
+                    found = Some(symbol);
+                }
+            }
+            found
+        };
+        let mut v1 = match least(None) {
+            Some(v1) => v1,
+            None => break,
+        };
+        let mut v2 = match least(Some(v1)) {
+            Some(v2) => v2,
+            None => break,
+        };
+        frequencies[v1] += frequencies[v2];
+        frequencies[v2] = 0;
+        code_size[v1] += 1;
+        while let Some(next) = others[v1] {
+            v1 = next;
+            code_size[v1] += 1;
+        }
+        others[v1] = Some(v2);
+        code_size[v2] += 1;
+        while let Some(next) = others[v2] {
+            v2 = next;
+            code_size[v2] += 1;
+        }
+    }
+
+    let longest = code_size.iter().copied().max().unwrap_or(0).max(16);
+    let mut bits = vec![0usize; longest + 1];
+    for &size in code_size.iter().filter(|&&size| size > 0) {
+        bits[size] += 1;
+    }
+    // codes longer than 16 bits are moved up, taking a shorter code apart
+    // for each pair of them
+    let mut i = longest;
+    while i > 16 {
+        while bits[i] > 0 {
+            let mut j = i - 2;
+            while bits[j] == 0 {
+                j -= 1;
+            }
+            bits[i] -= 2;
+            bits[i - 1] += 1;
+            bits[j + 1] += 2;
+            bits[j] -= 1;
+        }
+        i -= 1;
+    }
+    while bits[i] == 0 {
+        i -= 1;
+    }
+    // the longest code is the reserved one
+    bits[i] -= 1;
+
+    let mut counts = [0; 16];
+    for (length, count) in counts.iter_mut().enumerate() {
+        *count = bits[length + 1] as u8;
+    }
+    // the reserved symbol is left out
+    let mut values = Vec::new();
+    for length in 1..=longest {
+        for (symbol, &size) in code_size[..256].iter().enumerate() {
+            if size == length {
+                values.push(symbol as u8);
+            }
+        }
+    }
+    (counts, values)
+}
+
+/// The code and its length of each symbol of a Huffman table.
+fn huffman_codes(counts: &[u8; 16], values: &[u8]) -> Vec<(u32, u8)> {
+    let mut codes = vec![(0, 0); 256];
+    let mut code = 0;
+    let mut symbols = values.iter();
+    for length in 1..=16 {
+        for _ in 0..counts[length - 1] {
+            if let Some(&symbol) = symbols.next() {
+                codes[symbol as usize] = (code, length as u8);
+            }
+            code += 1;
+        }
+        code <<= 1;
+    }
+    codes
+}
+
+impl Jpeg {
+    /// Calls `visit` with each block in the order a single scan of all the
+    /// components codes them, along with the index of its component.
+    fn for_each_block(&self, mut visit: impl FnMut(usize, &[i16; 64])) {
+        let frame = &self.frame;
+        if let [component] = &frame.components[..] {
+            let (across, down) = frame.blocks_covering(component);
+            for y in 0..down {
+                for x in 0..across {
+                    visit(
+                        0,
+                        &component.blocks[y * component.blocks_across + x],
+                    );
+                }
+            }
+            return;
+        }
+        let (mcus_across, mcus_down) = frame.mcus();
+        for mcu_y in 0..mcus_down {
+            for mcu_x in 0..mcus_across {
+                for (index, component) in frame.components.iter().enumerate() {
+                    for v in 0..component.v {
+                        for h in 0..component.h {
+                            let x = mcu_x * component.h + h;
+                            let y = mcu_y * component.v + v;
+                            visit(
+                                index,
+                                &component.blocks
+                                    [y * component.blocks_across + x],
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes the image as a single scan, with Huffman tables made for it.
+    fn write(&self) -> Result<Vec<u8>, JpegError> {
+        let frame = &self.frame;
+        let blocks_per_mcu: usize =
+            frame.components.iter().map(|c| c.h * c.v).sum();
+        if frame.components.len() > 4
+            || (frame.components.len() > 1 && blocks_per_mcu > 10)
+        {
+            return Err(JpegError::Unsupported(
+                "its components can't be coded in one scan",
+            ));
+        }
+        // the first component, which is usually the luminance, gets tables
+        // of its own and the others share theirs
+        let table = |index: usize| index.min(1);
+
+        let mut frequencies = [[[0u32; 256]; 2]; 2];
+        let mut predictions = vec![0; frame.components.len()];
+        self.for_each_block(|index, block| {
+            block_symbols(block, &mut predictions[index], |symbol| {
+                frequencies[table(index)][symbol.ac as usize]
+                    [symbol.value as usize] += 1;
+            });
+        });
+        let tables: Vec<Vec<_>> = frequencies
+            .iter()
+            .map(|classes| {
+                classes
+                    .iter()
+                    .map(|frequencies| {
+                        if frequencies.iter().all(|&count| count == 0) {
+                            None
+                        } else {
+                            Some(optimal_table(frequencies))
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut writer = BitWriter::default();
+        let codes: Vec<Vec<_>> = tables
+            .iter()
+            .map(|classes| {
+                classes
+                    .iter()
+                    .map(|table| {
+                        table.as_ref().map(|(counts, values)| {
+                            huffman_codes(counts, values)
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut predictions = vec![0; frame.components.len()];
+        self.for_each_block(|index, block| {
+            block_symbols(block, &mut predictions[index], |symbol| {
+                // every symbol was counted, so its table exists
+                if let Some(codes) = &codes[table(index)][symbol.ac as usize] {
+                    let (code, length) = codes[symbol.value as usize];
+                    writer.write(code, length);
+                    writer.write(symbol.bits, symbol.size);
+                }
+            });
+        });
+        let scan = writer.finish();
+
+        let mut out = vec![0xff, SOI];
+        for (marker, contents) in self.metadata.iter() {
+            write_segment(&mut out, *marker, contents)?;
+        }
+
+        let mut contents = Vec::new();
+        for (id, table) in self.quantization.iter().enumerate() {
+            if let Some(table) = table {
+                contents.push((table.wide as u8) << 4 | id as u8);
+                for &position in ZIGZAG.iter() {
+                    let value = table.values[position];
+                    if table.wide {
+                        contents.extend_from_slice(&value.to_be_bytes());
+                    } else {
+                        contents.push(value as u8);
+                    }
+                }
+            }
+        }
+        write_segment(&mut out, DQT, &contents)?;
+
+        let mut contents = vec![8];
+        contents.extend_from_slice(&(frame.height as u16).to_be_bytes());
+        contents.extend_from_slice(&(frame.width as u16).to_be_bytes());
+        contents.push(frame.components.len() as u8);
+        for component in frame.components.iter() {
+            contents.extend_from_slice(&[
+                component.id,
+                (component.h << 4 | component.v) as u8,
+                component.quantization,
+            ]);
+        }
+        write_segment(&mut out, frame.marker, &contents)?;
+
+        let mut contents = Vec::new();
+        for (id, classes) in tables.iter().enumerate() {
+            for (class, table) in classes.iter().enumerate() {
+                if let Some((counts, values)) = table {
+                    contents.push((class << 4 | id) as u8);
+                    contents.extend_from_slice(counts);
+                    contents.extend_from_slice(values);
+                }
+            }
+        }
+        write_segment(&mut out, DHT, &contents)?;
+
+        let mut contents = vec![frame.components.len() as u8];
+        for (index, component) in frame.components.iter().enumerate() {
+            let id = table(index) as u8;
+            contents.extend_from_slice(&[component.id, id << 4 | id]);
+        }
+        contents.extend_from_slice(&[0, 63, 0]);
+        write_segment(&mut out, SOS, &contents)?;
+        out.extend_from_slice(&scan);
+        out.extend_from_slice(&[0xff, EOI]);
+        Ok(out)
+    }
+}
+
+/// The index of the APP1 segment holding the EXIF data, if there is one.
+fn exif_segment(segments: &[(u8, Vec<u8>)]) -> Option<usize> {
+    segments.iter().position(|(marker, contents)| {
+        *marker == APP1 && contents.starts_with(EXIF_HEADER)
+    })
+}
+
+/// The contents of an APP1 segment with EXIF data holding only the
+/// orientation.
+fn new_exif(orientation: u16) -> Vec<u8> {
+    let mut exif = EXIF_HEADER.to_vec();
+    // a big endian TIFF header, with the first IFD right after it
+    exif.extend_from_slice(b"MM\0\x2a\0\0\0\x08");
+    exif.extend_from_slice(&1u16.to_be_bytes());
+    exif.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    exif.extend_from_slice(&SHORT.to_be_bytes());
+    exif.extend_from_slice(&1u32.to_be_bytes());
+    exif.extend_from_slice(&orientation.to_be_bytes());
+    exif.extend_from_slice(&[0, 0]);
+    exif.extend_from_slice(&0u32.to_be_bytes());
+    exif
+}
+
+/// Sets the orientation in the contents of an EXIF APP1 segment. If the
+/// first IFD doesn't have the tag, the IFD is copied to the end with it
+/// added, which leaves everything else where it is.
+fn set_exif_orientation(
+    segment: &mut Vec<u8>,
+    orientation: u16,
+) -> Result<(), JpegError> {
+    let mut tiff = Tiff::new(segment.split_off(EXIF_HEADER.len()))?;
+    let ifd = tiff.u32(4)? as usize;
+    let count = tiff.u16(ifd)? as usize;
+
+    let mut entries = Vec::with_capacity(count + 1);
+    for i in 0..count {
+        let entry = ifd + 2 + 12 * i;
+        let bytes = tiff
+            .data
+            .get(entry..entry + 12)
+            .ok_or(JpegError::Invalid("bad EXIF data"))?
+            .to_vec();
+        if tiff.tag(&bytes) == ORIENTATION_TAG {
+            tiff.set_u16(entry + 2, SHORT)?;
+            tiff.set_u32(entry + 4, 1)?;
+            tiff.set_u16(entry + 8, orientation)?;
+            tiff.set_u16(entry + 10, 0)?;
+            segment.extend_from_slice(&tiff.data);
+            return Ok(());
+        }
+        entries.push(bytes);
+    }
+    let next = tiff.u32(ifd + 2 + 12 * count)?;
+
+    let mut orientation_entry = Tiff {
+        data: vec![0; 12],
+        big_endian: tiff.big_endian,
+    };
+    orientation_entry.set_u16(0, ORIENTATION_TAG)?;
+    orientation_entry.set_u16(2, SHORT)?;
+    orientation_entry.set_u32(4, 1)?;
+    orientation_entry.set_u16(8, orientation)?;
+    // the tags of an IFD are sorted
+    let position = entries
+        .iter()
+        .position(|entry| tiff.tag(entry) > ORIENTATION_TAG)
+        .unwrap_or(entries.len());
+    entries.insert(position, orientation_entry.data);
+
+    // IFDs start on a word boundary
+    if tiff.data.len() % 2 == 1 {
+        tiff.data.push(0);
+    }
+    let new_ifd = tiff.data.len();
+    let end = new_ifd + 2 + 12 * entries.len() + 4;
+    tiff.data.resize(end, 0);
+    tiff.set_u16(new_ifd, entries.len() as u16)?;
+    for (i, entry) in entries.iter().enumerate() {
+        let start = new_ifd + 2 + 12 * i;
+        tiff.data[start..start + 12].copy_from_slice(entry);
+    }
+    tiff.set_u32(end - 4, next)?;
+    tiff.set_u32(4, new_ifd as u32)?;
+    segment.extend_from_slice(&tiff.data);
+    Ok(())
+}
+
+/// The TIFF structure EXIF data is stored in, in either byte order.
+struct Tiff {
+    data: Vec<u8>,
+    big_endian: bool,
+}
+
+impl Tiff {
+    fn new(data: Vec<u8>) -> Result<Self, JpegError> {
+        let big_endian = match data.get(..2) {
+            Some(b"MM") => true,
+            Some(b"II") => false,
+            _ => return Err(JpegError::Invalid("bad EXIF data")),
+        };
+        Ok(Tiff { data, big_endian })
+    }
+
+    fn u16(&self, pos: usize) -> Result<u16, JpegError> {
+        match self.data.get(pos..pos + 2) {
+            Some(&[a, b]) if self.big_endian => Ok(u16::from_be_bytes([a, b])),
+            Some(&[a, b]) => Ok(u16::from_le_bytes([a, b])),
+            _ => Err(JpegError::Invalid("bad EXIF data")),
+        }
+    }
+
+    fn u32(&self, pos: usize) -> Result<u32, JpegError> {
+        match self.data.get(pos..pos + 4) {
+            Some(&[a, b, c, d]) if self.big_endian => {
+                Ok(u32::from_be_bytes([a, b, c, d]))
+            }
+            Some(&[a, b, c, d]) => Ok(u32::from_le_bytes([a, b, c, d])),
+            _ => Err(JpegError::Invalid("bad EXIF data")),
+        }
+    }
+
+    /// The tag of an IFD entry.
+    fn tag(&self, entry: &[u8]) -> u16 {
+        if self.big_endian {
+            u16::from_be_bytes([entry[0], entry[1]])
+        } else {
+            u16::from_le_bytes([entry[0], entry[1]])
+        }
+    }
+
+    fn set_u16(&mut self, pos: usize, value: u16) -> Result<(), JpegError> {
+        let bytes = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.set_bytes(pos, &bytes)
+    }
+
+    fn set_u32(&mut self, pos: usize, value: u32) -> Result<(), JpegError> {
+        let bytes = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.set_bytes(pos, &bytes)
+    }
+
+    /// Writes `bytes` at `pos`, failing if the data ends before they do.
+    fn set_bytes(&mut self, pos: usize, bytes: &[u8]) -> Result<(), JpegError> {
+        self.data
+            .get_mut(pos..pos + bytes.len())
+            .ok_or(JpegError::Invalid("bad EXIF data"))?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{codecs::jpeg::JpegEncoder, ColorType, Rgb, RgbImage};
+
+    use super::*;
+
+    /// How far a decoded pixel of a transformed image can be from the
+    /// original, as the inverse DCT rounds differently along each axis.
+    const TOLERANCE: u8 = 4;
+
+    /// A JPEG of `width` by `height` pixels, with gradients and a bright
+    /// corner so a misplaced block or a wrong flip shows.
+    fn fixture(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            let corner = x < width / 3 && y < height / 3;
+            Rgb([
+                (x * 255 / width) as u8,
+                (y * 255 / height) as u8,
+                if corner { 255 } else { 40 },
+            ])
+        });
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, 90)
+            .encode(&image, width, height, ColorType::Rgb8)
+            .unwrap();
+        data
+    }
+
+    /// JPEGs from another encoder than the one of `image`, which only writes
+    /// RGB without subsampling, of the same picture as `fixture`: one whose
+    /// MCUs fill the image and one whose height ends in a partial MCU.
+    const ENCODED: [(&[u8], &[u8]); 3] = [
+        // 4:2:0, MCUs of 16 by 16 pixels
+        (
+            include_bytes!("../tests/fixtures/jpeg/420-48x32.jpg"),
+            include_bytes!("../tests/fixtures/jpeg/420-48x30.jpg"),
+        ),
+        // 4:2:2, MCUs of 16 by 8 pixels
+        (
+            include_bytes!("../tests/fixtures/jpeg/422-48x32.jpg"),
+            include_bytes!("../tests/fixtures/jpeg/422-48x30.jpg"),
+        ),
+        // grayscale, MCUs of a single block
+        (
+            include_bytes!("../tests/fixtures/jpeg/gray-48x32.jpg"),
+            include_bytes!("../tests/fixtures/jpeg/gray-48x30.jpg"),
+        ),
+    ];
+
+    fn decode(data: &[u8]) -> RgbImage {
+        image::load_from_memory(data).unwrap().to_rgb8()
+    }
+
+    fn assert_close(actual: &RgbImage, expected: &RgbImage, orientation: u32) {
+        assert_eq!(
+            actual.dimensions(),
+            expected.dimensions(),
+            "orientation {}",
+            orientation
+        );
+        for (x, y, pixel) in actual.enumerate_pixels() {
+            let other = expected.get_pixel(x, y);
+            for channel in 0..3 {
+                let difference =
+                    (pixel[channel] as i32 - other[channel] as i32).abs();
+                assert!(
+                    difference <= TOLERANCE as i32,
+                    "orientation {}: {:?} at ({}, {}) should be {:?}",
+                    orientation,
+                    pixel,
+                    x,
+                    y,
+                    other
+                );
+            }
+        }
+    }
+
+    /// Checks that transforming `data` decodes to the same image as
+    /// transforming the decoded original.
+    fn assert_transforms(data: &[u8], orientation: u32) {
+        let transformed = transform(data, orientation).unwrap();
+        let expected = orientation::apply(decode(data), orientation);
+        assert_close(&decode(&transformed), &expected, orientation);
+    }
+
+    /// Codes the JPEG in `data` again with a restart marker every `interval`
+    /// MCUs, which the encoder of `image` doesn't write. Its standard Huffman
+    /// tables code every symbol, so they're kept.
+    fn with_restarts(data: &[u8], interval: u16) -> Vec<u8> {
+        let jpeg = read(data).unwrap();
+        let mut codes = vec![vec![Vec::new(); 2]; 2];
+        let mut out = vec![0xff, SOI];
+        let mut pos = 2;
+        loop {
+            let marker = next_marker(data, &mut pos).unwrap();
+            let contents = segment(data, &mut pos).unwrap();
+            if marker == DHT {
+                let mut rest = contents;
+                while let Some((&spec, tail)) = rest.split_first() {
+                    let mut counts = [0; 16];
+                    counts.copy_from_slice(&tail[..16]);
+                    let total: usize = counts.iter().map(|&c| c as usize).sum();
+                    codes[(spec & 0xf) as usize][(spec >> 4) as usize] =
+                        huffman_codes(&counts, &tail[16..16 + total]);
+                    rest = &tail[16 + total..];
+                }
+            }
+            if marker == SOS {
+                write_segment(&mut out, DRI, &interval.to_be_bytes()).unwrap();
+                write_segment(&mut out, SOS, contents).unwrap();
+                break;
+            }
+            write_segment(&mut out, marker, contents).unwrap();
+        }
+
+        let blocks_per_mcu: usize =
+            jpeg.frame.components.iter().map(|c| c.h * c.v).sum();
+        let mut writer = BitWriter::default();
+        let mut predictions = vec![0; jpeg.frame.components.len()];
+        let mut visited = 0;
+        let mut restarts = 0;
+        jpeg.for_each_block(|index, block| {
+            let mcu = visited / blocks_per_mcu;
+            if visited % blocks_per_mcu == 0
+                && mcu > 0
+                && mcu % interval as usize == 0
+            {
+                out.extend(std::mem::take(&mut writer).finish());
+                out.extend_from_slice(&[0xff, 0xd0 + (restarts % 8) as u8]);
+                restarts += 1;
+                predictions.iter_mut().for_each(|p| *p = 0);
+            }
+            visited += 1;
+            block_symbols(block, &mut predictions[index], |symbol| {
+                let (code, length) = codes[index.min(1)][symbol.ac as usize]
+                    [symbol.value as usize];
+                writer.write(code, length);
+                writer.write(symbol.bits, symbol.size);
+            });
+        });
+        out.extend(writer.finish());
+        out.extend_from_slice(&[0xff, EOI]);
+        out
+    }
+
+    #[test]
+    fn transforms_every_orientation() {
+        let data = fixture(48, 32);
+        for orientation in 1..=8 {
+            assert_transforms(&data, orientation);
+        }
+        for (data, _) in ENCODED.iter() {
+            for orientation in 1..=8 {
+                assert_transforms(data, orientation);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_restart_markers() {
+        let data = with_restarts(&fixture(48, 32), 5);
+        assert_close(&decode(&data), &decode(&fixture(48, 32)), 1);
+        for orientation in 1..=8 {
+            assert_transforms(&data, orientation);
+        }
+    }
+
+    #[test]
+    fn flipping_partial_mcus_is_unsupported() {
+        // only the height ends in a partial MCU
+        assert_partial_mcus(&fixture(48, 30));
+        for (_, data) in ENCODED.iter() {
+            assert_partial_mcus(data);
+        }
+    }
+
+    fn assert_partial_mcus(data: &[u8]) {
+        for orientation in 1..=8 {
+            let (transpose, flip_x, flip_y) = orientation::axes(orientation);
+            let flips_height = if transpose { flip_x } else { flip_y };
+            if flips_height {
+                assert!(
+                    matches!(
+                        transform(data, orientation),
+                        Err(JpegError::Unsupported(_))
+                    ),
+                    "orientation {}",
+                    orientation
+                );
+            } else {
+                assert_transforms(data, orientation);
+            }
+        }
+    }
+
+    #[test]
+    fn sets_the_orientation() {
+        let data = set_orientation(&fixture(16, 16), 6).unwrap();
+        let exif = exif::Reader::new()
+            .read_from_container(&mut io::Cursor::new(&data))
+            .unwrap();
+        let field = exif
+            .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+            .unwrap();
+        assert_eq!(field.value.get_uint(0), Some(6));
+        // the image data is left alone
+        assert_eq!(decode(&data), decode(&fixture(16, 16)));
+
+        let data = set_orientation(&data, 3).unwrap();
+        let exif = exif::Reader::new()
+            .read_from_container(&mut io::Cursor::new(&data))
+            .unwrap();
+        let field = exif
+            .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+            .unwrap();
+        assert_eq!(field.value.get_uint(0), Some(3));
+    }
+
+    #[test]
+    fn truncated_exif_is_invalid() {
+        let data = fixture(16, 16);
+        let mut exif = EXIF_HEADER.to_vec();
+        // an IFD of one entry that ends right after its tag and type
+        exif.extend_from_slice(b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03");
+        let mut truncated = vec![0xff, SOI];
+        write_segment(&mut truncated, APP1, &exif).unwrap();
+        truncated.extend_from_slice(&data[2..]);
+        assert!(matches!(
+            set_orientation(&truncated, 6),
+            Err(JpegError::Invalid(_))
+        ));
+        assert!(matches!(
+            transform(&truncated, 6),
+            Err(JpegError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::UNIX_EPOCH,
+};
+
+use druid::im::{HashMap, HashSet, Vector};
+use log::error;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_data::{AppState, ImageFolder, Thumbnail},
+    search::SavedSearch,
+};
+
+const LIBRARY_DIR: &str = "photo-gallery";
+const LIBRARY_FILE: &str = "library.json";
+
+/// The library waiting to be written by the thread saving it.
+static SAVES: Lazy<Saves> = Lazy::new(Saves::start);
+
+/// The on disk record of every folder that has been added to the gallery.
+///
+/// This is loaded before the window is created so the gallery opens with the
+/// folders of the previous session, and is reconciled against the disk in the
+/// background afterwards.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Library {
+    /// The folders picked with "+ Add Folder".
+    pub roots: Vec<PathBuf>,
+    /// Every folder found under the roots that contains images.
+    pub folders: Vec<LibraryFolder>,
+    /// The searches saved from the main view, in the order they were saved.
+    #[serde(default)]
+    pub searches: Vec<SavedSearch>,
+    /// The albums made in the main view.
+    #[serde(default)]
+    pub albums: Vec<LibraryAlbum>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryFolder {
+    pub path: PathBuf,
+    /// Modification time of the directory when its images were last listed,
+    /// in seconds since the unix epoch.
+    pub modified: u64,
+    pub paths: Vec<PathBuf>,
+}
+
+/// An album, with its images in the order they were arranged in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryAlbum {
+    pub name: String,
+    pub paths: Vec<PathBuf>,
+}
+
+impl Library {
+    /// The location of the library file, which lives in the XDG data dir
+    /// on Linux and the platform equivalent elsewhere.
+    pub fn location() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join(LIBRARY_DIR).join(LIBRARY_FILE))
+    }
+
+    /// Loads the library, falling back to an empty one if it doesn't exist
+    /// yet or can't be read.
+    pub fn load() -> Self {
+        let path = match Self::location() {
+            Some(path) => path,
+            None => return Library::default(),
+        };
+        if !path.exists() {
+            return Library::default();
+        }
+
+        let library = File::open(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                serde_json::from_reader(BufReader::new(file))
+                    .map_err(|err| err.to_string())
+            });
+        match library {
+            Ok(library) => library,
+            Err(err) => {
+                error!("Error reading library {}: {}", path.display(), err);
+                Library::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = match Self::location() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // write to a temporary file first so a crash while saving doesn't
+        // leave behind a truncated library
+        let temp_path = path.with_extension("json.tmp");
+        let file = File::create(&temp_path)?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        fs::rename(temp_path, path)
+    }
+
+    pub fn from_state(state: &AppState) -> Self {
+        let mut roots: Vec<PathBuf> = state
+            .roots
+            .iter()
+            .map(|root| root.as_ref().clone())
+            .collect();
+        roots.sort();
+
+        let folders = state
+            .all_images
+            .iter()
+            .filter(|folder| !folder.album)
+            .map(|folder| LibraryFolder {
+                path: folder.name.as_ref().clone(),
+                modified: folder.modified,
+                paths: folder
+                    .paths
+                    .iter()
+                    .map(|path| path.as_ref().clone())
+                    .collect(),
+            })
+            .collect();
+
+        let albums = state
+            .all_images
+            .iter()
+            .filter(|folder| folder.album)
+            .map(|album| LibraryAlbum {
+                name: album.name.to_string_lossy().to_string(),
+                paths: album
+                    .paths
+                    .iter()
+                    .map(|path| path.as_ref().clone())
+                    .collect(),
+            })
+            .collect();
+
+        Library {
+            roots,
+            folders,
+            searches: state.saved_searches.iter().cloned().collect(),
+            albums,
+        }
+    }
+
+    pub fn roots(&self) -> HashSet<Arc<PathBuf>> {
+        self.roots
+            .iter()
+            .map(|root| Arc::new(root.clone()))
+            .collect()
+    }
+
+    pub fn folder_paths(&self) -> HashSet<Arc<PathBuf>> {
+        self.folders
+            .iter()
+            .map(|folder| Arc::new(folder.path.clone()))
+            .collect()
+    }
+
+    /// The folders of the library, followed by its albums.
+    pub fn image_folders(&self) -> Vector<ImageFolder> {
+        let albums = self.albums.iter().map(|album| {
+            let paths = album
+                .paths
+                .iter()
+                .map(|path| Arc::new(path.clone()))
+                .collect();
+            ImageFolder::album(&album.name, paths)
+        });
+        self.folders
+            .iter()
+            .map(|folder| {
+                let paths: Vector<Arc<PathBuf>> = folder
+                    .paths
+                    .iter()
+                    .map(|path| Arc::new(path.clone()))
+                    .collect();
+                let thumbnails =
+                    (0..paths.len()).map(Thumbnail::empty).collect();
+                ImageFolder {
+                    name: Arc::new(folder.path.clone()),
+                    folder_thumbnail: Thumbnail::empty(0),
+                    paths,
+                    selected: None,
+                    thumbnails,
+                    modified: folder.modified,
+                    metadata: HashMap::new(),
+                    album: false,
+                }
+            })
+            .chain(albums)
+            .collect()
+    }
+}
+
+/// Returns the modification time of `path` in seconds since the unix epoch,
+/// or 0 if it can't be read.
+pub fn modified_time(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Saves the library of `state` on a thread of its own, so the UI never
+/// waits for the disk. Libraries saved while another one is being written
+/// only have the last of them written after it.
+pub(crate) fn save_library(state: &AppState) {
+    let library = Library::from_state(state);
+    let mut pending = SAVES.lock();
+    pending.next = Some(library);
+    SAVES.changed.notify_all();
+}
+
+/// Waits for the library saved last to be written, so it isn't lost when the
+/// app exits.
+pub(crate) fn wait_for_saves() {
+    let mut pending = SAVES.lock();
+    while pending.next.is_some() || pending.writing {
+        pending = SAVES
+            .changed
+            .wait(pending)
+            .unwrap_or_else(|err| err.into_inner());
+    }
+}
+
+struct Saves {
+    pending: Mutex<PendingSave>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct PendingSave {
+    next: Option<Library>,
+    writing: bool,
+}
+
+impl Saves {
+    fn start() -> Self {
+        thread::spawn(|| write_saves(&SAVES));
+        Saves {
+            pending: Mutex::new(PendingSave::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PendingSave> {
+        self.pending.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn write_saves(saves: &Saves) {
+    loop {
+        let library = {
+            let mut pending = saves.lock();
+            pending.writing = false;
+            saves.changed.notify_all();
+            let library = loop {
+                if let Some(library) = pending.next.take() {
+                    break library;
+                }
+                pending = saves
+                    .changed
+                    .wait(pending)
+                    .unwrap_or_else(|err| err.into_inner());
+            };
+            pending.writing = true;
+            library
+        };
+        if let Err(err) = library.save() {
+            error!("Error saving library: {}", err);
+        }
+    }
+}
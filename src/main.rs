@@ -1,42 +1,160 @@
+use std::{env, process};
+
+use app_commands::{PUSH_VIEW_WITH_SELECTED_IMAGE, START_SLIDESHOW};
 use app_data::AppState;
+use cli::Args;
+use config::Config;
+use delegate::Delegate;
 use druid::{
-    im::{vector, HashSet, Vector},
-    AppLauncher, Widget, WindowDesc,
+    im::{vector, Vector},
+    AppLauncher, Target, Widget, WindowDesc, WindowState,
 };
 
 // use druid_widget_nursery::navigator::{Navigator, View, ViewController};
 use druid_navigator::navigator::Navigator;
-use folder_view::folder_navigator;
+use folder_view::{folder_navigator, FolderView};
+use library::{wait_for_saves, Library};
 use log::error;
-use main_view::{main_view, AppView};
+use main_view::{flatten_and_add_paths, main_view, reconcile_library, AppView};
+use sort::FolderSort;
+use toasts::with_toasts;
+use watcher::LibraryWatcher;
+use workers::CancelToken;
 
 mod app_commands;
 mod app_data;
+mod cli;
+mod config;
+mod delegate;
+mod edits;
+mod error;
 mod folder_view;
+mod image_cache;
+mod jpeg;
+mod library;
 mod main_view;
+mod metadata;
+mod orientation;
+mod search;
+mod sidecar;
+mod sort;
+mod thumbnail_cache;
+mod toasts;
+mod trash;
+mod watcher;
 pub mod widgets;
+mod workers;
 
 fn main() {
-    let window = WindowDesc::new(navigator).title("Gallery");
-
-    match AppLauncher::with_window(window).use_simple_logger().launch(
-        AppState {
-            folder_paths: HashSet::new(),
-            current_image_idx: 0,
-            // this will back the Navigator, so it always has to be initialized with something
-            views: vector![AppView::MainView],
-            all_images: Vector::new(),
-            selected_folder: None,
-        },
-    ) {
+    let args = match Args::parse(env::args_os().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+
+    let config = Config::load();
+    image_cache::configure(&config.image_cache);
+    let library = Library::load();
+    let mut state = AppState {
+        roots: library.roots(),
+        folder_paths: library.folder_paths(),
+        current_image_idx: 0,
+        // this will back the Navigator, so it always has to be initialized with something
+        views: vector![AppView::MainView],
+        all_images: library.image_folders(),
+        selected_folder: None,
+        focused_folder: None,
+        image_formats: config.image_formats(),
+        folder_sort: FolderSort::Name,
+        folder_sort_ascending: true,
+        folder_filter: String::new(),
+        visible_folders: Vector::new(),
+        slideshow: config.slideshow.clone(),
+        jpeg_rotation: config.jpeg_rotation,
+        toasts: Vector::new(),
+        folder_jobs: CancelToken::new(),
+        search_query: String::new(),
+        searching: false,
+        search_jobs: CancelToken::new(),
+        search_results: None,
+        saved_searches: library.searches.iter().cloned().collect(),
+        naming_search: false,
+        search_name: String::new(),
+        naming_album: false,
+        album_name: String::new(),
+        image_view_folder: None,
+        pending_folder: None,
+    };
+    let opened = cli::open(&args, &mut state);
+    state.update_visible_folders();
+
+    let mut window = WindowDesc::new(navigator).title("Gallery");
+    if args.fullscreen {
+        // druid has no fullscreen window state, so this is the same
+        // maximized presentation as the slideshow's
+        window = window
+            .show_titlebar(false)
+            .set_window_state(WindowState::MAXIMIZED);
+    }
+
+    let launcher = AppLauncher::with_window(window).use_simple_logger();
+    let watcher = LibraryWatcher::new(
+        state.roots.iter().map(|root| root.to_path_buf()).collect(),
+        state.image_formats.clone(),
+        launcher.get_external_handle(),
+    );
+    let launcher = launcher.delegate(Delegate::new(watcher));
+    reconcile_library(
+        library,
+        state.image_formats.clone(),
+        launcher.get_external_handle(),
+    );
+    for root in opened.new_roots {
+        flatten_and_add_paths(
+            root,
+            state.folder_paths.clone(),
+            state.image_formats.clone(),
+            launcher.get_external_handle(),
+        );
+    }
+
+    // the views are only built once the app is launched
+    let handle = launcher.get_external_handle();
+    if opened.start_slideshow {
+        handle
+            .submit_command(START_SLIDESHOW, (), Target::Auto)
+            .unwrap();
+    } else if let Some(index) = opened.show_image {
+        handle
+            .submit_command(
+                PUSH_VIEW_WITH_SELECTED_IMAGE,
+                (FolderView::SingleImage, index),
+                Target::Auto,
+            )
+            .unwrap();
+    }
+
+    match launcher.launch(state) {
         Ok(_) => {}
         Err(err) => {
             error!("There was an error launching the application: {}", err);
         }
     }
+    wait_for_saves();
 }
 
 fn navigator() -> impl Widget<AppState> {
-    Navigator::new(AppView::MainView, main_view)
-        .with_view_builder(AppView::FolderView, folder_navigator)
+    // errors can happen in any view, so the toasts are shown around all of
+    // them
+    with_toasts(
+        Navigator::new(AppView::MainView, main_view)
+            .with_view_builder(AppView::FolderView, folder_navigator)
+            .with_view_builder(AppView::ImageView, folder_navigator),
+    )
 }
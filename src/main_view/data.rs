@@ -1,38 +1,80 @@
 use std::{
+    collections::HashMap as StdHashMap,
     fs::{self, read_dir},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
     thread,
 };
 
 use druid::{
     commands::OPEN_FILE,
-    im::{HashSet, Vector},
+    im::{HashMap, HashSet, Vector},
     piet::ImageFormat,
     widget::{Container, Controller},
-    Data, Env, Event, ExtEventSink, ImageBuf, Target, Widget,
+    Command, Data, Env, Event, EventCtx, ExtEventSink, ImageBuf, Target,
+    Widget, WidgetId,
 };
 use druid_gridview::GridIter;
 use druid_navigator::navigator::{View, ViewController};
 use image::{
-    imageops::thumbnail, io::Reader, GenericImageView, ImageError, RgbImage,
-    SubImage,
+    imageops::thumbnail, io::Reader, GenericImageView, RgbImage, SubImage,
 };
 use log::error;
 use walkdir::{DirEntry, WalkDir};
 
 use crate::{
     app_commands::{
-        CREATED_FIRST_IMAGE_THUMBNAIL, FINISHED_READING_ALL_PATHS,
-        FINISHED_READING_FOLDER_IMAGE, POP_VIEW, SELECTED_FOLDER,
+        CANCEL_NEW_ALBUM, CANCEL_SAVE_SEARCH, CONFIRM_NEW_ALBUM,
+        CONFIRM_SAVE_SEARCH, CREATED_FIRST_IMAGE_THUMBNAIL,
+        FINISHED_READING_ALL_PATHS, FINISHED_READING_FOLDER_IMAGE, NEW_ALBUM,
+        OPENED_IMAGE, OPEN_IMAGE, POP_VIEW, PUSH_VIEW_WITH_SELECTED_IMAGE,
+        RECONCILED_FOLDER, REMOVED_FOLDER, REMOVE_SAVED_SEARCH, RUN_SEARCH,
+        SAVE_SEARCH, SEARCH_RESULTS, SELECTED_FOLDER, SHOW_ERROR, TAKE_FOCUS,
     },
-    app_data::{AppState, ImageFolder, Thumbnail},
+    app_data::{AppState, ImageFolder, Thumbnail, ThumbnailState},
+    config::{format_label, ImageFormats},
+    edits,
+    error::{report_error, GalleryError},
+    folder_view::FolderView,
+    library::{modified_time, save_library, Library},
+    search::{search_library, SavedSearch, SearchQuery, SearchResults},
+    sort::{sort_folders, sort_paths, SortMode},
+    thumbnail_cache,
+    widgets::GridSelection,
+    workers::{self, CancelToken, Priority},
 };
 
+impl AppState {
+    /// Sorts and filters the folders shown in the main grid again.
+    pub fn update_visible_folders(&mut self) {
+        let visible = sort_folders(
+            &self.all_images,
+            self.folder_sort,
+            self.folder_sort_ascending,
+            &self.folder_filter,
+        );
+        if visible != self.visible_folders {
+            self.visible_folders = visible;
+        }
+    }
+
+    /// Whether anything the shown folders depend on differs from `old`.
+    fn folders_changed(&self, old: &AppState) -> bool {
+        !self.all_images.same(&old.all_images)
+            || self.folder_sort != old.folder_sort
+            || self.folder_sort_ascending != old.folder_sort_ascending
+            || self.folder_filter != old.folder_filter
+    }
+}
+
+// the cells get the index of their folder in `all_images`, not their position
+// in the grid
 impl GridIter<(ImageFolder, usize)> for AppState {
     fn for_each(&self, mut cb: impl FnMut(&(ImageFolder, usize), usize)) {
-        for (i, image_folder) in self.all_images.iter().enumerate() {
-            cb(&(image_folder.clone(), i), i)
+        for (i, idx) in self.visible_folders.iter().enumerate() {
+            if let Some(image_folder) = self.all_images.get(*idx) {
+                cb(&(image_folder.clone(), *idx), i)
+            }
         }
     }
 
@@ -40,13 +82,15 @@ impl GridIter<(ImageFolder, usize)> for AppState {
         &mut self,
         mut cb: impl FnMut(&mut (ImageFolder, usize), usize),
     ) {
-        for (i, image_folder) in self.all_images.iter_mut().enumerate() {
-            cb(&mut (image_folder.clone(), i), i)
+        for (i, idx) in self.visible_folders.iter().enumerate() {
+            if let Some(image_folder) = self.all_images.get(*idx) {
+                cb(&mut (image_folder.clone(), *idx), i)
+            }
         }
     }
 
     fn data_len(&self) -> usize {
-        self.all_images.len()
+        self.visible_folders.len()
     }
 
     fn child_data(&self) -> Option<(ImageFolder, usize)> {
@@ -55,23 +99,48 @@ impl GridIter<(ImageFolder, usize)> for AppState {
             None => Some((
                 ImageFolder {
                     name: Arc::new(PathBuf::from("".to_owned())),
-                    folder_thumbnail: Thumbnail {
-                        index: 0,
-                        image: ImageBuf::empty(),
-                    },
+                    folder_thumbnail: Thumbnail::empty(0),
                     paths: Vector::new(),
                     selected: None,
                     thumbnails: Vector::new(),
+                    modified: 0,
+                    metadata: HashMap::new(),
+                    album: false,
                 },
                 0,
             )),
         }
     }
 }
+impl GridSelection for AppState {
+    fn item_count(&self) -> usize {
+        self.visible_folders.len()
+    }
+
+    fn selected_item(&self) -> Option<usize> {
+        let focused = self.focused_folder?;
+        self.visible_folders.index_of(&focused)
+    }
+
+    fn select_item(&mut self, position: usize) {
+        self.focused_folder = self.visible_folders.get(position).copied();
+    }
+
+    fn selected_cell(&self) -> Option<u64> {
+        self.selected_item()?;
+        self.focused_folder.map(|idx| idx as u64)
+    }
+
+    fn is_top_view(&self) -> bool {
+        self.views.len() == 1
+    }
+}
+
 #[derive(Debug, Clone, Data, PartialEq, Hash, Eq)]
 pub enum AppView {
     MainView,
-    // this will eventually be used when opening a single file for viewing
+    /// An image opened on its own, from the command line or with "Open File",
+    /// along with the other images of its folder.
     ImageView,
     FolderView,
 }
@@ -99,7 +168,48 @@ impl ViewController<AppView> for AppState {
     }
 }
 
-pub struct MainViewController;
+pub struct MainViewController {
+    /// The text box naming the search to save.
+    pub search_name_box: WidgetId,
+    /// The text box naming a new album.
+    pub album_name_box: WidgetId,
+}
+
+impl MainViewController {
+    fn start_naming_search(&self, ctx: &mut EventCtx, data: &mut AppState) {
+        if data.search_query.trim().is_empty() {
+            return;
+        }
+        data.search_name = data.search_query.trim().to_string();
+        data.naming_search = true;
+        ctx.submit_command(Command::new(
+            TAKE_FOCUS,
+            (),
+            Target::Widget(self.search_name_box),
+        ));
+    }
+
+    fn stop_naming_search(&self, ctx: &mut EventCtx, data: &mut AppState) {
+        data.naming_search = false;
+        // gives the focus back to the grid
+        ctx.submit_command(Command::new(TAKE_FOCUS, (), Target::Auto));
+    }
+
+    fn start_naming_album(&self, ctx: &mut EventCtx, data: &mut AppState) {
+        data.album_name = String::new();
+        data.naming_album = true;
+        ctx.submit_command(Command::new(
+            TAKE_FOCUS,
+            (),
+            Target::Widget(self.album_name_box),
+        ));
+    }
+
+    fn stop_naming_album(&self, ctx: &mut EventCtx, data: &mut AppState) {
+        data.naming_album = false;
+        ctx.submit_command(Command::new(TAKE_FOCUS, (), Target::Auto));
+    }
+}
 
 impl Controller<AppState, Container<AppState>> for MainViewController {
     fn event(
@@ -111,85 +221,203 @@ impl Controller<AppState, Container<AppState>> for MainViewController {
         env: &Env,
     ) {
         match event {
-            Event::Command(selector)
-                if selector.is(FINISHED_READING_FOLDER_IMAGE) =>
-            {
-                let image_folder =
-                    selector.get_unchecked(FINISHED_READING_FOLDER_IMAGE);
-                data.all_images.push_back(image_folder.clone());
-                ctx.request_layout();
-                ctx.request_paint();
-            }
             Event::Command(selector) if selector.is(SELECTED_FOLDER) => {
                 let selected = selector.get_unchecked(SELECTED_FOLDER);
                 data.selected_folder = Some(*selected);
+                data.search_results = None;
+                data.folder_jobs = CancelToken::new();
                 data.add_view(AppView::FolderView);
             }
             Event::Command(selector) if selector.is(POP_VIEW) => {
+                // thumbnails of the folder that was left aren't needed anymore
+                data.folder_jobs.cancel();
+                if data.current_view() == &AppView::ImageView {
+                    data.image_view_folder = None;
+                }
                 data.pop_view();
             }
-            Event::Command(cmd) if cmd.is(FINISHED_READING_ALL_PATHS) => {
-                let current_folders =
-                    cmd.get_unchecked(FINISHED_READING_ALL_PATHS);
-                data.folder_paths = current_folders.clone();
-                let handle = ctx.get_external_handle();
-                let folders = data.all_images.clone();
-                thread::spawn(move || {
-                    for (folder_idx, folder) in folders.iter().enumerate() {
-                        let thumbnail =
-                            create_first_image_thumbnail(folder).unwrap();
-                        handle
-                            .submit_command(
-                                CREATED_FIRST_IMAGE_THUMBNAIL,
-                                (thumbnail, folder_idx),
-                                Target::Auto,
-                            )
-                            .unwrap();
-                    }
-                });
+            Event::Command(cmd) if cmd.is(OPEN_IMAGE) => {
+                let path = cmd.get_unchecked(OPEN_IMAGE).path();
+                read_image_with_folder(
+                    path.to_path_buf(),
+                    data.image_formats.clone(),
+                    ctx.get_external_handle(),
+                );
+            }
+            Event::Command(cmd) if cmd.is(OPENED_IMAGE) => {
+                let folder = cmd.get_unchecked(OPENED_IMAGE).clone();
+                let index = folder.selected.unwrap_or(0);
+                data.folder_jobs = CancelToken::new();
+                data.image_view_folder = Some(folder);
+                data.add_view(AppView::ImageView);
+                // the image view opens on the folder's grid, which shows the
+                // image once it's built
+                ctx.submit_command(
+                    PUSH_VIEW_WITH_SELECTED_IMAGE
+                        .with((FolderView::SingleImage, index)),
+                );
             }
-            Event::Command(cmd) if cmd.is(CREATED_FIRST_IMAGE_THUMBNAIL) => {
-                let (thumbnail, folder_idx) =
-                    cmd.get_unchecked(CREATED_FIRST_IMAGE_THUMBNAIL);
-                data.all_images[*folder_idx].folder_thumbnail =
-                    thumbnail.clone();
+            Event::Command(cmd) if cmd.is(RUN_SEARCH) => {
+                let query = cmd.get_unchecked(RUN_SEARCH).clone();
+                run_search(ctx, data, query);
+            }
+            Event::Command(cmd) if cmd.is(SEARCH_RESULTS) => {
+                let results = cmd.get_unchecked(SEARCH_RESULTS);
+                // results of a search that another one replaced are dropped
+                if results.jobs.same(&data.search_jobs) {
+                    open_search_results(ctx, data, results);
+                }
+            }
+            Event::Command(cmd) if cmd.is(SAVE_SEARCH) => {
+                self.start_naming_search(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(CONFIRM_SAVE_SEARCH) => {
+                self.stop_naming_search(ctx, data);
+                save_search(data);
+            }
+            Event::Command(cmd) if cmd.is(CANCEL_SAVE_SEARCH) => {
+                self.stop_naming_search(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(NEW_ALBUM) => {
+                self.start_naming_album(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(CONFIRM_NEW_ALBUM) => {
+                self.stop_naming_album(ctx, data);
+                create_album(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(CANCEL_NEW_ALBUM) => {
+                self.stop_naming_album(ctx, data);
+            }
+            Event::Command(cmd) if cmd.is(REMOVE_SAVED_SEARCH) => {
+                let name = cmd.get_unchecked(REMOVE_SAVED_SEARCH);
+                data.saved_searches.retain(|search| &search.name != name);
+                save_library(data);
             }
             Event::Command(cmd) if cmd.is(OPEN_FILE) => {
                 let file_info = cmd.get_unchecked(OPEN_FILE);
+                data.roots.insert(Arc::new(file_info.path().to_path_buf()));
                 let handle = ctx.get_external_handle();
                 let folders = data.folder_paths.clone();
                 flatten_and_add_paths(
                     file_info.path().to_path_buf(),
                     folders,
+                    data.image_formats.clone(),
                     handle,
                 );
             }
             _ => {}
         }
-        child.event(ctx, event, data, env)
+
+        // the sort buttons and the filter box change the shown folders, while
+        // changes to the folders themselves are handled by `Delegate`
+        let old_data = data.clone();
+        child.event(ctx, event, data, env);
+        if data.folders_changed(&old_data) {
+            data.update_visible_folders();
+        }
     }
 }
 
-fn flatten_and_add_paths(
+/// Starts searching the library for `query`, cancelling the search that was
+/// running.
+fn run_search(ctx: &mut EventCtx, data: &mut AppState, query: String) {
+    let parsed = match SearchQuery::parse(&query) {
+        Ok(parsed) => parsed,
+        Err(reason) => {
+            let message = format!("Couldn't search for {}: {}", query, reason);
+            ctx.submit_command(SHOW_ERROR.with(message));
+            return;
+        }
+    };
+    data.search_jobs.cancel();
+    data.search_jobs = CancelToken::new();
+    data.search_query = query.clone();
+    data.searching = true;
+    search_library(
+        query,
+        parsed,
+        data.all_images.clone(),
+        data.search_jobs.clone(),
+        ctx.get_external_handle(),
+    );
+}
+
+/// Shows the images found in the folder view, as if they were a folder.
+fn open_search_results(
+    ctx: &mut EventCtx,
+    data: &mut AppState,
+    results: &SearchResults,
+) {
+    if results.paths.is_empty() {
+        let message = format!("No images match {}", results.query);
+        ctx.submit_command(SHOW_ERROR.with(message));
+        return;
+    }
+    data.search_results = Some(results.to_folder(&data.all_images));
+    data.selected_folder = None;
+    data.folder_jobs = CancelToken::new();
+    data.add_view(AppView::FolderView);
+}
+
+/// Saves the current search under the name typed in, replacing a saved
+/// search of the same name.
+fn save_search(data: &mut AppState) {
+    let name = data.search_name.trim();
+    let query = data.search_query.trim();
+    if name.is_empty() || query.is_empty() {
+        return;
+    }
+    let search = SavedSearch {
+        name: name.to_string(),
+        query: query.to_string(),
+    };
+    match data
+        .saved_searches
+        .iter()
+        .position(|saved| saved.name == search.name)
+    {
+        Some(idx) => data.saved_searches[idx] = search,
+        None => data.saved_searches.push_back(search),
+    }
+    save_library(data);
+}
+
+/// Adds an empty album with the name typed in, which has to differ from the
+/// names of the other albums.
+fn create_album(ctx: &mut EventCtx, data: &mut AppState) {
+    let name = data.album_name.trim();
+    if name.is_empty() {
+        return;
+    }
+    let error = if name.contains(std::path::is_separator) {
+        Some("album names can't contain a path separator".to_string())
+    } else if data
+        .all_images
+        .iter()
+        .any(|folder| folder.album && folder.name.as_os_str() == name)
+    {
+        Some(format!("there's already an album named {}", name))
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        let message = format!("Couldn't create the album: {}", error);
+        ctx.submit_command(SHOW_ERROR.with(message));
+        return;
+    }
+    data.all_images
+        .push_back(ImageFolder::album(name, Vector::new()));
+    save_library(data);
+}
+
+pub fn flatten_and_add_paths(
     path: PathBuf,
     mut current_folders: HashSet<Arc<PathBuf>>,
+    formats: ImageFormats,
     handle: ExtEventSink,
 ) {
     thread::spawn(move || {
-        let entries = WalkDir::new(path).into_iter().filter_entry(|entry| {
-            // only walks directories, not files, and only keeps directories
-            // that don't fail to read and are not empty
-            if entry.path().is_dir() {
-                match read_dir(entry.path()) {
-                    Ok(mut dir) => dir.next().is_some(),
-                    Err(_) => false,
-                }
-            } else {
-                false
-            }
-        });
-        for (_i, entry) in entries.enumerate() {
-            let entry = entry.unwrap();
+        for entry in walk_directories(path) {
             let current_folder = entry.path().to_path_buf();
             // checks if this directory has already been added previously
             // mostly dealing with if you add a directory that was the child of another directory
@@ -197,51 +425,228 @@ fn flatten_and_add_paths(
             if current_folders.contains(&current_folder) {
                 continue;
             }
-            let (thumbnails, paths) = check_folder_has_images(&entry);
-            if !thumbnails.is_empty() {
-                current_folders.insert(Arc::new(current_folder.clone()));
-                let image_folder = ImageFolder {
-                    paths,
-                    folder_thumbnail: Thumbnail {
-                        index: 0,
-                        image: ImageBuf::empty(),
-                    },
-                    thumbnails,
-                    name: Arc::new(current_folder),
-                    selected: None,
-                };
-                handle
-                    .submit_command(
+            match read_image_folder(entry.path(), &formats) {
+                Ok(Some(image_folder)) => {
+                    current_folders.insert(Arc::new(current_folder));
+                    // this only fails when the app is closing
+                    let _ = handle.submit_command(
                         FINISHED_READING_FOLDER_IMAGE,
                         image_folder,
                         Target::Auto,
-                    )
-                    .unwrap();
+                    );
+                }
+                Ok(None) => {}
+                Err(err) => report_error(&handle, &err),
             }
         }
-        handle
-            .submit_command(
-                FINISHED_READING_ALL_PATHS,
-                current_folders,
-                Target::Auto,
-            )
-            .unwrap();
+        // this only fails when the app is closing
+        let _ = handle.submit_command(
+            FINISHED_READING_ALL_PATHS,
+            current_folders,
+            Target::Auto,
+        );
     });
 }
 
+/// Lists the folder of the image at `path` on another thread, as every file
+/// of it is opened to find the images, and sends it with `OPENED_IMAGE`.
+pub fn read_image_with_folder(
+    path: PathBuf,
+    formats: ImageFormats,
+    handle: ExtEventSink,
+) {
+    thread::spawn(move || {
+        // this only fails when the app is closing
+        let _ = match image_with_folder(&path, &formats) {
+            Ok(folder) => {
+                handle.submit_command(OPENED_IMAGE, folder, Target::Auto)
+            }
+            Err(err) => handle.submit_command(SHOW_ERROR, err, Target::Auto),
+        };
+    });
+}
+
+/// Checks the library saved by the previous session against the disk.
+///
+/// Only folders whose modification time changed are listed again, and the
+/// roots are walked for new directories without reading the folders that are
+/// already known, so this is much cheaper than adding every root again.
+pub fn reconcile_library(
+    library: Library,
+    formats: ImageFormats,
+    handle: ExtEventSink,
+) {
+    thread::spawn(move || {
+        let mut current_folders = HashSet::new();
+        for folder in library.folders.iter() {
+            let path = Arc::new(folder.path.clone());
+            if !folder.path.is_dir() {
+                // this only fails when the app is closing
+                let _ =
+                    handle.submit_command(REMOVED_FOLDER, path, Target::Auto);
+                continue;
+            }
+            if modified_time(&folder.path) == folder.modified {
+                current_folders.insert(path);
+                continue;
+            }
+            match read_image_folder(&folder.path, &formats) {
+                Ok(Some(image_folder)) => {
+                    current_folders.insert(path);
+                    // this only fails when the app is closing
+                    let _ = handle.submit_command(
+                        RECONCILED_FOLDER,
+                        image_folder,
+                        Target::Auto,
+                    );
+                }
+                Ok(None) => {
+                    // this only fails when the app is closing
+                    let _ = handle.submit_command(
+                        REMOVED_FOLDER,
+                        path,
+                        Target::Auto,
+                    );
+                }
+                Err(err) => {
+                    // the folder is kept as it was, it could only be
+                    // unreadable for now
+                    current_folders.insert(path);
+                    report_error(&handle, &err);
+                }
+            }
+        }
+
+        let known_folders = library.folder_paths();
+        for root in library.roots.iter() {
+            for entry in walk_directories(root.clone()) {
+                let current_folder = entry.path().to_path_buf();
+                if known_folders.contains(&current_folder)
+                    || current_folders.contains(&current_folder)
+                {
+                    continue;
+                }
+                match read_image_folder(entry.path(), &formats) {
+                    Ok(Some(image_folder)) => {
+                        current_folders.insert(Arc::new(current_folder));
+                        // this only fails when the app is closing
+                        let _ = handle.submit_command(
+                            FINISHED_READING_FOLDER_IMAGE,
+                            image_folder,
+                            Target::Auto,
+                        );
+                    }
+                    Ok(None) => {}
+                    Err(err) => report_error(&handle, &err),
+                }
+            }
+        }
+
+        // this only fails when the app is closing
+        let _ = handle.submit_command(
+            FINISHED_READING_ALL_PATHS,
+            current_folders,
+            Target::Auto,
+        );
+    });
+}
+
+pub fn walk_directories(path: PathBuf) -> impl Iterator<Item = DirEntry> {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|entry| {
+            // only walks directories, not files, and only keeps directories
+            // that don't fail to read and are not empty
+            if entry.path().is_dir() {
+                match read_dir(entry.path()) {
+                    Ok(mut dir) => dir.next().is_some(),
+                    Err(_) => false,
+                }
+            } else {
+                false
+            }
+        })
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                error!("Error walking directories: {}", err);
+                None
+            }
+        })
+}
+
+/// Lists the images in the folder of the image at `path`, with that image
+/// selected, so it can be opened on its own in the image view.
+pub fn image_with_folder(
+    path: &Path,
+    formats: &ImageFormats,
+) -> Result<ImageFolder, String> {
+    let not_an_image =
+        || format!("{} isn't an image that can be opened", path.display());
+    let parent = path.parent().ok_or_else(not_an_image)?;
+    let mut folder = read_image_folder(parent, formats)
+        .map_err(|err| err.to_string())?
+        .ok_or_else(not_an_image)?;
+    let index = folder
+        .paths
+        .index_of(&Arc::new(path.to_path_buf()))
+        .ok_or_else(not_an_image)?;
+    folder.selected = Some(index);
+    Ok(folder)
+}
+
+/// Lists the images in `path`, returning `None` if it has none.
+pub fn read_image_folder(
+    path: &Path,
+    formats: &ImageFormats,
+) -> Result<Option<ImageFolder>, GalleryError> {
+    let (thumbnails, paths) = check_folder_has_images(path, formats)?;
+    if thumbnails.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(ImageFolder {
+        paths,
+        folder_thumbnail: Thumbnail::empty(0),
+        thumbnails,
+        name: Arc::new(path.to_path_buf()),
+        selected: None,
+        modified: modified_time(path),
+        metadata: HashMap::new(),
+        album: false,
+    }))
+}
+
 fn check_folder_has_images(
-    entry: &DirEntry,
-) -> (Vector<Thumbnail>, Vector<Arc<PathBuf>>) {
-    let mut images = Vector::new();
-    let mut paths = Vector::new();
-    let entries = fs::read_dir(entry.path()).unwrap();
+    path: &Path,
+    formats: &ImageFormats,
+) -> Result<(Vector<Thumbnail>, Vector<Arc<PathBuf>>), GalleryError> {
+    let mut paths = Vec::new();
+    let mut found_formats = StdHashMap::new();
+    let entries =
+        fs::read_dir(path).map_err(|err| GalleryError::read_dir(path, err))?;
     for file in entries {
-        let file = file.unwrap();
+        // a single entry that can't be read doesn't stop the rest of the
+        // folder from being listed
+        let file = match file {
+            Ok(file) => file,
+            Err(err) => {
+                error!("Error reading an entry of {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        // hidden files are left out, which includes the temporary files
+        // images are written to while they're rotated or exported
+        if file.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
         if file.path().is_file() {
-            match Reader::open(file.path()) {
+            // the format is guessed from the start of the file, as it is when
+            // the image is read, so misnamed images are still found
+            let image = Reader::open(file.path())
+                .and_then(|image| image.with_guessed_format());
+            let format = match image {
                 Ok(image) => match image.format() {
-                    Some(image::ImageFormat::Png)
-                    | Some(image::ImageFormat::Jpeg) => {}
+                    Some(format) if formats.contains(format) => format,
                     Some(_) | None => continue,
                 },
                 Err(err) => {
@@ -249,25 +654,78 @@ fn check_folder_has_images(
                     continue;
                 }
             };
-            images.push_back(Thumbnail {
-                index: images.len(),
-                image: ImageBuf::empty(),
-            });
-            paths.push_back(Arc::new(file.path().to_path_buf()));
+            let path = Arc::new(file.path().to_path_buf());
+            found_formats.insert(path.clone(), format);
+            paths.push(path);
         }
     }
-    (images, paths)
+    // `read_dir` has no particular order, so folders start out sorted by name
+    sort_paths(&mut paths, SortMode::Name, true, &HashMap::new());
+    let images = paths
+        .iter()
+        .enumerate()
+        .map(|(index, path)| Thumbnail {
+            format: found_formats.get(path).copied().and_then(format_label),
+            ..Thumbnail::empty(index)
+        })
+        .collect();
+    Ok((images, paths.into_iter().collect()))
+}
+
+/// Creates the covers of `folders` on the worker pool, sending each with
+/// `CREATED_FIRST_IMAGE_THUMBNAIL`. Covers that can't be created are sent as
+/// failed thumbnails.
+pub fn create_cover_thumbnails(
+    folders: Vec<ImageFolder>,
+    handle: ExtEventSink,
+) {
+    // covers are never cancelled, the main view shows all of them
+    let cancel = CancelToken::new();
+    // albums can be empty, and are left without a cover then
+    for folder in folders
+        .into_iter()
+        .filter(|folder| !folder.paths.is_empty())
+    {
+        let handle = handle.clone();
+        workers::spawn(Priority::Visible, &cancel, move || {
+            let thumbnail = match create_first_image_thumbnail(&folder) {
+                Ok(thumbnail) => thumbnail,
+                Err(err) => {
+                    // the folder itself still opens, so this is only logged
+                    // and the broken image shows up there
+                    error!(
+                        "Error creating the cover of {}: {}",
+                        folder.name.display(),
+                        err
+                    );
+                    Thumbnail::failed(0, &err.reason())
+                }
+            };
+            // this only fails when the app is closing
+            let _ = handle.submit_command(
+                CREATED_FIRST_IMAGE_THUMBNAIL,
+                (thumbnail, folder.name.clone()),
+                Target::Auto,
+            );
+        });
+    }
 }
 
 fn create_first_image_thumbnail(
     folder: &ImageFolder,
-) -> Result<Thumbnail, ImageError> {
+) -> Result<Thumbnail, GalleryError> {
     let image_path = folder.paths[0].clone();
-    let image = Reader::open(image_path.as_ref())?
-        .with_guessed_format()?
-        .decode()?
-        .to_rgb8();
     const THUMBNAIL_HEIGHT_MAX: f64 = 250.;
+    // the cover is a square crop of the cached image, whose long side is
+    // twice the cover's size or a little more, so its short side covers the
+    // cover for images up to about 2:1, and the crops of wider panoramas are
+    // scaled up
+    let image = thumbnail_cache::load_or_create(
+        image_path.as_ref(),
+        (THUMBNAIL_HEIGHT_MAX * 2.) as u32,
+    )
+    .map_err(|err| GalleryError::image(&image_path, err))?;
+    let image = edits::apply_saved(&image_path, image);
     let image = find_largest_square_crop(&image);
     let (width, height) = image.dimensions();
     let (new_width, new_height) = {
@@ -285,7 +743,11 @@ fn create_first_image_thumbnail(
         height as usize,
     );
 
-    Ok(Thumbnail { index: 0, image })
+    Ok(Thumbnail {
+        image,
+        state: ThumbnailState::Loaded,
+        ..Thumbnail::empty(0)
+    })
 }
 
 fn find_largest_square_crop(
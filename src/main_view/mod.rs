@@ -1,5 +1,9 @@
 mod data;
 mod view;
 
-pub use data::{AppView, MainViewController};
+pub use data::{
+    create_cover_thumbnails, flatten_and_add_paths, image_with_folder,
+    read_image_folder, reconcile_library, walk_directories, AppView,
+    MainViewController,
+};
 pub use view::main_view;
@@ -1,19 +1,33 @@
+use std::{path::PathBuf, sync::Arc};
+
 use druid::{
     commands::SHOW_OPEN_PANEL,
     lens,
     widget::{
-        Container, Controller, CrossAxisAlignment, Flex, Image, Label,
-        MainAxisAlignment, Painter,
+        Container, Controller, CrossAxisAlignment, Either, Flex, Image, Label,
+        List, MainAxisAlignment, Painter, SizedBox, TextBox,
     },
-    Color, Command, Cursor, FileDialogOptions, ImageBuf, LensExt,
-    RenderContext, Target, Widget, WidgetExt,
+    Color, Command, ContextMenu, Cursor, Env, Event, EventCtx,
+    FileDialogOptions, ImageBuf, KbKey, LensExt, LocalizedString, MenuDesc,
+    MenuItem, Point, RenderContext, Size, Target, Widget, WidgetExt, WidgetId,
 };
 use druid_gridview::GridView;
 
 use crate::{
-    app_commands::SELECTED_FOLDER,
-    app_data::{AppState, GalleryThumbnailController, ImageFolder},
-    widgets::{Button, Scroll},
+    app_commands::{
+        CANCEL_NEW_ALBUM, CANCEL_SAVE_SEARCH, CONFIRM_NEW_ALBUM,
+        CONFIRM_SAVE_SEARCH, DELETE_ALBUM, NEW_ALBUM, OPEN_IMAGE,
+        REMOVE_SAVED_SEARCH, RUN_SEARCH, SAVE_SEARCH, SELECTED_FOLDER,
+    },
+    app_data::{
+        paint_failed_thumbnail, AppState, GalleryThumbnailController,
+        ImageFolder,
+    },
+    folder_view::edit_bar,
+    search::SavedSearch,
+    widgets::{
+        Button, Foreground, GridNavigator, GridSelection, Scroll, SELECTED_CELL,
+    },
 };
 
 use super::MainViewController;
@@ -33,9 +47,118 @@ pub fn main_view() -> Box<dyn Widget<AppState>> {
     })
     .fix_height(50.);
 
+    let open_file_btn = Button::new(
+        "Open File",
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xdd, 0xdd, 0xdd),
+        Color::rgb8(0x9f, 0x9f, 0x9f),
+        16.,
+    )
+    .on_click(|ctx, _data, _env| {
+        let file_dialog = FileDialogOptions::new()
+            .title("Open File")
+            .button_text("Open")
+            .accept_command(OPEN_IMAGE);
+        ctx.submit_command(SHOW_OPEN_PANEL.with(file_dialog));
+    })
+    .fix_height(50.);
+
+    let new_album_btn = Button::new(
+        "+ New Album",
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xdd, 0xdd, 0xdd),
+        Color::rgb8(0x9f, 0x9f, 0x9f),
+        16.,
+    )
+    .on_click(|ctx, _data, _env| {
+        ctx.submit_command(NEW_ALBUM);
+    })
+    .fix_height(50.);
+
+    let filter_box = TextBox::new()
+        .with_placeholder("Filter folders")
+        .lens(AppState::folder_filter)
+        .fix_width(200.);
+
+    let sort_btn = Button::new(
+        |data: &AppState, _env: &Env| {
+            format!("Sort: {}", data.folder_sort.label())
+        },
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xdd, 0xdd, 0xdd),
+        Color::rgb8(0x9f, 0x9f, 0x9f),
+        16.,
+    )
+    .on_click(|_ctx, data: &mut AppState, _env| {
+        data.folder_sort = data.folder_sort.next();
+    })
+    .fix_height(50.);
+
+    let order_btn = Button::new(
+        |data: &AppState, _env: &Env| {
+            let arrow = if data.folder_sort_ascending {
+                "↑"
+            } else {
+                "↓"
+            };
+            arrow.to_string()
+        },
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xdd, 0xdd, 0xdd),
+        Color::rgb8(0x9f, 0x9f, 0x9f),
+        16.,
+    )
+    .on_click(|_ctx, data: &mut AppState, _env| {
+        data.folder_sort_ascending = !data.folder_sort_ascending;
+    })
+    .fix_height(50.);
+
+    let search_box = TextBox::new()
+        .with_placeholder("Search images, like rating:>=4 date:2025")
+        .controller(SearchBoxController)
+        .lens(AppState::search_query)
+        .fix_width(300.);
+
+    let save_search_btn = Button::new(
+        "Save Search",
+        Color::BLACK,
+        Color::rgb8(0xff, 0xff, 0xff),
+        Color::rgb8(0xdd, 0xdd, 0xdd),
+        Color::rgb8(0x9f, 0x9f, 0x9f),
+        16.,
+    )
+    .on_click(|ctx, _data: &mut AppState, _env| {
+        ctx.submit_command(SAVE_SEARCH);
+    })
+    .fix_height(50.);
+
+    let search_status = Label::dynamic(|data: &AppState, _env| {
+        if data.searching {
+            "Searching…".to_string()
+        } else {
+            String::new()
+        }
+    })
+    .with_text_color(Color::grey8(0x70));
+
     let menu_btns = Container::new(
         Flex::row()
+            .with_spacer(10.)
+            .with_child(search_box)
+            .with_child(save_search_btn)
+            .with_child(search_status)
+            .with_flex_spacer(1.0)
+            .with_child(filter_box)
+            .with_spacer(10.)
+            .with_child(sort_btn)
+            .with_child(order_btn)
+            .with_child(new_album_btn)
             .with_child(add_folder_btn)
+            .with_child(open_file_btn)
             .must_fill_main_axis(true)
             .main_axis_alignment(MainAxisAlignment::End)
             .fix_height(40.),
@@ -46,19 +169,105 @@ pub fn main_view() -> Box<dyn Widget<AppState>> {
         .wrap()
         .with_spacing(30.)
         .padding(20.);
+    let gallery = GridNavigator::new(
+        Scroll::new(gallery_list).vertical(),
+        Size::new(280., 310.),
+    )
+    .with_origin(Point::new(20., 20.))
+    .on_open(|ctx, data: &mut AppState, _env| {
+        // the focused folder can be hidden by the filter
+        if let Some(position) = data.selected_item() {
+            ctx.submit_command(Command::new(
+                SELECTED_FOLDER,
+                data.visible_folders[position],
+                Target::Auto,
+            ));
+        }
+    })
+    .expand_width();
+
+    let search_name_box = WidgetId::next();
+    let search_name_bar = Either::new(
+        |data: &AppState, _env| data.naming_search,
+        edit_bar(
+            "Save search as",
+            "Save",
+            AppState::search_name,
+            search_name_box,
+            CONFIRM_SAVE_SEARCH,
+            CANCEL_SAVE_SEARCH,
+        ),
+        SizedBox::empty(),
+    );
+
+    let album_name_box = WidgetId::next();
+    let album_name_bar = Either::new(
+        |data: &AppState, _env| data.naming_album,
+        edit_bar(
+            "New album",
+            "Create",
+            AppState::album_name,
+            album_name_box,
+            CONFIRM_NEW_ALBUM,
+            CANCEL_NEW_ALBUM,
+        ),
+        SizedBox::empty(),
+    );
+
+    let saved_searches = Either::new(
+        |data: &AppState, _env| data.saved_searches.is_empty(),
+        SizedBox::empty(),
+        List::new(saved_search_builder)
+            .horizontal()
+            .with_spacing(10.)
+            .lens(AppState::saved_searches)
+            .padding((10., 5.)),
+    );
+
+    // the menu is kept out of the scrolled area so it stays visible
     let layout = Flex::column()
         .with_child(menu_btns)
-        .with_child(gallery_list)
+        .with_child(search_name_bar)
+        .with_child(album_name_bar)
+        .with_child(saved_searches)
+        .with_flex_child(gallery, 1.0)
         .cross_axis_alignment(CrossAxisAlignment::Start);
 
-    let container = Container::new(layout).controller(MainViewController);
+    let container = Container::new(layout).controller(MainViewController {
+        search_name_box,
+        album_name_box,
+    });
 
-    Box::new(
-        Scroll::new(container)
-            .vertical()
-            .expand_height()
-            .background(Color::WHITE),
-    )
+    Box::new(container.expand_height().background(Color::WHITE))
+}
+
+/// A saved search, which runs when clicked and can be removed with its ✕.
+fn saved_search_builder() -> impl Widget<SavedSearch> {
+    let button = |text: fn(&SavedSearch) -> String| {
+        Button::new(
+            move |search: &SavedSearch, _env: &Env| text(search),
+            Color::BLACK,
+            Color::rgb8(0xee, 0xee, 0xee),
+            Color::rgb8(0xdd, 0xdd, 0xdd),
+            Color::rgb8(0x9f, 0x9f, 0x9f),
+            14.,
+        )
+        .fix_height(30.)
+    };
+    let open = button(|search| search.name.clone()).on_click(
+        |ctx, search: &mut SavedSearch, _env| {
+            ctx.submit_command(RUN_SEARCH.with(search.query.clone()));
+        },
+    );
+    let remove = button(|_search| "✕".to_string()).on_click(
+        |ctx, search: &mut SavedSearch, _env| {
+            ctx.submit_command(REMOVE_SAVED_SEARCH.with(search.name.clone()));
+        },
+    );
+    Flex::row()
+        .with_child(open)
+        .with_child(remove)
+        .border(Color::rgb8(0xcc, 0xcc, 0xcc), 1.)
 }
 
 fn image_gridview_builder() -> impl Widget<(ImageFolder, usize)> {
@@ -71,27 +280,29 @@ fn image_gridview_builder() -> impl Widget<(ImageFolder, usize)> {
     // this will display the folder name
     let folder_name =
         Label::dynamic(|(folder, _idx): &(ImageFolder, usize), _env| {
-            folder
-                .name
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_string()
+            let name = folder.name.file_name().unwrap().to_string_lossy();
+            if folder.album {
+                format!("Album · {}", name)
+            } else {
+                name.to_string()
+            }
         })
         .with_text_color(Color::BLACK)
         .padding(5.);
 
-    let thumbnail = Image::new(ImageBuf::empty())
-        .controller(GalleryThumbnailController)
+    let thumbnail =
+        Image::new(ImageBuf::empty()).controller(GalleryThumbnailController);
+    let thumbnail = Foreground::new(thumbnail, paint_failed_thumbnail)
         .lens(folder_thumbnail_lens)
         .fix_size(250., 250.);
 
     Flex::column()
         .with_child(folder_name)
         .with_child(thumbnail)
-        .background(Painter::new(|ctx, _data, _env| {
+        .background(Painter::new(|ctx, (_folder, idx), env| {
             let is_hot = ctx.is_hot();
             let is_active = ctx.is_active();
+            let is_focused = env.get(SELECTED_CELL) == *idx as u64;
             let (background_color, border_color, border_width) = if is_active {
                 (
                     Color::rgb8(0x9f, 0x9f, 0x9f),
@@ -104,6 +315,12 @@ fn image_gridview_builder() -> impl Widget<(ImageFolder, usize)> {
                     Color::rgb8(0x2a, 0x82, 0xfc),
                     3.,
                 )
+            } else if is_focused {
+                (
+                    Color::rgb8(0xff, 0xff, 0xff),
+                    Color::rgb8(0x16, 0x69, 0xdd),
+                    3.,
+                )
             } else {
                 (
                     Color::rgb8(0xff, 0xff, 0xff),
@@ -142,6 +359,17 @@ impl Controller<(ImageFolder, usize), Container<(ImageFolder, usize)>>
         if ctx.is_hot() {
             ctx.set_cursor(&Cursor::OpenHand);
         }
+        if let Event::MouseDown(mouse) = event {
+            let (folder, _idx) = data;
+            if mouse.button.is_right() && folder.album {
+                ctx.show_context_menu(ContextMenu::new(
+                    album_menu(folder.name.clone()),
+                    mouse.window_pos,
+                ));
+                ctx.set_handled();
+                return;
+            }
+        }
         child.event(ctx, event, data, env)
     }
 
@@ -167,3 +395,34 @@ impl Controller<(ImageFolder, usize), Container<(ImageFolder, usize)>>
         child.update(ctx, old_data, data, env)
     }
 }
+
+fn album_menu(album: Arc<PathBuf>) -> MenuDesc<AppState> {
+    MenuDesc::empty().append(MenuItem::new(
+        LocalizedString::new("gallery-delete-album")
+            .with_placeholder("Delete Album"),
+        DELETE_ALBUM.with(album),
+    ))
+}
+
+/// Runs the search when Enter is pressed in the search box.
+struct SearchBoxController;
+
+impl<W: Widget<String>> Controller<String, W> for SearchBoxController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut String,
+        env: &Env,
+    ) {
+        if let Event::KeyDown(key) = event {
+            if key.key == KbKey::Enter && !data.trim().is_empty() {
+                ctx.submit_command(RUN_SEARCH.with(data.trim().to_string()));
+                ctx.set_handled();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env)
+    }
+}
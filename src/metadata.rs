@@ -0,0 +1,229 @@
+//! Reading the EXIF and XMP metadata of images.
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use druid::Data;
+use exif::{Exif, In, Tag, Value};
+
+use crate::orientation;
+
+/// XMP packets are embedded near the start of a file, so only this much of it
+/// is searched for one.
+const XMP_SEARCH_LEN: u64 = 512 * 1024;
+
+/// The metadata shown in the info panel of the single image view.
+#[derive(Debug, Clone, Default, Data, PartialEq)]
+pub struct ImageMetadata {
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    pub exposure_time: Option<String>,
+    pub aperture: Option<String>,
+    pub iso: Option<u32>,
+    pub focal_length: Option<String>,
+    /// The capture date as written in EXIF, `YYYY:MM:DD HH:MM:SS`, which also
+    /// sorts chronologically.
+    pub capture_date: Option<String>,
+    pub dimensions: Option<(u32, u32)>,
+    pub file_size: u64,
+    /// Latitude and longitude in degrees.
+    pub gps: Option<(f64, f64)>,
+}
+
+impl ImageMetadata {
+    pub fn dimensions_text(&self) -> Option<String> {
+        self.dimensions
+            .map(|(width, height)| format!("{} × {}", width, height))
+    }
+
+    pub fn file_size_text(&self) -> String {
+        let size = self.file_size as f64;
+        if size >= 1024. * 1024. {
+            format!("{:.1} MB", size / (1024. * 1024.))
+        } else if size >= 1024. {
+            format!("{:.1} KB", size / 1024.)
+        } else {
+            format!("{} B", self.file_size)
+        }
+    }
+
+    pub fn gps_text(&self) -> Option<String> {
+        self.gps.map(|(latitude, longitude)| {
+            format!(
+                "{:.5}° {}, {:.5}° {}",
+                latitude.abs(),
+                if latitude >= 0. { "N" } else { "S" },
+                longitude.abs(),
+                if longitude >= 0. { "E" } else { "W" },
+            )
+        })
+    }
+}
+
+/// Reads the metadata of the image at `path`. Anything that can't be read is
+/// left empty, so this never fails.
+pub fn read_metadata(path: &Path) -> ImageMetadata {
+    let mut metadata = ImageMetadata {
+        file_size: fs::metadata(path).map(|file| file.len()).unwrap_or(0),
+        dimensions: image::image_dimensions(path).ok(),
+        ..ImageMetadata::default()
+    };
+
+    if let Some(exif) = read_exif(path) {
+        let orientation = exif
+            .get_field(Tag::Orientation, In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0))
+            .unwrap_or(1);
+        if orientation::swaps_dimensions(orientation) {
+            metadata.dimensions =
+                metadata.dimensions.map(|(width, height)| (height, width));
+        }
+
+        let make = ascii_field(&exif, Tag::Make);
+        let model = ascii_field(&exif, Tag::Model);
+        metadata.camera = match (make, model) {
+            // models often already start with the make, like "Canon EOS R5"
+            (Some(make), Some(model)) if model.starts_with(&make) => {
+                Some(model)
+            }
+            (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+            (make, model) => model.or(make),
+        };
+        metadata.lens = ascii_field(&exif, Tag::LensModel);
+        metadata.exposure_time = display_field(&exif, Tag::ExposureTime)
+            .map(|time| format!("{} s", time));
+        metadata.aperture = display_field(&exif, Tag::FNumber)
+            .map(|f_number| format!("f/{}", f_number));
+        metadata.iso = exif
+            .get_field(Tag::PhotographicSensitivity, In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0));
+        metadata.focal_length = display_field(&exif, Tag::FocalLength)
+            .map(|length| format!("{} mm", length));
+        metadata.capture_date = ascii_field(&exif, Tag::DateTimeOriginal)
+            .or_else(|| ascii_field(&exif, Tag::DateTime));
+        metadata.gps = read_gps(&exif);
+    }
+
+    // XMP fills in whatever the EXIF data is missing, for example in files
+    // that were exported from an editor that only writes XMP
+    if let Some(packet) = read_xmp_packet(path) {
+        if metadata.camera.is_none() {
+            metadata.camera = xmp_value(&packet, "tiff:Model");
+        }
+        if metadata.lens.is_none() {
+            metadata.lens = xmp_value(&packet, "aux:Lens")
+                .or_else(|| xmp_value(&packet, "exifEX:LensModel"));
+        }
+        if metadata.capture_date.is_none() {
+            metadata.capture_date = xmp_value(&packet, "exif:DateTimeOriginal")
+                .or_else(|| xmp_value(&packet, "xmp:CreateDate"))
+                .map(|date| xmp_date_to_exif(&date));
+        }
+    }
+
+    metadata
+}
+
+pub fn read_exif(path: &Path) -> Option<Exif> {
+    let file = File::open(path).ok()?;
+    exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()
+}
+
+fn ascii_field(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values
+            .iter()
+            .map(|value| String::from_utf8_lossy(value).trim().to_string())
+            .find(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+fn display_field(exif: &Exif, tag: Tag) -> Option<String> {
+    exif.get_field(tag, In::PRIMARY)
+        .map(|field| field.display_value().to_string())
+}
+
+fn read_gps(exif: &Exif) -> Option<(f64, f64)> {
+    let coordinate = |tag: Tag, reference: Tag, negative: &str| {
+        let degrees = match &exif.get_field(tag, In::PRIMARY)?.value {
+            Value::Rational(parts) if parts.len() >= 3 => {
+                parts[0].to_f64()
+                    + parts[1].to_f64() / 60.
+                    + parts[2].to_f64() / 3600.
+            }
+            _ => return None,
+        };
+        match ascii_field(exif, reference) {
+            Some(reference) if reference == negative => Some(-degrees),
+            _ => Some(degrees),
+        }
+    };
+    let latitude = coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, "S")?;
+    let longitude = coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, "W")?;
+    Some((latitude, longitude))
+}
+
+/// Finds the XMP packet embedded in the file at `path`.
+pub fn read_xmp_packet(path: &Path) -> Option<String> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(XMP_SEARCH_LEN)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    let start = find_bytes(&bytes, b"<x:xmpmeta")?;
+    let end = find_bytes(&bytes[start..], b"</x:xmpmeta>")?;
+    let packet = &bytes[start..start + end + b"</x:xmpmeta>".len()];
+    Some(String::from_utf8_lossy(packet).into_owned())
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Returns the value of the XMP property `name`, which can either be written
+/// as an attribute, `name="value"`, or as an element, `<name>value</name>`.
+pub fn xmp_value(packet: &str, name: &str) -> Option<String> {
+    let attribute = format!("{}=\"", name);
+    if let Some(start) = packet.find(&attribute) {
+        let value = &packet[start + attribute.len()..];
+        let end = value.find('"')?;
+        return Some(unescape_xml(&value[..end]));
+    }
+
+    let open_tag = format!("<{}>", name);
+    let close_tag = format!("</{}>", name);
+    let start = packet.find(&open_tag)? + open_tag.len();
+    let end = packet[start..].find(&close_tag)? + start;
+    let value = packet[start..end].trim();
+    if value.is_empty() || value.starts_with('<') {
+        return None;
+    }
+    Some(unescape_xml(value))
+}
+
+pub fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// XMP dates look like `2021-03-04T12:00:00`, EXIF dates like
+/// `2021:03:04 12:00:00`.
+fn xmp_date_to_exif(date: &str) -> String {
+    let date: String = date.chars().take(19).collect();
+    let mut exif_date = date.replacen('-', ":", 2);
+    exif_date = exif_date.replacen('T', " ", 1);
+    exif_date
+}
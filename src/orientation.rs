@@ -0,0 +1,116 @@
+//! Applying the EXIF orientation of images, and combining orientations with
+//! the rotations and flips the user makes.
+
+use std::path::Path;
+
+use exif::{In, Tag};
+use image::{
+    imageops::{
+        flip_horizontal, flip_vertical, rotate180, rotate270, rotate90,
+    },
+    io::Reader,
+    DynamicImage, ImageError, RgbImage,
+};
+
+use crate::metadata::read_exif;
+
+/// Decodes the image at `path` and rotates or flips it upright according to
+/// its EXIF orientation.
+pub fn open_image(path: &Path) -> Result<RgbImage, ImageError> {
+    let image = Reader::open(path)?
+        .with_guessed_format()?
+        .decode()?
+        .to_rgb8();
+    Ok(apply(image, read(path)))
+}
+
+/// Reads the EXIF orientation of the image at `path`, which is 1 when the
+/// image is already upright or has no orientation.
+pub fn read(path: &Path) -> u32 {
+    read_exif(path)
+        .and_then(|exif| {
+            exif.get_field(Tag::Orientation, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+/// Whether `orientation` swaps the width and height of the image.
+pub fn swaps_dimensions(orientation: u32) -> bool {
+    matches!(orientation, 5..=8)
+}
+
+/// Transforms `image` so it's upright, undoing one of the eight EXIF
+/// orientations.
+pub fn apply(image: RgbImage, orientation: u32) -> RgbImage {
+    match orientation {
+        2 => flip_horizontal(&image),
+        3 => rotate180(&image),
+        4 => flip_vertical(&image),
+        // transpose, mirrored across the top left to bottom right diagonal
+        5 => flip_horizontal(&rotate90(&image)),
+        6 => rotate90(&image),
+        // transverse, mirrored across the top right to bottom left diagonal
+        7 => flip_horizontal(&rotate270(&image)),
+        8 => rotate270(&image),
+        _ => image,
+    }
+}
+
+/// Like `apply`, for images decoded in their own color type, so they can be
+/// saved again without losing anything.
+pub fn apply_dynamic(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// The transform `apply` makes for `orientation`, as a matrix mapping the
+/// position of a pixel relative to the center of the image, with y going
+/// down, to where it ends up.
+fn matrix(orientation: u32) -> [[i32; 2]; 2] {
+    match orientation {
+        2 => [[-1, 0], [0, 1]],
+        3 => [[-1, 0], [0, -1]],
+        4 => [[1, 0], [0, -1]],
+        5 => [[0, 1], [1, 0]],
+        6 => [[0, -1], [1, 0]],
+        7 => [[0, -1], [-1, 0]],
+        8 => [[0, 1], [-1, 0]],
+        _ => [[1, 0], [0, 1]],
+    }
+}
+
+/// The orientation that transforms an image the same as applying `first`
+/// and then `then`.
+pub fn compose(first: u32, then: u32) -> u32 {
+    let (a, b) = (matrix(then), matrix(first));
+    let mut product = [[0; 2]; 2];
+    for (row, product_row) in product.iter_mut().enumerate() {
+        for (column, value) in product_row.iter_mut().enumerate() {
+            *value = a[row][0] * b[0][column] + a[row][1] * b[1][column];
+        }
+    }
+    (1..=8)
+        .find(|&orientation| matrix(orientation) == product)
+        .unwrap_or(1)
+}
+
+/// Breaks the transform of `orientation` down into whether it mirrors the
+/// image across the top left to bottom right diagonal, and whether it then
+/// flips it horizontally and vertically.
+pub fn axes(orientation: u32) -> (bool, bool, bool) {
+    let [[a, b], [c, d]] = matrix(orientation);
+    if a == 0 {
+        (true, b < 0, c < 0)
+    } else {
+        (false, a < 0, d < 0)
+    }
+}
@@ -0,0 +1,495 @@
+//! Searching every image in the library.
+//!
+//! A query is a list of terms separated by spaces, all of which an image has
+//! to match. Plain words are looked for in the path of the image, while
+//! `key:value` terms look at its annotations and metadata:
+//!
+//! - `folder:trip` matches images in folders whose path contains `trip`
+//! - `tag:beach` matches images with a tag containing `beach`
+//! - `rating:5`, `rating:>=4` or `rating:2-4` match by stars
+//! - `label:red` and `flag:pick`, `flag:reject` or `flag:none`
+//! - `camera:fuji` and `lens:35mm` match the EXIF camera and lens
+//! - `date:2025`, `date:2025-06` or `date:2024-12..2025-02` match the capture
+//!   date, and `from:2025-03-01` and `to:2025-03-31` bound it on one side
+//! - `width:>=4000` and `height:<1080` match the dimensions
+//!
+//! Values with spaces are quoted, like `tag:"new york"`. Everything is
+//! matched ignoring case.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use druid::{
+    im::{HashMap, Vector},
+    Data, ExtEventSink, Target,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_commands::SEARCH_RESULTS,
+    app_data::{ImageFolder, Thumbnail},
+    metadata::{read_metadata, ImageMetadata},
+    sidecar::{read_annotations, Annotations, ColorLabel, Flag},
+    workers::CancelToken,
+};
+
+/// A search kept in the library, shown as a button above the folders.
+#[derive(Debug, Clone, Data, PartialEq, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: String,
+}
+
+/// The images found by a search, sent with `SEARCH_RESULTS`.
+#[derive(Debug, Clone)]
+pub struct SearchResults {
+    pub query: String,
+    pub paths: Vector<Arc<PathBuf>>,
+    /// Metadata that had to be read for the search, kept so it doesn't have
+    /// to be read again.
+    pub metadata: HashMap<Arc<PathBuf>, Arc<ImageMetadata>>,
+    /// The token of the search, which is cancelled once another one starts.
+    pub jobs: CancelToken,
+}
+
+impl SearchResults {
+    /// The results as a folder, which the folder view can show like any
+    /// other.
+    pub fn to_folder(&self, library: &Vector<ImageFolder>) -> ImageFolder {
+        let mut metadata = HashMap::new();
+        for path in self.paths.iter() {
+            let known = self.metadata.get(path).cloned().or_else(|| {
+                library
+                    .iter()
+                    .find(|folder| Some(folder.name.as_path()) == path.parent())
+                    .and_then(|folder| folder.metadata.get(path).cloned())
+            });
+            if let Some(known) = known {
+                metadata.insert(path.clone(), known);
+            }
+        }
+        let thumbnails = (0..self.paths.len()).map(Thumbnail::empty).collect();
+        ImageFolder {
+            name: Arc::new(PathBuf::from(format!("Search: {}", self.query))),
+            folder_thumbnail: Thumbnail::empty(0),
+            paths: self.paths.clone(),
+            selected: None,
+            thumbnails,
+            modified: 0,
+            metadata,
+            album: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    /// The terms, cheapest to check first.
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    Path(String),
+    Folder(String),
+    Tag(String),
+    Rating(u32, u32),
+    Label(ColorLabel),
+    Flag(Flag),
+    Camera(String),
+    Lens(String),
+    /// The first and last capture dates, as prefixes of EXIF dates like
+    /// `2025:06`.
+    Date(Option<String>, Option<String>),
+    Width(u32, u32),
+    Height(u32, u32),
+}
+
+impl Term {
+    fn parse(term: &str) -> Result<Term, String> {
+        let (key, value) = match term.find(':') {
+            Some(colon) => (&term[..colon], &term[colon + 1..]),
+            None => return Ok(Term::Path(term.to_lowercase())),
+        };
+        let invalid = || format!("\"{}\" isn't a valid {}", value, key);
+        let text = value.to_lowercase();
+        let term = match key.to_lowercase().as_str() {
+            "folder" => Term::Folder(text),
+            "tag" => Term::Tag(text),
+            "rating" | "stars" => {
+                let (min, max) = parse_range(value).ok_or_else(invalid)?;
+                Term::Rating(min, max)
+            }
+            "label" => {
+                Term::Label(ColorLabel::from_name(value).ok_or_else(invalid)?)
+            }
+            "flag" => Term::Flag(match text.as_str() {
+                "pick" | "picked" => Flag::Pick,
+                "reject" | "rejected" => Flag::Reject,
+                "none" | "unflagged" => Flag::Unflagged,
+                _ => return Err(invalid()),
+            }),
+            "camera" => Term::Camera(text),
+            "lens" => Term::Lens(text),
+            "date" => match value.find("..") {
+                // either side of the range can be left open
+                Some(dots) => {
+                    let bound = |date: &str| match date.trim() {
+                        "" => Ok(None),
+                        date => parse_date(date).map(Some).ok_or_else(invalid),
+                    };
+                    Term::Date(
+                        bound(&value[..dots])?,
+                        bound(&value[dots + 2..])?,
+                    )
+                }
+                None => {
+                    let date = parse_date(value).ok_or_else(invalid)?;
+                    Term::Date(Some(date.clone()), Some(date))
+                }
+            },
+            "from" => {
+                let first = parse_date(value).ok_or_else(invalid)?;
+                Term::Date(Some(first), None)
+            }
+            "to" => {
+                let last = parse_date(value).ok_or_else(invalid)?;
+                Term::Date(None, Some(last))
+            }
+            "width" => {
+                let (min, max) = parse_range(value).ok_or_else(invalid)?;
+                Term::Width(min, max)
+            }
+            "height" => {
+                let (min, max) = parse_range(value).ok_or_else(invalid)?;
+                Term::Height(min, max)
+            }
+            // paths can contain colons too, like `C:`
+            _ => Term::Path(term.to_lowercase()),
+        };
+        Ok(term)
+    }
+
+    /// How much has to be read to check the term: nothing, the sidecar or
+    /// the image's metadata.
+    fn cost(&self) -> u8 {
+        match self {
+            Term::Path(_) | Term::Folder(_) => 0,
+            Term::Tag(_)
+            | Term::Rating(..)
+            | Term::Label(_)
+            | Term::Flag(_) => 1,
+            _ => 2,
+        }
+    }
+
+    fn matches(&self, image: &mut Candidate) -> bool {
+        let contains = |value: &Option<String>, text: &str| {
+            value
+                .as_ref()
+                .map_or(false, |value| value.to_lowercase().contains(text))
+        };
+        match self {
+            Term::Path(text) => image
+                .path
+                .to_string_lossy()
+                .to_lowercase()
+                .contains(text.as_str()),
+            Term::Folder(text) => image.path.parent().map_or(false, |folder| {
+                folder
+                    .to_string_lossy()
+                    .to_lowercase()
+                    .contains(text.as_str())
+            }),
+            Term::Tag(text) => image
+                .annotations()
+                .tags
+                .iter()
+                .any(|tag| tag.to_lowercase().contains(text.as_str())),
+            Term::Rating(min, max) => {
+                let rating = image.annotations().rating as u32;
+                *min <= rating && rating <= *max
+            }
+            Term::Label(label) => image.annotations().label == Some(*label),
+            Term::Flag(flag) => image.annotations().flag == *flag,
+            Term::Camera(text) => contains(&image.metadata().camera, text),
+            Term::Lens(text) => contains(&image.metadata().lens, text),
+            Term::Date(first, last) => {
+                let date = match &image.metadata().capture_date {
+                    Some(date) => date.clone(),
+                    None => return false,
+                };
+                // EXIF dates have a fixed width, so comparing as much of the
+                // date as the bound has compares them chronologically
+                let prefix = |bound: &str| {
+                    date.get(..bound.len()).unwrap_or(&date).to_string()
+                };
+                first.as_ref().map_or(true, |first| prefix(first) >= *first)
+                    && last.as_ref().map_or(true, |last| prefix(last) <= *last)
+            }
+            Term::Width(min, max) => match image.metadata().dimensions {
+                Some((width, _)) => *min <= width && width <= *max,
+                None => false,
+            },
+            Term::Height(min, max) => match image.metadata().dimensions {
+                Some((_, height)) => *min <= height && height <= *max,
+                None => false,
+            },
+        }
+    }
+}
+
+/// Parses `4`, `>=4`, `>4`, `<=4`, `<4`, `4+` or `2-4` into the smallest and
+/// largest values they allow.
+fn parse_range(value: &str) -> Option<(u32, u32)> {
+    let value = value.trim();
+    let number = |number: &str| number.trim().parse::<u32>().ok();
+    if let Some(min) = value.strip_prefix(">=") {
+        Some((number(min)?, u32::MAX))
+    } else if let Some(min) = value.strip_prefix('>') {
+        Some((number(min)?.checked_add(1)?, u32::MAX))
+    } else if let Some(max) = value.strip_prefix("<=") {
+        Some((0, number(max)?))
+    } else if let Some(max) = value.strip_prefix('<') {
+        Some((0, number(max)?.checked_sub(1)?))
+    } else if let Some(min) = value.strip_suffix('+') {
+        Some((number(min)?, u32::MAX))
+    } else if let Some(dash) = value.find('-') {
+        Some((number(&value[..dash])?, number(&value[dash + 1..])?))
+    } else {
+        let value = number(value)?;
+        Some((value, value))
+    }
+}
+
+/// Turns `2025`, `2025-06` or `2025-06-14` into the start of an EXIF date,
+/// like `2025:06:14`.
+fn parse_date(date: &str) -> Option<String> {
+    let parts: Vec<&str> =
+        date.trim().split(|c| c == '-' || c == '/').collect();
+    if parts.len() > 3
+        || parts[0].len() != 4
+        || parts[1..]
+            .iter()
+            .any(|part| part.is_empty() || part.len() > 2)
+        || parts.iter().any(|part| part.parse::<u32>().is_err())
+    {
+        return None;
+    }
+    let mut exif_date = parts[0].to_string();
+    for part in &parts[1..] {
+        exif_date.push_str(&format!(":{:0>2}", part));
+    }
+    Some(exif_date)
+}
+
+/// Splits `query` at spaces that aren't in quotes, dropping the quotes.
+fn split_terms(query: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !term.is_empty() {
+                    terms.push(std::mem::take(&mut term));
+                }
+            }
+            c => term.push(c),
+        }
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+    terms
+}
+
+impl SearchQuery {
+    /// Parses `query`, failing with a message for the user if a term has a
+    /// value that doesn't make sense for its key.
+    pub fn parse(query: &str) -> Result<Self, String> {
+        let mut terms = split_terms(query)
+            .iter()
+            .map(|term| Term::parse(term))
+            .collect::<Result<Vec<_>, _>>()?;
+        if terms.is_empty() {
+            return Err("the search is empty".to_string());
+        }
+        terms.sort_by_key(Term::cost);
+        Ok(SearchQuery { terms })
+    }
+}
+
+/// An image being checked against a query, whose sidecar and metadata are
+/// only read if a term needs them.
+struct Candidate<'a> {
+    path: &'a Path,
+    annotations: Option<Annotations>,
+    metadata: Option<Arc<ImageMetadata>>,
+    /// Whether the metadata was read from the file, rather than already
+    /// known.
+    read_metadata: bool,
+}
+
+impl<'a> Candidate<'a> {
+    fn annotations(&mut self) -> &Annotations {
+        let path = self.path;
+        // a sidecar that can't be read counts as no annotations, the folder
+        // view reports it once the folder is opened
+        self.annotations
+            .get_or_insert_with(|| read_annotations(path).unwrap_or_default())
+    }
+
+    fn metadata(&mut self) -> &ImageMetadata {
+        let path = self.path;
+        let read = &mut self.read_metadata;
+        self.metadata.get_or_insert_with(|| {
+            *read = true;
+            Arc::new(read_metadata(path))
+        })
+    }
+}
+
+/// Checks every image of `folders` against `query` on another thread, and
+/// sends the images that match with `SEARCH_RESULTS`, unless `jobs` was
+/// cancelled in the meantime.
+pub fn search_library(
+    query: String,
+    parsed: SearchQuery,
+    folders: Vector<ImageFolder>,
+    jobs: CancelToken,
+    handle: ExtEventSink,
+) {
+    thread::spawn(move || {
+        let mut paths = Vector::new();
+        let mut metadata = HashMap::new();
+        // the images of albums are all in the folders as well
+        for folder in folders.iter().filter(|folder| !folder.album) {
+            for path in folder.paths.iter() {
+                if jobs.is_cancelled() {
+                    return;
+                }
+                let mut image = Candidate {
+                    path,
+                    annotations: None,
+                    metadata: folder.metadata.get(path).cloned(),
+                    read_metadata: false,
+                };
+                if parsed.terms.iter().all(|term| term.matches(&mut image)) {
+                    paths.push_back(path.clone());
+                }
+                if image.read_metadata {
+                    if let Some(read) = image.metadata {
+                        metadata.insert(path.clone(), read);
+                    }
+                }
+            }
+        }
+        let results = SearchResults {
+            query,
+            paths,
+            metadata,
+            jobs,
+        };
+        // this only fails when the app is closing
+        let _ = handle.submit_command(SEARCH_RESULTS, results, Target::Auto);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges() {
+        let max = u32::MAX;
+        let cases = [
+            ("4", Some((4, 4))),
+            (" 4 ", Some((4, 4))),
+            ("007", Some((7, 7))),
+            (">=4", Some((4, max))),
+            (">4", Some((5, max))),
+            (">0", Some((1, max))),
+            ("<=4", Some((0, 4))),
+            ("<4", Some((0, 3))),
+            ("<0", None),
+            ("4+", Some((4, max))),
+            ("2-4", Some((2, 4))),
+            ("2 - 4", Some((2, 4))),
+            ("4294967295", Some((max, max))),
+            (">4294967295", None),
+            ("-4", None),
+            (">", None),
+            ("", None),
+            ("four", None),
+        ];
+        for (value, expected) in cases.iter() {
+            assert_eq!(parse_range(value), *expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn parses_dates() {
+        let cases = [
+            ("2025", Some("2025")),
+            (" 2025 ", Some("2025")),
+            ("2025-06", Some("2025:06")),
+            ("2025-6-4", Some("2025:06:04")),
+            ("2025/06/14", Some("2025:06:14")),
+            ("25", None),
+            ("2025-", None),
+            ("2025-123", None),
+            ("2025-06-14-01", None),
+            ("June", None),
+            ("", None),
+        ];
+        for (date, expected) in cases.iter() {
+            assert_eq!(parse_date(date).as_deref(), *expected, "{:?}", date);
+        }
+    }
+
+    #[test]
+    fn parses_date_ranges() {
+        let cases = [
+            ("date:2025-06", Some((Some("2025:06"), Some("2025:06")))),
+            ("date:2020..2021", Some((Some("2020"), Some("2021")))),
+            ("date:2020..", Some((Some("2020"), None))),
+            ("date:..2021-03", Some((None, Some("2021:03")))),
+            ("from:2020", Some((Some("2020"), None))),
+            ("to:2021", Some((None, Some("2021")))),
+            ("date:2020..then", None),
+            ("date:yesterday", None),
+        ];
+        for (term, expected) in cases.iter() {
+            let range = match Term::parse(term) {
+                Ok(Term::Date(first, last)) => Some((first, last)),
+                _ => None,
+            };
+            let range = range
+                .as_ref()
+                .map(|(first, last)| (first.as_deref(), last.as_deref()));
+            assert_eq!(range, *expected, "{:?}", term);
+        }
+    }
+
+    #[test]
+    fn splits_terms() {
+        let cases: [(&str, &[&str]); 8] = [
+            ("a b", &["a", "b"]),
+            ("  a   b  ", &["a", "b"]),
+            ("\"a b\" c", &["a b", "c"]),
+            ("tag:\"new york\"", &["tag:new york"]),
+            ("a\"b c\"d", &["ab cd"]),
+            ("\"unclosed term", &["unclosed term"]),
+            ("\"\"", &[]),
+            ("", &[]),
+        ];
+        for (query, expected) in cases.iter() {
+            assert_eq!(split_terms(query), *expected, "{:?}", query);
+        }
+    }
+}
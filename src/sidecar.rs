@@ -0,0 +1,409 @@
+//! Star ratings, color labels, pick and reject flags and tags, kept in XMP
+//! sidecar files next to the images.
+//!
+//! Sidecars are named after the whole file name of the image, like
+//! `IMG_0001.jpg.xmp`, as darktable and digiKam do. The rating is written as
+//! `xmp:Rating`, with -1 for rejected images, the color label as `xmp:Label`,
+//! the flag as `digiKam:PickLabel` and the tags as `dc:subject`. Sidecars
+//! that already exist are edited in place, so whatever else other programs
+//! keep in them, like darktable's history, is left alone.
+//!
+//! The edit stack of an image is kept in the same sidecar, as a sequence of
+//! `gallery:edits`, one edit like `exposure 0.5` per item.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use druid::{im::Vector, Color, Data};
+use once_cell::sync::Lazy;
+
+use crate::{
+    edits::{Edit, EditStack},
+    metadata::{unescape_xml, xmp_value},
+};
+
+/// The sidecar of a new image, before any program wrote one.
+const EMPTY_SIDECAR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="">
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+/// Held while a sidecar is read and written again, as rotations turn the saved
+/// edits on the worker pool while the sidecar writer thread writes
+/// annotations and edits, so neither loses the other's change.
+static WRITING: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+const NAMESPACES: [(&str, &str); 3] = [
+    ("xmp", "http://ns.adobe.com/xap/1.0/"),
+    ("dc", "http://purl.org/dc/elements/1.1/"),
+    ("digiKam", "http://www.digikam.org/ns/1.0/"),
+];
+
+const EDITS_NAMESPACE: (&str, &str) =
+    ("gallery", "https://arthmis.github.io/photo-gallery/ns/1.0/");
+
+#[derive(Debug, Clone, Copy, Data, PartialEq, Eq)]
+pub enum ColorLabel {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+impl ColorLabel {
+    pub const ALL: [ColorLabel; 5] = [
+        ColorLabel::Red,
+        ColorLabel::Yellow,
+        ColorLabel::Green,
+        ColorLabel::Blue,
+        ColorLabel::Purple,
+    ];
+
+    /// The name used for it in `xmp:Label`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorLabel::Red => "Red",
+            ColorLabel::Yellow => "Yellow",
+            ColorLabel::Green => "Green",
+            ColorLabel::Blue => "Blue",
+            ColorLabel::Purple => "Purple",
+        }
+    }
+
+    /// The label named `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        ColorLabel::ALL
+            .iter()
+            .copied()
+            .find(|label| label.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            ColorLabel::Red => Color::rgb8(0xe0, 0x3c, 0x31),
+            ColorLabel::Yellow => Color::rgb8(0xf2, 0xc0, 0x1e),
+            ColorLabel::Green => Color::rgb8(0x3c, 0xb0, 0x43),
+            ColorLabel::Blue => Color::rgb8(0x2f, 0x7d, 0xe1),
+            ColorLabel::Purple => Color::rgb8(0x9b, 0x4d, 0xca),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Data, PartialEq, Eq)]
+pub enum Flag {
+    Unflagged,
+    Pick,
+    Reject,
+}
+
+impl Default for Flag {
+    fn default() -> Self {
+        Flag::Unflagged
+    }
+}
+
+#[derive(Debug, Clone, Default, Data, PartialEq)]
+pub struct Annotations {
+    /// From 0 to 5 stars.
+    pub rating: u8,
+    pub label: Option<ColorLabel>,
+    pub flag: Flag,
+    pub tags: Vector<String>,
+}
+
+impl Annotations {
+    /// The rating as stars, like `★★★☆☆`.
+    pub fn stars(&self) -> String {
+        (0..5)
+            .map(|star| if star < self.rating { '★' } else { '☆' })
+            .collect()
+    }
+}
+
+/// Where the sidecar of the image at `path` is.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".xmp");
+    PathBuf::from(sidecar)
+}
+
+/// Whether `path` is a sidecar rather than an image.
+pub fn is_sidecar(path: &Path) -> bool {
+    path.extension().map_or(false, |extension| {
+        extension.to_string_lossy().eq_ignore_ascii_case("xmp")
+    })
+}
+
+/// Reads the annotations of the image at `path`, which has none if it has no
+/// sidecar.
+pub fn read_annotations(path: &Path) -> io::Result<Annotations> {
+    let packet = match read_packet(path)? {
+        Some(packet) => packet,
+        None => return Ok(Annotations::default()),
+    };
+
+    let mut annotations = Annotations::default();
+    match xmp_value(&packet, "xmp:Rating").and_then(|r| r.parse::<i32>().ok()) {
+        Some(rating) if rating < 0 => annotations.flag = Flag::Reject,
+        Some(rating) => annotations.rating = rating.min(5) as u8,
+        None => (),
+    }
+    annotations.label = xmp_value(&packet, "xmp:Label")
+        .and_then(|label| ColorLabel::from_name(&label));
+    match xmp_value(&packet, "digiKam:PickLabel").as_deref() {
+        Some("1") => annotations.flag = Flag::Reject,
+        Some("3") => annotations.flag = Flag::Pick,
+        _ => (),
+    }
+    annotations.tags = read_list(&packet, "dc:subject");
+    Ok(annotations)
+}
+
+/// Reads the edit stack of the image at `path`, which is empty if it has no
+/// sidecar. Edits this version doesn't know are skipped.
+pub fn read_edits(path: &Path) -> io::Result<EditStack> {
+    let packet = match read_packet(path)? {
+        Some(packet) => packet,
+        None => return Ok(EditStack::default()),
+    };
+    let edits = read_list(&packet, "gallery:edits")
+        .iter()
+        .filter_map(|edit| Edit::parse(edit))
+        .collect();
+    Ok(EditStack { edits })
+}
+
+/// Reads the sidecar of the image at `path`, if it has one.
+fn read_packet(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(sidecar_path(path)) {
+        Ok(packet) => Ok(Some(packet)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// The items of the bag or sequence in the element `name`.
+fn read_list(packet: &str, name: &str) -> Vector<String> {
+    let start = match packet.find(&format!("<{}>", name)) {
+        Some(start) => start,
+        None => return Vector::new(),
+    };
+    let end = packet[start..]
+        .find(&format!("</{}>", name))
+        .map_or(packet.len(), |end| start + end);
+    let mut items = Vector::new();
+    let mut rest = &packet[start..end];
+    while let Some(item) = rest.find("<rdf:li") {
+        rest = &rest[item..];
+        let content = match rest.find('>') {
+            Some(close) => &rest[close + 1..],
+            None => break,
+        };
+        let item_end = match content.find("</rdf:li>") {
+            Some(item_end) => item_end,
+            None => break,
+        };
+        let item = unescape_xml(content[..item_end].trim());
+        if !item.is_empty() {
+            items.push_back(item);
+        }
+        rest = &content[item_end..];
+    }
+    items
+}
+
+/// Writes the annotations of the image at `path` to its sidecar, creating
+/// it if there isn't one yet.
+pub fn write_annotations(
+    path: &Path,
+    annotations: &Annotations,
+) -> io::Result<()> {
+    update_sidecar(path, |packet| update_packet(packet, annotations))
+}
+
+/// Writes the edit stack of the image at `path` to its sidecar, creating it
+/// if there isn't one yet.
+pub fn write_edits(path: &Path, edits: &EditStack) -> io::Result<()> {
+    update_sidecar(path, |packet| update_edits(packet, edits))
+}
+
+/// Changes the sidecar of the image at `path` with `update`, starting from
+/// an empty one if there isn't one yet.
+fn update_sidecar(
+    path: &Path,
+    update: impl FnOnce(&str) -> Option<String>,
+) -> io::Result<()> {
+    let _writing = WRITING.lock().unwrap_or_else(|err| err.into_inner());
+    let sidecar = sidecar_path(path);
+    let packet =
+        read_packet(path)?.unwrap_or_else(|| EMPTY_SIDECAR.to_string());
+    let packet = update(&packet).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no rdf:Description", sidecar.display()),
+        )
+    })?;
+    // the sidecar is written next to it first, so a crash or a full disk
+    // never leaves it half written. The name still ends in .xmp, so the
+    // watcher takes it for a sidecar too.
+    let name = sidecar.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = sidecar.with_file_name(format!(".{}.saving.xmp", name));
+    let result = fs::write(&temp_path, packet)
+        .and_then(|_| fs::rename(&temp_path, &sidecar));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Replaces the annotations in `packet`, keeping everything else.
+fn update_packet(packet: &str, annotations: &Annotations) -> Option<String> {
+    let mut packet = packet.to_string();
+    for name in &["xmp:Rating", "xmp:Label", "digiKam:PickLabel"] {
+        remove_property(&mut packet, name);
+    }
+    remove_element(&mut packet, "dc:subject");
+
+    let mut attributes = String::new();
+    for (prefix, uri) in NAMESPACES.iter() {
+        if !packet.contains(&format!("xmlns:{}=", prefix)) {
+            attributes.push_str(&format!(" xmlns:{}=\"{}\"", prefix, uri));
+        }
+    }
+    let rating = match annotations.flag {
+        Flag::Reject => -1,
+        _ => annotations.rating as i32,
+    };
+    attributes.push_str(&format!(" xmp:Rating=\"{}\"", rating));
+    if let Some(label) = annotations.label {
+        attributes.push_str(&format!(" xmp:Label=\"{}\"", label.name()));
+    }
+    match annotations.flag {
+        Flag::Pick => attributes.push_str(" digiKam:PickLabel=\"3\""),
+        Flag::Reject => attributes.push_str(" digiKam:PickLabel=\"1\""),
+        Flag::Unflagged => (),
+    }
+
+    let mut children = String::new();
+    if !annotations.tags.is_empty() {
+        children.push_str(&list_element(
+            "dc:subject",
+            "rdf:Bag",
+            &annotations.tags,
+        ));
+    }
+    add_to_description(&mut packet, &attributes, &children)?;
+    Some(packet)
+}
+
+/// Replaces the edit stack in `packet`, keeping everything else.
+fn update_edits(packet: &str, edits: &EditStack) -> Option<String> {
+    let mut packet = packet.to_string();
+    remove_element(&mut packet, "gallery:edits");
+
+    let (prefix, uri) = EDITS_NAMESPACE;
+    let mut attributes = String::new();
+    if !packet.contains(&format!("xmlns:{}=", prefix)) {
+        attributes.push_str(&format!(" xmlns:{}=\"{}\"", prefix, uri));
+    }
+    let mut children = String::new();
+    if !edits.edits.is_empty() {
+        let items: Vec<String> =
+            edits.edits.iter().map(|edit| edit.to_string()).collect();
+        children.push_str(&list_element("gallery:edits", "rdf:Seq", &items));
+    }
+    add_to_description(&mut packet, &attributes, &children)?;
+    Some(packet)
+}
+
+/// The element `name` holding a bag or sequence of the items.
+fn list_element<'a>(
+    name: &str,
+    list: &str,
+    items: impl IntoIterator<Item = &'a String>,
+) -> String {
+    let mut element = format!("\n   <{}>\n    <{}>\n", name, list);
+    for item in items {
+        element
+            .push_str(&format!("     <rdf:li>{}</rdf:li>\n", escape_xml(item)));
+    }
+    element.push_str(&format!("    </{}>\n   </{}>", list, name));
+    element
+}
+
+/// Adds the attributes and the child elements to the `rdf:Description` of
+/// `packet`, which is `None` if it has none.
+fn add_to_description(
+    packet: &mut String,
+    attributes: &str,
+    children: &str,
+) -> Option<()> {
+    let description = packet.find("<rdf:Description")?;
+    let name_end = description + "<rdf:Description".len();
+    let tag_end = packet[description..].find('>')? + description;
+    let self_closing = packet[..tag_end].ends_with('/');
+
+    if self_closing {
+        packet.replace_range(
+            tag_end - 1..tag_end + 1,
+            &format!(">{}\n  </rdf:Description>", children),
+        );
+    } else {
+        packet.insert_str(tag_end + 1, children);
+    }
+    packet.insert_str(name_end, attributes);
+    Some(())
+}
+
+/// Removes the property `name` from `packet`, whether it's written as an
+/// attribute or as an element.
+fn remove_property(packet: &mut String, name: &str) {
+    let attribute = format!("{}=\"", name);
+    while let Some(start) = packet.find(&attribute) {
+        let value_start = start + attribute.len();
+        let end = match packet[value_start..].find('"') {
+            Some(end) => value_start + end + 1,
+            None => break,
+        };
+        // the whitespace before the attribute goes along with it
+        let start = packet[..start].trim_end().len();
+        packet.replace_range(start..end, "");
+    }
+    remove_element(packet, name);
+}
+
+fn remove_element(packet: &mut String, name: &str) {
+    let open_tag = format!("<{}>", name);
+    let close_tag = format!("</{}>", name);
+    while let Some(start) = packet.find(&open_tag) {
+        let end = match packet[start..].find(&close_tag) {
+            Some(end) => start + end + close_tag.len(),
+            None => break,
+        };
+        let start = packet[..start].trim_end().len();
+        packet.replace_range(start..end, "");
+    }
+    let empty_tag = format!("<{}/>", name);
+    while let Some(start) = packet.find(&empty_tag) {
+        let start = packet[..start].trim_end().len();
+        let end =
+            packet[start..].find(&empty_tag).unwrap() + start + empty_tag.len();
+        packet.replace_range(start..end, "");
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
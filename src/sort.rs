@@ -0,0 +1,297 @@
+//! Ordering of the folders in the main view and of the images in a folder.
+
+use std::{cmp::Ordering, path::PathBuf, sync::Arc};
+
+use druid::{
+    im::{HashMap, Vector},
+    Data,
+};
+
+use crate::{
+    app_data::ImageFolder,
+    library::modified_time,
+    metadata::{read_metadata, ImageMetadata},
+};
+
+#[derive(Debug, Clone, Copy, Data, PartialEq, Eq)]
+pub enum SortMode {
+    /// File names, with runs of digits compared by their value so
+    /// `IMG_2.jpg` comes before `IMG_10.jpg`.
+    Name,
+    Modified,
+    CaptureDate,
+    FileSize,
+    Dimensions,
+}
+
+impl SortMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::Name => "Name",
+            SortMode::Modified => "Modified",
+            SortMode::CaptureDate => "Date taken",
+            SortMode::FileSize => "File size",
+            SortMode::Dimensions => "Dimensions",
+        }
+    }
+
+    /// The mode that follows this one when cycling through them.
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::Name => SortMode::Modified,
+            SortMode::Modified => SortMode::CaptureDate,
+            SortMode::CaptureDate => SortMode::FileSize,
+            SortMode::FileSize => SortMode::Dimensions,
+            SortMode::Dimensions => SortMode::Name,
+        }
+    }
+
+    fn needs_metadata(self) -> bool {
+        matches!(
+            self,
+            SortMode::CaptureDate | SortMode::FileSize | SortMode::Dimensions
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Data, PartialEq, Eq)]
+pub enum FolderSort {
+    Name,
+    Path,
+    ImageCount,
+    /// The most recently modified folders first when descending.
+    Modified,
+}
+
+impl FolderSort {
+    pub fn label(self) -> &'static str {
+        match self {
+            FolderSort::Name => "Name",
+            FolderSort::Path => "Path",
+            FolderSort::ImageCount => "Image count",
+            FolderSort::Modified => "Modified",
+        }
+    }
+
+    /// The sort that follows this one when cycling through them.
+    pub fn next(self) -> Self {
+        match self {
+            FolderSort::Name => FolderSort::Path,
+            FolderSort::Path => FolderSort::ImageCount,
+            FolderSort::ImageCount => FolderSort::Modified,
+            FolderSort::Modified => FolderSort::Name,
+        }
+    }
+}
+
+/// Returns the indices of the folders whose name contains `filter`, ignoring
+/// case, in the order given by `sort`. Albums come before the folders.
+pub fn sort_folders(
+    folders: &Vector<ImageFolder>,
+    sort: FolderSort,
+    ascending: bool,
+    filter: &str,
+) -> Vector<usize> {
+    let filter = filter.trim().to_lowercase();
+    let name = |folder: &ImageFolder| {
+        folder
+            .name
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+
+    let mut indices: Vec<usize> = folders
+        .iter()
+        .enumerate()
+        .filter(|(_, folder)| {
+            filter.is_empty() || name(folder).to_lowercase().contains(&filter)
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+
+    indices.sort_by(|&a, &b| {
+        let (a, b) = (&folders[a], &folders[b]);
+        let ordering = match sort {
+            FolderSort::Name => natural_cmp(&name(a), &name(b)),
+            FolderSort::Path => natural_cmp(
+                &a.name.to_string_lossy(),
+                &b.name.to_string_lossy(),
+            ),
+            FolderSort::ImageCount => a.paths.len().cmp(&b.paths.len()),
+            FolderSort::Modified => a.modified.cmp(&b.modified),
+        }
+        .then_with(|| natural_cmp(&name(a), &name(b)));
+        let ordering = if ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        };
+        b.album.cmp(&a.album).then(ordering)
+    });
+
+    indices.into_iter().collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Number(u64),
+    Date(String),
+}
+
+/// Sorts `paths` by `mode`. Images missing the value being sorted by, such as
+/// photos without a capture date, always come last.
+///
+/// Metadata that isn't in `metadata` yet is read and returned, so it doesn't
+/// have to be read again.
+pub fn sort_paths(
+    paths: &mut Vec<Arc<PathBuf>>,
+    mode: SortMode,
+    ascending: bool,
+    metadata: &HashMap<Arc<PathBuf>, Arc<ImageMetadata>>,
+) -> HashMap<Arc<PathBuf>, Arc<ImageMetadata>> {
+    let mut read = HashMap::new();
+    if mode.needs_metadata() {
+        for path in paths.iter() {
+            if !metadata.contains_key(path) {
+                read.insert(path.clone(), Arc::new(read_metadata(path)));
+            }
+        }
+    }
+
+    let mut keyed: Vec<(Option<SortValue>, String, Arc<PathBuf>)> = paths
+        .drain(..)
+        .map(|path| {
+            let image_metadata =
+                metadata.get(&path).or_else(|| read.get(&path));
+            let value = match mode {
+                SortMode::Name => None,
+                SortMode::Modified => {
+                    Some(SortValue::Number(modified_time(&path)))
+                }
+                SortMode::CaptureDate => image_metadata
+                    .and_then(|metadata| metadata.capture_date.clone())
+                    .map(SortValue::Date),
+                SortMode::FileSize => image_metadata
+                    .map(|metadata| SortValue::Number(metadata.file_size)),
+                SortMode::Dimensions => image_metadata
+                    .and_then(|metadata| metadata.dimensions)
+                    .map(|(width, height)| {
+                        SortValue::Number(width as u64 * height as u64)
+                    }),
+            };
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (value, name, path)
+        })
+        .collect();
+
+    keyed.sort_by(|(value_a, name_a, _), (value_b, name_b, _)| {
+        let ordering = match (value_a, value_b) {
+            (Some(a), Some(b)) => {
+                a.cmp(b).then_with(|| natural_cmp(name_a, name_b))
+            }
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => natural_cmp(name_a, name_b),
+        };
+        if ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    });
+
+    paths.extend(keyed.into_iter().map(|(_, _, path)| path));
+    read
+}
+
+/// Compares strings case insensitively, treating runs of digits as numbers.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_char), Some(b_char))
+                if a_char.is_ascii_digit() && b_char.is_ascii_digit() =>
+            {
+                let mut a_number = String::new();
+                while let Some(digit) =
+                    a_chars.peek().copied().filter(char::is_ascii_digit)
+                {
+                    a_number.push(digit);
+                    a_chars.next();
+                }
+                let mut b_number = String::new();
+                while let Some(digit) =
+                    b_chars.peek().copied().filter(char::is_ascii_digit)
+                {
+                    b_number.push(digit);
+                    b_chars.next();
+                }
+                // compare without leading zeros by length first, which also
+                // handles numbers too long for any integer type
+                let a_digits = a_number.trim_start_matches('0');
+                let b_digits = b_number.trim_start_matches('0');
+                let ordering = a_digits
+                    .len()
+                    .cmp(&b_digits.len())
+                    .then_with(|| a_digits.cmp(b_digits));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(a_char), Some(b_char)) => {
+                let ordering = a_char.to_lowercase().cmp(b_char.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_naturally() {
+        let cases = [
+            ("img2", "img10", Ordering::Less),
+            ("img10", "img2", Ordering::Greater),
+            ("x9y", "x10y", Ordering::Less),
+            ("img1a", "img1b", Ordering::Less),
+            ("a", "a1", Ordering::Less),
+            ("", "a", Ordering::Less),
+            ("img", "img", Ordering::Equal),
+            // case only matters when nothing else does
+            ("IMG1", "img2", Ordering::Less),
+            ("img2", "IMG10", Ordering::Less),
+            ("Photo", "photo", Ordering::Less),
+            // so do leading zeros
+            ("img007", "img8", Ordering::Less),
+            ("img010", "img9", Ordering::Greater),
+            ("img07", "img7", Ordering::Less),
+            ("img7", "img007", Ordering::Greater),
+            ("img0", "img00", Ordering::Less),
+            // longer than any integer type
+            ("12345678901234567890123", "99", Ordering::Greater),
+            (
+                "12345678901234567890123",
+                "12345678901234567890124",
+                Ordering::Less,
+            ),
+        ];
+        for (a, b, expected) in cases.iter() {
+            assert_eq!(natural_cmp(a, b), *expected, "{} and {}", a, b);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! A thumbnail cache following the freedesktop thumbnail specification.
+//!
+//! Thumbnails are stored as PNGs under `$XDG_CACHE_HOME/thumbnails`, named
+//! after the md5 hash of the file's URI, and are only used if the
+//! modification time recorded in them matches the file's.
+//! See <https://specifications.freedesktop.org/thumbnail-spec/latest/> for
+//! details.
+
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use image::{imageops::thumbnail, ImageError, RgbImage};
+use log::error;
+
+use crate::{library::modified_time, orientation};
+
+const URI_KEY: &str = "Thumb::URI";
+const MTIME_KEY: &str = "Thumb::MTime";
+
+/// The sizes defined by the specification, each with its own directory.
+const SIZES: [(u32, &str); 4] = [
+    (128, "normal"),
+    (256, "large"),
+    (512, "x-large"),
+    (1024, "xx-large"),
+];
+
+/// Returns the image at `path` scaled to fit within the smallest cache size
+/// that is at least `size` pixels on each side.
+///
+/// The cached thumbnail is used if it's still valid, otherwise the image is
+/// decoded and a new thumbnail is written to the cache.
+pub fn load_or_create(path: &Path, size: u32) -> Result<RgbImage, ImageError> {
+    let path = fs::canonicalize(path)?;
+    let (max_size, dir_name) = SIZES
+        .iter()
+        .copied()
+        .find(|(max_size, _)| *max_size >= size)
+        .unwrap_or(SIZES[SIZES.len() - 1]);
+
+    let uri = file_uri(&path);
+    let mtime = modified_time(&path);
+    let cache_path =
+        cache_dir().map(|dir| dir.join(dir_name).join(cache_file_name(&uri)));
+
+    if let Some(cache_path) = cache_path.as_ref() {
+        if let Some(image) = read_thumbnail(cache_path, &uri, mtime) {
+            return Ok(image);
+        }
+    }
+
+    // thumbnails are stored upright, so every use of them, including the
+    // square crop of folder covers, gets the orientation right
+    let image = orientation::open_image(&path)?;
+    let image = fit_within(image, max_size);
+
+    if let Some(cache_path) = cache_path {
+        if let Err(err) = write_thumbnail(&cache_path, &image, &uri, mtime) {
+            error!("Error writing thumbnail for {}: {}", path.display(), err);
+        }
+    }
+
+    Ok(image)
+}
+
+/// Deletes the cached thumbnails of the image at `path`, after it changed
+/// within the second its modification time is recorded to, which would
+/// otherwise leave them looking valid.
+pub fn remove(path: &Path) {
+    let path = match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return,
+    };
+    let dir = match cache_dir() {
+        Some(dir) => dir,
+        None => return,
+    };
+    let file_name = cache_file_name(&file_uri(&path));
+    for (_, dir_name) in SIZES.iter() {
+        // most sizes won't have been created
+        let _ = fs::remove_file(dir.join(dir_name).join(&file_name));
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("thumbnails"))
+}
+
+fn fit_within(image: RgbImage, max_size: u32) -> RgbImage {
+    let (width, height) = image.dimensions();
+    if width <= max_size && height <= max_size {
+        return image;
+    }
+    let scale = max_size as f64 / width.max(height) as f64;
+    let new_width = ((width as f64 * scale).round() as u32).max(1);
+    let new_height = ((height as f64 * scale).round() as u32).max(1);
+    thumbnail(&image, new_width, new_height)
+}
+
+/// The specification names thumbnails after the md5 hash of the file's URI.
+fn cache_file_name(uri: &str) -> String {
+    format!("{:x}.png", md5::compute(uri.as_bytes()))
+}
+
+/// Builds a `file://` URI the way GLib's `g_filename_to_uri` does, which
+/// other thumbnailers use, so they share the cached thumbnails. Everything
+/// but the characters allowed in a URI path is percent encoded.
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path_bytes(path).iter() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'~'
+            | b'/'
+            | b'!'
+            | b'$'
+            | b'&'
+            | b'\''
+            | b'('
+            | b')'
+            | b'*'
+            | b'+'
+            | b','
+            | b';'
+            | b'='
+            | b':'
+            | b'@' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// The bytes of `path`, which on Unix don't have to be UTF-8.
+#[cfg(unix)]
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    }
+}
+
+fn read_thumbnail(
+    cache_path: &Path,
+    uri: &str,
+    mtime: u64,
+) -> Option<RgbImage> {
+    let file = File::open(cache_path).ok()?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer).ok()?;
+
+    let text = &reader.info().uncompressed_latin1_text;
+    let matches = |key: &str, value: &str| {
+        text.iter()
+            .any(|chunk| chunk.keyword == key && chunk.text == value)
+    };
+    if !matches(URI_KEY, uri) || !matches(MTIME_KEY, &mtime.to_string()) {
+        return None;
+    }
+
+    buffer.truncate(frame.buffer_size());
+    let pixels = match frame.color_type {
+        png::ColorType::Rgb => buffer,
+        png::ColorType::Rgba => buffer
+            .chunks_exact(4)
+            .flat_map(|pixel| pixel[..3].to_vec())
+            .collect(),
+        png::ColorType::Grayscale => {
+            buffer.iter().flat_map(|&gray| vec![gray; 3]).collect()
+        }
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|pixel| vec![pixel[0]; 3])
+            .collect(),
+        png::ColorType::Indexed => return None,
+    };
+    RgbImage::from_raw(frame.width, frame.height, pixels)
+}
+
+fn write_thumbnail(
+    cache_path: &Path,
+    image: &RgbImage,
+    uri: &str,
+    mtime: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // the specification asks for thumbnails to be written to a temporary
+    // file and renamed, so other programs never read a partial thumbnail
+    let temp_path =
+        cache_path.with_extension(format!("png.{}.tmp", std::process::id()));
+    {
+        let file = File::create(&temp_path)?;
+        let (width, height) = image.dimensions();
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.add_text_chunk(URI_KEY.to_string(), uri.to_string())?;
+        encoder.add_text_chunk(MTIME_KEY.to_string(), mtime.to_string())?;
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(image.as_raw())?;
+    }
+    set_private(&temp_path)?;
+    fs::rename(&temp_path, cache_path)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_private(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn set_private(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! Errors shown to the user at the bottom of the window.
+
+use std::{collections::HashMap, time::Duration};
+
+use druid::{
+    widget::{Controller, CrossAxisAlignment, Flex, Label, LineBreaking, List},
+    Color, Command, Env, Event, EventCtx, Target, TimerToken, Widget,
+    WidgetExt,
+};
+
+use crate::{
+    app_commands::{DISMISS_TOAST, SHOW_ERROR},
+    app_data::{AppState, Toast},
+};
+
+/// How long a toast is shown if it isn't clicked away.
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+/// Older toasts are dropped once there are this many, so a folder full of
+/// broken images doesn't bury the window in them.
+const MAX_TOASTS: usize = 5;
+
+/// Shows the errors sent with `SHOW_ERROR` below `content`.
+pub fn with_toasts(
+    content: impl Widget<AppState> + 'static,
+) -> impl Widget<AppState> {
+    let toasts = List::new(toast).lens(AppState::toasts);
+
+    Flex::column()
+        .with_flex_child(content, 1.0)
+        .with_child(toasts)
+        .cross_axis_alignment(CrossAxisAlignment::Fill)
+        .controller(ToastController::new())
+}
+
+fn toast() -> impl Widget<Toast> {
+    Label::dynamic(|toast: &Toast, _env| toast.message.clone())
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .with_text_color(Color::WHITE)
+        .padding(10.)
+        .expand_width()
+        .background(Color::rgb8(0xb0, 0x2a, 0x2a))
+        .rounded(4.)
+        .padding((10., 0., 10., 10.))
+        .on_click(|ctx, toast: &mut Toast, _env| {
+            ctx.submit_command(Command::new(
+                DISMISS_TOAST,
+                toast.id,
+                Target::Auto,
+            ));
+        })
+}
+
+struct ToastController {
+    next_id: u64,
+    /// The toast each timer dismisses.
+    timers: HashMap<TimerToken, u64>,
+}
+
+impl ToastController {
+    fn new() -> Self {
+        ToastController {
+            next_id: 0,
+            timers: HashMap::new(),
+        }
+    }
+}
+
+impl<W: Widget<AppState>> Controller<AppState, W> for ToastController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(SHOW_ERROR) => {
+                let message = cmd.get_unchecked(SHOW_ERROR);
+                while data.toasts.len() >= MAX_TOASTS {
+                    data.toasts.pop_front();
+                }
+                data.toasts.push_back(Toast {
+                    id: self.next_id,
+                    message: message.clone(),
+                });
+                let timer = ctx.request_timer(TOAST_DURATION);
+                self.timers.insert(timer, self.next_id);
+                self.next_id += 1;
+                return;
+            }
+            Event::Command(cmd) if cmd.is(DISMISS_TOAST) => {
+                let id = *cmd.get_unchecked(DISMISS_TOAST);
+                data.toasts.retain(|toast| toast.id != id);
+                return;
+            }
+            Event::Timer(token) => {
+                if let Some(id) = self.timers.remove(token) {
+                    data.toasts.retain(|toast| toast.id != id);
+                    return;
+                }
+            }
+            _ => (),
+        }
+        child.event(ctx, event, data, env)
+    }
+}
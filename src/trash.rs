@@ -0,0 +1,362 @@
+//! Moving files to the trash and back, following the freedesktop.org trash
+//! specification, so deleted images show up in the file manager's trash.
+
+use std::{
+    fs::{self, DirBuilder, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::thumbnail_cache::path_bytes;
+
+/// A file that was moved to the trash, with what's needed to restore it.
+#[derive(Debug, Clone)]
+pub struct TrashedFile {
+    /// Where the file was before it was trashed.
+    pub original: PathBuf,
+    /// Where the file is in the trash.
+    trashed: PathBuf,
+    /// The `.trashinfo` file recording where it came from.
+    info: PathBuf,
+}
+
+/// The home trash, `$XDG_DATA_HOME/Trash`.
+fn trash_dir() -> io::Result<PathBuf> {
+    dirs::data_dir()
+        .map(|dir| dir.join("Trash"))
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no home trash directory")
+        })
+}
+
+/// A trash directory along with the directory its `.trashinfo` paths are
+/// relative to, which is the top of the file system for the trash of another
+/// mount, as the specification asks.
+struct Trash {
+    dir: PathBuf,
+    topdir: Option<PathBuf>,
+}
+
+/// The trash `path` goes to. That's the home trash if it's on the same file
+/// system, and `$topdir/.Trash-$uid` at the top of the file system of `path`
+/// otherwise, so trashing never copies whole images across devices.
+#[cfg(unix)]
+fn trash_for(path: &Path) -> io::Result<Trash> {
+    use std::os::unix::fs::MetadataExt;
+
+    let home = trash_dir()?;
+    let device = fs::metadata(path)?.dev();
+    // the home trash may not have been created yet
+    let home_device = home
+        .ancestors()
+        .find_map(|dir| fs::metadata(dir).ok())
+        .map(|metadata| metadata.dev());
+    if home_device == Some(device) {
+        return Ok(Trash {
+            dir: home,
+            topdir: None,
+        });
+    }
+    let mut topdir = path;
+    while let Some(parent) = topdir.parent() {
+        match fs::metadata(parent) {
+            Ok(metadata) if metadata.dev() == device => topdir = parent,
+            _ => break,
+        }
+    }
+    // the process owns its own entry in /proc
+    let uid = fs::metadata("/proc/self")?.uid();
+    Ok(Trash {
+        dir: topdir.join(format!(".Trash-{}", uid)),
+        topdir: Some(topdir.to_path_buf()),
+    })
+}
+
+#[cfg(not(unix))]
+fn trash_for(_path: &Path) -> io::Result<Trash> {
+    Ok(Trash {
+        dir: trash_dir()?,
+        topdir: None,
+    })
+}
+
+/// Creates the `files` and `info` directories of a trash, readable only by
+/// the user.
+fn create_trash(trash: &Path) -> io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(trash.join("files"))?;
+    builder.create(trash.join("info"))
+}
+
+/// Moves `path` to the trash.
+pub fn trash_file(path: &Path) -> io::Result<TrashedFile> {
+    let original = fs::canonicalize(path)?;
+    let trash = match trash_for(&original) {
+        Ok(trash) if trash.topdir.is_none() => trash,
+        Ok(trash) if create_trash(&trash.dir).is_ok() => trash,
+        // the top of the file system can be read-only or owned by someone
+        // else, which leaves copying the image to the home trash
+        _ => Trash {
+            dir: trash_dir()?,
+            topdir: None,
+        },
+    };
+    create_trash(&trash.dir)?;
+    let files = trash.dir.join("files");
+    let infos = trash.dir.join("info");
+
+    let (name, mut info_file) = reserve_name(&original, &files, &infos)?;
+    let info = infos.join(format!("{}.trashinfo", name));
+    let trashed = files.join(&name);
+    let recorded = match &trash.topdir {
+        Some(topdir) => original.strip_prefix(topdir).unwrap_or(&original),
+        None => &original,
+    };
+    let written = write!(
+        info_file,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(recorded),
+        deletion_date(SystemTime::now())
+    );
+    if let Err(err) = written.and_then(|_| move_file(&original, &trashed)) {
+        let _ = fs::remove_file(&info);
+        return Err(err);
+    }
+    Ok(TrashedFile {
+        original,
+        trashed,
+        info,
+    })
+}
+
+/// Moves a trashed file back to where it was, unless something else took its
+/// place in the meantime.
+pub fn restore(file: &TrashedFile) -> io::Result<()> {
+    move_file(&file.trashed, &file.original)?;
+    fs::remove_file(&file.info)
+}
+
+/// Picks a name in the trash that isn't taken, by creating its `.trashinfo`
+/// file, which the specification uses as the lock on the name.
+fn reserve_name(
+    original: &Path,
+    files: &Path,
+    infos: &Path,
+) -> io::Result<(String, fs::File)> {
+    let file_name = original
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "image".to_string());
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(dot) if dot > 0 => file_name.split_at(dot),
+        _ => (file_name.as_str(), ""),
+    };
+    for attempt in 1.. {
+        let name = if attempt == 1 {
+            file_name.clone()
+        } else {
+            format!("{}.{}{}", stem, attempt, extension)
+        };
+        if files.join(&name).exists() {
+            continue;
+        }
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(infos.join(format!("{}.trashinfo", name)))
+        {
+            Ok(info) => return Ok((name, info)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+/// The error `rename` fails with when the paths are on different file
+/// systems, `EXDEV` and `ERROR_NOT_SAME_DEVICE`.
+#[cfg(unix)]
+const CROSSES_DEVICES: i32 = 18;
+#[cfg(windows)]
+const CROSSES_DEVICES: i32 = 17;
+
+/// Moves `from` to `to`, copying it instead if they're on different file
+/// systems. The copy is removed again if it fails or `from` can't be removed,
+/// so the file never ends up half copied or in both places.
+///
+/// A file at `to` is never replaced, even one created after the caller
+/// checked for it, as `rename` would on Unix. The file is linked to its new
+/// name, which fails if it's taken, and then unlinked from its old one. Only
+/// a name that differs in case from the old one of the same file, on a file
+/// system that ignores case, is renamed directly.
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if same_file(from, to) {
+        return fs::rename(from, to);
+    }
+    match fs::hard_link(from, to) {
+        Ok(()) => {
+            if let Err(err) = fs::remove_file(from) {
+                let _ = fs::remove_file(to);
+                return Err(err);
+            }
+            return Ok(());
+        }
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(already_exists(to));
+        }
+        Err(err) if err.raw_os_error() == Some(CROSSES_DEVICES) => (),
+        // some file systems, like FAT, have no hard links, which leaves
+        // checking right before renaming
+        Err(_) => {
+            if fs::symlink_metadata(to).is_ok() {
+                return Err(already_exists(to));
+            }
+            match fs::rename(from, to) {
+                Err(err) if err.raw_os_error() == Some(CROSSES_DEVICES) => (),
+                result => return result,
+            }
+        }
+    }
+    copy_file(from, to)?;
+    if let Err(err) = fs::remove_file(from) {
+        let _ = fs::remove_file(to);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Copies `from` to `to`, with its permissions, without replacing a file at
+/// `to`. A partial copy is removed again.
+pub fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut source = fs::File::open(from)?;
+    let mut copy =
+        match OpenOptions::new().write(true).create_new(true).open(to) {
+            Ok(copy) => copy,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(already_exists(to));
+            }
+            Err(err) => return Err(err),
+        };
+    let copied = io::copy(&mut source, &mut copy)
+        .and_then(|_| source.metadata())
+        .and_then(|metadata| copy.set_permissions(metadata.permissions()));
+    if let Err(err) = copied {
+        // the file was created above, so this can't remove another one
+        let _ = fs::remove_file(to);
+        return Err(err);
+    }
+    Ok(())
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    )
+}
+
+/// Whether `a` and `b` are names of the same file, which they are when only
+/// their case differs on a file system that ignores case.
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Percent encodes `path` like the `Path` key of a `.trashinfo` file. The
+/// bytes of the path are encoded as they are, so names that aren't UTF-8 are
+/// restored as they were.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path_bytes(path).iter() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'/'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Formats `time` as `YYYY-MM-DDThh:mm:ss` in local time, as the
+/// specification asks.
+fn deletion_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() as i64)
+        .unwrap_or(0);
+    let seconds = seconds + utc_offset(seconds);
+    let (days, seconds) =
+        (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    // converts days since the epoch to a date, from Howard Hinnant's
+    // `civil_from_days`
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The offset of local time from UTC at `seconds` since the epoch, in
+/// seconds, which depends on the time of year where there's daylight saving.
+#[cfg(unix)]
+fn utc_offset(seconds: i64) -> i64 {
+    let time = seconds as libc::time_t;
+    // SAFETY: `tm` is only read once `localtime_r` filled it in, and unlike
+    // `localtime` it doesn't share its result with other threads
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+/// freedesktop.org trashes are only kept on Unix, elsewhere the date is only
+/// shown by the gallery itself.
+#[cfg(not(unix))]
+fn utc_offset(_seconds: i64) -> i64 {
+    0
+}
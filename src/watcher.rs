@@ -0,0 +1,215 @@
+//! Watching the roots of the library for changes on disk.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use druid::{ExtEventSink, Target};
+use log::error;
+use notify::{
+    watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher,
+};
+
+use crate::{
+    app_commands::{
+        CHANGED_IMAGE, MOVED_IMAGES, RECONCILED_FOLDER, REMOVED_FOLDER,
+    },
+    config::ImageFormats,
+    error::report_error,
+    main_view::{read_image_folder, walk_directories},
+    sidecar::is_sidecar,
+};
+
+/// Changes are collected for this long before they are sent, so copying many
+/// images into a folder lists it once instead of once per image.
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Keeps the library in sync with the disk by watching every root
+/// recursively on another thread.
+///
+/// Folders that gain or lose images are listed again and sent with
+/// `RECONCILED_FOLDER`, folders without images left or that vanished are sent
+/// with `REMOVED_FOLDER`, images that were rewritten with `CHANGED_IMAGE` and
+/// renamed files and directories with `MOVED_IMAGES`.
+pub struct LibraryWatcher {
+    roots: Sender<PathBuf>,
+}
+
+impl LibraryWatcher {
+    pub fn new(
+        roots: Vec<PathBuf>,
+        formats: ImageFormats,
+        handle: ExtEventSink,
+    ) -> Self {
+        let (sender, receiver) = channel();
+        for root in roots {
+            sender.send(root).unwrap();
+        }
+        thread::spawn(move || watch(receiver, formats, handle));
+        LibraryWatcher { roots: sender }
+    }
+
+    /// Starts watching a root that was added to the library.
+    pub fn watch(&self, root: PathBuf) {
+        if self.roots.send(root).is_err() {
+            error!("The file watcher stopped, the library won't be updated");
+        }
+    }
+}
+
+fn watch(
+    roots: Receiver<PathBuf>,
+    formats: ImageFormats,
+    handle: ExtEventSink,
+) {
+    let (sender, events) = channel();
+    let mut watcher: RecommendedWatcher = match watcher(sender, DEBOUNCE) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            error!("Error starting the file watcher: {}", err);
+            return;
+        }
+    };
+
+    loop {
+        // new roots are picked up between events, which arrive at least as
+        // often as the timeout
+        while let Ok(root) = roots.try_recv() {
+            if let Err(err) = watcher.watch(&root, RecursiveMode::Recursive) {
+                error!("Error watching {}: {}", root.display(), err);
+            }
+        }
+
+        let event = match events.recv_timeout(DEBOUNCE) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        let mut batch = vec![event];
+        batch.extend(events.try_iter());
+        handle_events(batch, &formats, &handle);
+    }
+}
+
+fn handle_events(
+    events: Vec<DebouncedEvent>,
+    formats: &ImageFormats,
+    handle: &ExtEventSink,
+) {
+    // every folder is listed once, however many of its images changed
+    let mut changed_folders = BTreeSet::new();
+    for event in events {
+        match event {
+            // sidecars are written whenever an image is rated or tagged, and
+            // don't change which images are in the library
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Remove(path)
+            | DebouncedEvent::Write(path)
+                if is_sidecar(&path) => {}
+            DebouncedEvent::Rename(from, to)
+                if is_sidecar(&from) && is_sidecar(&to) => {}
+            DebouncedEvent::Create(path) => {
+                created(&path, &mut changed_folders, handle);
+            }
+            DebouncedEvent::Remove(path) => {
+                removed(&path, &mut changed_folders, handle);
+            }
+            DebouncedEvent::Rename(from, to) => {
+                removed(&from, &mut changed_folders, handle);
+                created(&to, &mut changed_folders, handle);
+                // albums refer to images by their paths, which are kept up
+                // with images and folders renamed outside of the gallery.
+                // This only fails when the app is closing
+                let _ = handle.submit_command(
+                    MOVED_IMAGES,
+                    vec![(Arc::new(from), Arc::new(to))],
+                    Target::Auto,
+                );
+            }
+            DebouncedEvent::Write(path) if path.is_file() => {
+                changed(path, handle);
+            }
+            DebouncedEvent::Error(err, path) => match path {
+                Some(path) => {
+                    error!("Error watching {}: {}", path.display(), err)
+                }
+                None => error!("Error watching the library: {}", err),
+            },
+            _ => (),
+        }
+    }
+
+    for folder in changed_folders {
+        // removed directories were already sent above
+        if !folder.is_dir() {
+            continue;
+        }
+        // this only fails when the app is closing
+        let _ = match read_image_folder(&folder, formats) {
+            Ok(Some(image_folder)) => handle.submit_command(
+                RECONCILED_FOLDER,
+                image_folder,
+                Target::Auto,
+            ),
+            Ok(None) => handle.submit_command(
+                REMOVED_FOLDER,
+                Arc::new(folder),
+                Target::Auto,
+            ),
+            Err(err) => {
+                report_error(handle, &err);
+                continue;
+            }
+        };
+    }
+}
+
+fn created(
+    path: &Path,
+    changed_folders: &mut BTreeSet<PathBuf>,
+    handle: &ExtEventSink,
+) {
+    if path.is_dir() {
+        // a directory that was moved in can already contain folders
+        for entry in walk_directories(path.to_path_buf()) {
+            changed_folders.insert(entry.path().to_path_buf());
+        }
+    } else if let Some(folder) = path.parent() {
+        changed_folders.insert(folder.to_path_buf());
+        // most editors save by writing a new file and renaming it over the
+        // image, which then still has to be read again
+        changed(path.to_path_buf(), handle);
+    }
+}
+
+/// Has the image at `path` read again. Images that aren't in the library are
+/// left alone by `Delegate`, so this is also sent for new files.
+fn changed(path: PathBuf, handle: &ExtEventSink) {
+    // this only fails when the app is closing
+    let _ = handle.submit_command(CHANGED_IMAGE, Arc::new(path), Target::Auto);
+}
+
+fn removed(
+    path: &Path,
+    changed_folders: &mut BTreeSet<PathBuf>,
+    handle: &ExtEventSink,
+) {
+    // whether this was a file or a directory can't be told anymore, so it's
+    // removed in case it was a folder and its parent is listed in case it was
+    // an image. This only fails when the app is closing
+    let _ = handle.submit_command(
+        REMOVED_FOLDER,
+        Arc::new(path.to_path_buf()),
+        Target::Auto,
+    );
+    if let Some(folder) = path.parent() {
+        changed_folders.insert(folder.to_path_buf());
+    }
+}
@@ -0,0 +1,255 @@
+//! An image widget that fades between the images it's given.
+
+use std::time::Duration;
+
+use druid::{
+    kurbo::{Rect, Size},
+    piet::{ImageFormat, InterpolationMode, PietImage},
+    widget::prelude::*,
+    Color, Data, ImageBuf,
+};
+use image::{
+    imageops::{overlay, resize, FilterType},
+    Rgb, RgbImage,
+};
+
+/// Scales `image` so it fits inside `size`, keeping its aspect ratio.
+///
+/// The sides are rounded down, like the canvases of [`Crossfade`], so the
+/// image never comes out a pixel larger than `size`.
+///
+/// [`Crossfade`]: struct.Crossfade.html
+pub fn fit_image(image: &RgbImage, size: Size) -> RgbImage {
+    let (width, height) = image.dimensions();
+    let scale = (size.width / width as f64).min(size.height / height as f64);
+    let new_width = ((width as f64 * scale).floor() as u32).max(1);
+    let new_height = ((height as f64 * scale).floor() as u32).max(1);
+    if (new_width, new_height) == (width, height) {
+        return image.clone();
+    }
+    resize(image, new_width, new_height, FilterType::Triangle)
+}
+
+struct Fade {
+    /// The outgoing and incoming images centred on canvases the size of the
+    /// widget, so they can be blended pixel by pixel.
+    from: RgbImage,
+    to: RgbImage,
+    elapsed: Duration,
+    duration: Duration,
+    frame: Option<PietImage>,
+}
+
+/// Shows an image fitted and centred on a black background, crossfading from
+/// the previous image whenever a new one is set.
+///
+/// The fade is blended on the CPU at the size of the widget in device
+/// pixels, so images are best scaled down to [`pixel_size`] with
+/// [`fit_image`] before they are set.
+///
+/// [`pixel_size`]: #method.pixel_size
+/// [`fit_image`]: fn.fit_image.html
+pub struct Crossfade {
+    image: Option<RgbImage>,
+    paint_data: Option<PietImage>,
+    fade: Option<Fade>,
+    /// The size of the widget in device pixels, which is larger than its
+    /// size in display points on screens that are scaled up.
+    pixel_size: Size,
+}
+
+impl Crossfade {
+    pub fn new() -> Self {
+        Crossfade {
+            image: None,
+            paint_data: None,
+            fade: None,
+            pixel_size: Size::ZERO,
+        }
+    }
+
+    /// The size of the widget in device pixels, which images are shown
+    /// sharply at.
+    pub fn pixel_size(&self) -> Size {
+        self.pixel_size
+    }
+
+    /// Replaces the image, fading to it over `duration`. The image is shown
+    /// right away if `duration` is zero or there's nothing to fade from.
+    ///
+    /// The caller has to request an animation frame when this starts a fade,
+    /// see [`is_fading`].
+    ///
+    /// [`is_fading`]: #method.is_fading
+    pub fn set_image(&mut self, image: RgbImage, duration: Duration) {
+        let width = self.pixel_size.width as u32;
+        let height = self.pixel_size.height as u32;
+        self.fade = match &self.image {
+            Some(previous)
+                if duration > Duration::from_secs(0)
+                    && width > 0
+                    && height > 0 =>
+            {
+                Some(Fade {
+                    from: Self::canvas(previous, self.pixel_size),
+                    to: Self::canvas(&image, self.pixel_size),
+                    elapsed: Duration::from_secs(0),
+                    duration,
+                    frame: None,
+                })
+            }
+            _ => None,
+        };
+        self.image = Some(image);
+        self.paint_data = None;
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    fn canvas(image: &RgbImage, size: Size) -> RgbImage {
+        let (width, height) = (size.width as u32, size.height as u32);
+        let image = if image.width() > width || image.height() > height {
+            fit_image(image, size)
+        } else {
+            image.clone()
+        };
+        let mut canvas = RgbImage::from_pixel(width, height, Rgb([0, 0, 0]));
+        // `fit_image` leaves at least a pixel, even on a canvas without any
+        overlay(
+            &mut canvas,
+            &image,
+            width.saturating_sub(image.width()) / 2,
+            height.saturating_sub(image.height()) / 2,
+        );
+        canvas
+    }
+
+    fn blend(fade: &Fade) -> ImageBuf {
+        let t = fade.elapsed.as_secs_f64() / fade.duration.as_secs_f64();
+        let t = (t.min(1.) * 256.) as u16;
+        let pixels: Vec<u8> = fade
+            .from
+            .as_raw()
+            .iter()
+            .zip(fade.to.as_raw())
+            .map(|(&from, &to)| {
+                ((from as u16 * (256 - t) + to as u16 * t) >> 8) as u8
+            })
+            .collect();
+        ImageBuf::from_raw(
+            pixels,
+            ImageFormat::Rgb,
+            fade.from.width() as usize,
+            fade.from.height() as usize,
+        )
+    }
+}
+
+impl Default for Crossfade {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Data> Widget<T> for Crossfade {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        _data: &mut T,
+        _env: &Env,
+    ) {
+        if let Event::AnimFrame(interval) = event {
+            if let Some(fade) = &mut self.fade {
+                fade.elapsed += Duration::from_nanos(*interval);
+                if fade.elapsed >= fade.duration {
+                    self.fade = None;
+                } else {
+                    fade.frame = None;
+                    ctx.request_anim_frame();
+                }
+                ctx.request_paint();
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &T,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(
+        &mut self,
+        _ctx: &mut UpdateCtx,
+        _old_data: &T,
+        _data: &T,
+        _env: &Env,
+    ) {
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &T,
+        _env: &Env,
+    ) -> Size {
+        bc.debug_check("Crossfade");
+
+        let size = bc.max();
+        let pixel_size = match ctx.window().get_scale() {
+            Ok(scale) => scale.to_px(&size),
+            Err(_) => size,
+        };
+        // whole pixels, as the canvases are
+        let pixel_size =
+            Size::new(pixel_size.width.floor(), pixel_size.height.floor());
+        if pixel_size != self.pixel_size {
+            // the canvases no longer match the widget, so the fade is skipped
+            self.fade = None;
+            self.pixel_size = pixel_size;
+        }
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &T, _env: &Env) {
+        let rect = ctx.size().to_rect();
+        ctx.fill(rect, &Color::BLACK);
+
+        if let Some(fade) = &mut self.fade {
+            if fade.frame.is_none() {
+                let frame = Self::blend(fade).to_image(ctx.render_ctx);
+                fade.frame = Some(frame);
+            }
+            let frame = fade.frame.as_ref().unwrap();
+            ctx.draw_image(frame, rect, InterpolationMode::Bilinear);
+            return;
+        }
+
+        let image = match &self.image {
+            Some(image) => image,
+            None => return,
+        };
+        let image_size = Size::new(image.width() as f64, image.height() as f64);
+        let scale = (rect.width() / image_size.width)
+            .min(rect.height() / image_size.height);
+        let image_rect =
+            Rect::from_center_size(rect.center(), image_size * scale);
+        let piet_image = self.paint_data.get_or_insert_with(|| {
+            ImageBuf::from_raw(
+                image.as_raw().clone(),
+                ImageFormat::Rgb,
+                image.width() as usize,
+                image.height() as usize,
+            )
+            .to_image(ctx.render_ctx)
+        });
+        ctx.draw_image(piet_image, image_rect, InterpolationMode::Bilinear);
+    }
+}
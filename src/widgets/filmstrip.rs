@@ -0,0 +1,188 @@
+//! A row of cells that keeps the selected cell in its middle.
+
+use std::collections::BTreeMap;
+
+use druid::{
+    kurbo::{Point, Size},
+    widget::prelude::*,
+    Data, WidgetPod,
+};
+
+use super::{GridItems, GridSelection, NO_SELECTION, SELECTED_CELL};
+
+type Cell<I> = WidgetPod<I, Box<dyn Widget<I>>>;
+
+/// A single row of equally sized cells, scrolled so the selected cell is
+/// always in the middle of it.
+///
+/// Like a [`VirtualGrid`], it only keeps widgets for the cells in
+/// [`GridItems::visible_items`], so those should be the cells around the
+/// selected one. The cells get the selected position as [`SELECTED_CELL`],
+/// and the mouse wheel moves the selection along the row.
+///
+/// Changes a cell makes to its data aren't kept.
+///
+/// [`VirtualGrid`]: struct.VirtualGrid.html
+/// [`GridItems::visible_items`]: trait.GridItems.html#tymethod.visible_items
+/// [`SELECTED_CELL`]: constant.SELECTED_CELL.html
+pub struct Filmstrip<I> {
+    builder: Box<dyn Fn() -> Box<dyn Widget<I>>>,
+    cells: BTreeMap<usize, Cell<I>>,
+    cell_size: Size,
+}
+
+impl<I: Data> Filmstrip<I> {
+    pub fn new<W: Widget<I> + 'static>(
+        builder: impl Fn() -> W + 'static,
+        cell_size: Size,
+    ) -> Self {
+        Filmstrip {
+            builder: Box::new(move || Box::new(builder())),
+            cells: BTreeMap::new(),
+            cell_size,
+        }
+    }
+
+    /// Creates the cells that came into view and drops the ones that left
+    /// it, returning whether any changed.
+    fn update_cells<T: GridItems<I>>(&mut self, data: &T) -> bool {
+        let visible = data.visible_items();
+        let visible = visible.start..visible.end.min(data.items_len());
+        let before = self.cells.len();
+        self.cells.retain(|index, _| visible.contains(index));
+        let mut changed = self.cells.len() != before;
+        for index in visible {
+            if !self.cells.contains_key(&index) {
+                self.cells.insert(index, WidgetPod::new((self.builder)()));
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn child_env<T: GridSelection>(data: &T, env: &Env) -> Env {
+        let selected = data.selected_cell().unwrap_or(NO_SELECTION);
+        env.clone().adding(SELECTED_CELL, selected)
+    }
+}
+
+impl<T, I> Widget<T> for Filmstrip<I>
+where
+    T: Data + GridItems<I> + GridSelection,
+    I: Data,
+{
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut T,
+        env: &Env,
+    ) {
+        if let Event::Wheel(mouse) = event {
+            let count = data.item_count();
+            if count > 0 {
+                let current = data.selected_item().unwrap_or(0);
+                // either direction of the wheel scrolls the strip
+                let delta = mouse.wheel_delta.x + mouse.wheel_delta.y;
+                if delta > 0. && current + 1 < count {
+                    data.select_item(current + 1);
+                } else if delta < 0. && current > 0 {
+                    data.select_item(current - 1);
+                }
+            }
+            ctx.set_handled();
+            return;
+        }
+        let env = Self::child_env(data, env);
+        for (index, cell) in self.cells.iter_mut() {
+            if let Some(mut item) = data.item(*index) {
+                cell.event(ctx, event, &mut item, &env);
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &T,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            // the new cells are added along with the strip itself
+            self.update_cells(data);
+        }
+        let env = Self::child_env(data, env);
+        for (index, cell) in self.cells.iter_mut() {
+            if let Some(item) = data.item(*index) {
+                cell.lifecycle(ctx, event, &item, &env);
+            }
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &T,
+        data: &T,
+        env: &Env,
+    ) {
+        if self.update_cells(data) {
+            ctx.children_changed();
+        }
+        // the cells move along whenever another one is selected
+        if data.selected_item() != old_data.selected_item() {
+            ctx.request_layout();
+        }
+        let env = Self::child_env(data, env);
+        for (index, cell) in self.cells.iter_mut() {
+            // cells that were just created are updated once they're added
+            if !cell.is_initialized() {
+                continue;
+            }
+            if let Some(item) = data.item(*index) {
+                cell.update(ctx, &item, &env);
+            }
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &T,
+        env: &Env,
+    ) -> Size {
+        bc.debug_check("Filmstrip");
+
+        let width = bc.max().width;
+        let cell_width = self.cell_size.width;
+        // the selected cell goes in the middle and the others next to it
+        let selected = data.selected_item().unwrap_or(0) as f64;
+        let center = (width - cell_width) / 2.;
+        let env = Self::child_env(data, env);
+        let cell_bc = BoxConstraints::new(Size::ZERO, self.cell_size);
+        for (index, cell) in self.cells.iter_mut() {
+            let item = match data.item(*index) {
+                Some(item) => item,
+                None => continue,
+            };
+            cell.layout(ctx, &cell_bc, &item, &env);
+            let x = center + (*index as f64 - selected) * cell_width;
+            cell.set_origin(ctx, &item, &env, Point::new(x, 0.));
+        }
+
+        bc.constrain(Size::new(width, self.cell_size.height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &T, env: &Env) {
+        let env = Self::child_env(data, env);
+        let clip = ctx.size().to_rect();
+        ctx.clip(clip);
+        for (index, cell) in self.cells.iter_mut() {
+            if let Some(item) = data.item(*index) {
+                cell.paint(ctx, &item, &env);
+            }
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! Keyboard navigation for a scrolled grid.
+
+use std::ops::Range;
+
+use druid::{
+    kurbo::{Point, Rect, Size},
+    widget::prelude::*,
+    Command, Data, KbKey, Key, Selector, Target,
+};
+
+use crate::{app_commands::TAKE_FOCUS, widgets::Scroll};
+
+/// The position of the selected cell, or `NO_SELECTION`. Cells use this to
+/// draw a focus ring around themselves.
+pub const SELECTED_CELL: Key<u64> = Key::new("app.grid.selected-cell");
+
+pub const NO_SELECTION: u64 = u64::MAX;
+
+/// Sent by the navigator to itself when a layout changed which cells are in
+/// view, since the data can't be changed during layout.
+const UPDATE_VISIBLE_ITEMS: Selector<()> =
+    Selector::new("app.grid.update-visible-items");
+
+/// Rows above and below the viewport that count as visible, so cells are
+/// ready shortly before they scroll into view.
+const OVERSCAN_ROWS: usize = 2;
+
+/// Data that backs a grid whose selection can be moved with the keyboard.
+pub trait GridSelection {
+    /// The number of cells in the grid.
+    fn item_count(&self) -> usize;
+
+    /// The position of the selected cell.
+    fn selected_item(&self) -> Option<usize>;
+
+    fn select_item(&mut self, position: usize);
+
+    /// The value the cells get as [`SELECTED_CELL`]. This is the position of
+    /// the selected cell, unless the cells identify themselves another way.
+    ///
+    /// [`SELECTED_CELL`]: constant.SELECTED_CELL.html
+    fn selected_cell(&self) -> Option<u64> {
+        self.selected_item().map(|position| position as u64)
+    }
+
+    /// Whether the grid is in the view that's currently shown, so it can take
+    /// the keyboard focus back when a view on top of it is popped.
+    fn is_top_view(&self) -> bool;
+
+    /// Called with the positions of the cells in or near the viewport
+    /// whenever they change, for grids that only load what's in view.
+    fn set_visible_items(&mut self, _items: Range<usize>) {}
+}
+
+type Action<T> = Box<dyn Fn(&mut EventCtx, &mut T, &Env)>;
+
+/// Wraps the [`Scroll`] containing a grid, moving the selection with the arrow
+/// keys, Home, End, PageUp and PageDown and keeping the selected cell in view.
+///
+/// Enter and Escape or Backspace run the actions set with [`on_open`] and
+/// [`on_back`].
+///
+/// [`on_open`]: struct.GridNavigator.html#method.on_open
+/// [`on_back`]: struct.GridNavigator.html#method.on_back
+pub struct GridNavigator<T, W> {
+    scroll: Scroll<T, W>,
+    /// The size of a cell, including the spacing between cells.
+    cell_size: Size,
+    /// Where the first cell starts within the scrolled content.
+    origin: Point,
+    on_open: Option<Action<T>>,
+    on_back: Option<Action<T>>,
+    /// The cells last passed to `GridSelection::set_visible_items`.
+    visible: Range<usize>,
+}
+
+impl<T: Data + GridSelection, W: Widget<T>> GridNavigator<T, W> {
+    pub fn new(scroll: Scroll<T, W>, cell_size: Size) -> Self {
+        GridNavigator {
+            scroll,
+            cell_size,
+            origin: Point::ORIGIN,
+            on_open: None,
+            on_back: None,
+            visible: 0..0,
+        }
+    }
+
+    pub fn with_origin(mut self, origin: Point) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn on_open(
+        mut self,
+        action: impl Fn(&mut EventCtx, &mut T, &Env) + 'static,
+    ) -> Self {
+        self.on_open = Some(Box::new(action));
+        self
+    }
+
+    pub fn on_back(
+        mut self,
+        action: impl Fn(&mut EventCtx, &mut T, &Env) + 'static,
+    ) -> Self {
+        self.on_back = Some(Box::new(action));
+        self
+    }
+
+    fn columns(&self) -> usize {
+        let width = self.scroll.child_size().width - self.origin.x;
+        ((width / self.cell_size.width).floor() as usize).max(1)
+    }
+
+    fn cell_rect(&self, position: usize) -> Rect {
+        let columns = self.columns();
+        let column = (position % columns) as f64;
+        let row = (position / columns) as f64;
+        Rect::from_origin_size(
+            Point::new(
+                self.origin.x + column * self.cell_size.width,
+                self.origin.y + row * self.cell_size.height,
+            ),
+            self.cell_size,
+        )
+    }
+
+    /// The positions of the cells in the viewport, along with `OVERSCAN_ROWS`
+    /// above and below it.
+    fn visible_items(
+        &self,
+        viewport_height: f64,
+        count: usize,
+    ) -> Range<usize> {
+        let columns = self.columns();
+        let top = self.scroll.offset().y - self.origin.y;
+        let first_row = (top / self.cell_size.height).floor().max(0.) as usize;
+        let last_row = ((top + viewport_height) / self.cell_size.height)
+            .ceil()
+            .max(0.) as usize;
+        let start = first_row.saturating_sub(OVERSCAN_ROWS) * columns;
+        let end = (last_row + OVERSCAN_ROWS) * columns;
+        start.min(count)..end.min(count)
+    }
+
+    fn update_visible_items(&mut self, ctx: &mut EventCtx, data: &mut T) {
+        let visible = self.visible_items(ctx.size().height, data.item_count());
+        if visible != self.visible {
+            data.set_visible_items(visible.clone());
+            self.visible = visible;
+        }
+    }
+
+    /// Returns the new selection for `key`, or `None` if it doesn't move the
+    /// selection.
+    fn next_position(
+        &self,
+        key: &KbKey,
+        current: usize,
+        count: usize,
+        viewport_height: f64,
+    ) -> Option<usize> {
+        let columns = self.columns();
+        let page = columns
+            * ((viewport_height / self.cell_size.height).floor() as usize)
+                .max(1);
+        let last = count - 1;
+        let next = match key {
+            KbKey::ArrowLeft => current.saturating_sub(1),
+            KbKey::ArrowRight => (current + 1).min(last),
+            KbKey::ArrowUp => current.checked_sub(columns).unwrap_or(current),
+            KbKey::ArrowDown => {
+                if current + columns <= last {
+                    current + columns
+                } else {
+                    current
+                }
+            }
+            KbKey::PageUp => current.saturating_sub(page),
+            KbKey::PageDown => (current + page).min(last),
+            KbKey::Home => 0,
+            KbKey::End => last,
+            _ => return None,
+        };
+        Some(next)
+    }
+
+    fn child_env(data: &T, env: &Env) -> Env {
+        let selected = data.selected_cell().unwrap_or(NO_SELECTION);
+        env.clone().adding(SELECTED_CELL, selected)
+    }
+
+    fn take_focus(widget_id: WidgetId) -> Command {
+        Command::new(TAKE_FOCUS, (), Target::Widget(widget_id))
+    }
+}
+
+impl<T: Data + GridSelection, W: Widget<T>> Widget<T> for GridNavigator<T, W> {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut T,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(TAKE_FOCUS) => {
+                ctx.request_focus();
+                return;
+            }
+            Event::Command(cmd) if cmd.is(UPDATE_VISIBLE_ITEMS) => {
+                self.update_visible_items(ctx, data);
+                return;
+            }
+            Event::MouseDown(_) => ctx.request_focus(),
+            Event::KeyDown(key) => {
+                match &key.key {
+                    KbKey::Enter => {
+                        if let Some(action) = &self.on_open {
+                            action(ctx, data, env);
+                        }
+                        ctx.set_handled();
+                        return;
+                    }
+                    KbKey::Escape | KbKey::Backspace => {
+                        if let Some(action) = &self.on_back {
+                            action(ctx, data, env);
+                        }
+                        ctx.set_handled();
+                        return;
+                    }
+                    _ => (),
+                }
+
+                let count = data.item_count();
+                if count == 0 {
+                    return;
+                }
+                let current = data.selected_item().unwrap_or(0).min(count - 1);
+                let next = self.next_position(
+                    &key.key,
+                    current,
+                    count,
+                    ctx.size().height,
+                );
+                if let Some(next) = next {
+                    data.select_item(next);
+                    let cell = self.cell_rect(next);
+                    self.scroll.scroll_to(cell);
+                    self.update_visible_items(ctx, data);
+                    ctx.set_handled();
+                    ctx.request_paint();
+                    return;
+                }
+            }
+            _ => (),
+        }
+        let env = Self::child_env(data, env);
+        self.scroll.event(ctx, event, data, &env);
+        // scrolling moves other cells into view
+        self.update_visible_items(ctx, data);
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &T,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.register_for_focus();
+            ctx.submit_command(Self::take_focus(ctx.widget_id()));
+        }
+        let env = Self::child_env(data, env);
+        self.scroll.lifecycle(ctx, event, data, &env);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &T,
+        data: &T,
+        env: &Env,
+    ) {
+        if data.is_top_view() && !old_data.is_top_view() {
+            ctx.submit_command(Self::take_focus(ctx.widget_id()));
+            // a view on top of the grid, like the filmstrip, can have moved
+            // the selection and loaded other cells
+            if let Some(selected) = data.selected_item() {
+                self.scroll.scroll_to(self.cell_rect(selected));
+            }
+            self.visible = 0..0;
+            ctx.submit_command(Command::new(
+                UPDATE_VISIBLE_ITEMS,
+                (),
+                Target::Widget(ctx.widget_id()),
+            ));
+            ctx.request_paint();
+        }
+        if data.selected_item() != old_data.selected_item() {
+            ctx.request_paint();
+        }
+        let env = Self::child_env(data, env);
+        self.scroll.update(ctx, old_data, data, &env);
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &T,
+        env: &Env,
+    ) -> Size {
+        let env = Self::child_env(data, env);
+        let size = self.scroll.layout(ctx, bc, data, &env);
+        // resizing the window or changing the number of cells changes which
+        // are in view
+        if self.visible_items(size.height, data.item_count()) != self.visible {
+            ctx.submit_command(Command::new(
+                UPDATE_VISIBLE_ITEMS,
+                (),
+                Target::Widget(ctx.widget_id()),
+            ));
+        }
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &T, env: &Env) {
+        let env = Self::child_env(data, env);
+        self.scroll.paint(ctx, data, &env);
+    }
+}
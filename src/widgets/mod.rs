@@ -1,6 +1,20 @@
 mod button;
+mod crossfade;
+mod filmstrip;
+mod foreground;
+mod grid_navigator;
 mod scroll;
 mod scroll_component;
+mod virtual_grid;
+mod zoom_image;
 
 pub use button::Button;
+pub use crossfade::{fit_image, Crossfade};
+pub use filmstrip::Filmstrip;
+pub use foreground::Foreground;
+pub use grid_navigator::{
+    GridNavigator, GridSelection, NO_SELECTION, SELECTED_CELL,
+};
 pub use scroll::Scroll;
+pub use virtual_grid::{GridItems, VirtualGrid};
+pub use zoom_image::{ZoomImage, ZoomPreset};
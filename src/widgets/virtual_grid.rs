@@ -0,0 +1,412 @@
+//! A grid that only creates the cells that are in view.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use druid::{
+    kurbo::{Point, Rect, Size, Vec2},
+    widget::prelude::*,
+    Color, Data, MouseEvent, WidgetPod,
+};
+
+/// How far the mouse has to move while pressed before it drags a rubber band
+/// instead of clicking.
+const DRAG_THRESHOLD: f64 = 5.;
+
+/// Data that backs a [`VirtualGrid`].
+///
+/// [`VirtualGrid`]: struct.VirtualGrid.html
+pub trait GridItems<I> {
+    /// The number of cells in the grid, whether they are shown or not.
+    fn items_len(&self) -> usize;
+
+    /// The data of the cell at `index`.
+    fn item(&self, index: usize) -> Option<I>;
+
+    /// The cells that are in or near the viewport, usually set through
+    /// [`GridSelection::set_visible_items`].
+    ///
+    /// [`GridSelection::set_visible_items`]: trait.GridSelection.html#method.set_visible_items
+    fn visible_items(&self) -> Range<usize>;
+
+    /// Replaces the selection with the cells at `items`, which are under a
+    /// rubber band dragged across the grid, or none if the grid was clicked
+    /// outside of its cells.
+    fn select_items(&mut self, _items: &[usize]) {}
+
+    /// Whether the cells can be dragged around to put them in another order.
+    fn can_move_items(&self) -> bool {
+        false
+    }
+
+    /// Moves the cell at `from` before the cell at `to`, or after the last
+    /// one if `to` is `items_len()`, after it was dragged there.
+    fn move_item(&mut self, _from: usize, _to: usize) {}
+}
+
+/// A rubber band the mouse is dragging, or may be about to.
+struct Band {
+    start: Point,
+    end: Point,
+    /// Whether the mouse moved far enough to drag it.
+    dragging: bool,
+}
+
+/// A cell the mouse is dragging to another place, or may be about to.
+struct Move {
+    from: usize,
+    start: Point,
+    /// Where the cell would go if it was dropped now.
+    to: usize,
+    /// Whether the mouse moved far enough to drag it.
+    dragging: bool,
+}
+
+type Cell<I> = WidgetPod<I, Box<dyn Widget<I>>>;
+
+/// A grid of equally sized cells, filled left to right and top to bottom,
+/// that only keeps widgets for the cells in [`GridItems::visible_items`].
+///
+/// The grid is as tall as all of its rows, so a [`Scroll`] around it scrolls
+/// through the whole of it, but the cells out of view are only created once
+/// they scroll into view and are dropped when they scroll out of it.
+///
+/// Changes a cell makes to its data aren't kept.
+///
+/// Dragging across the grid selects the cells under the rubber band with
+/// [`GridItems::select_items`]. If [`GridItems::can_move_items`], dragging a
+/// cell moves it instead, and the band can only be dragged from between the
+/// cells.
+///
+/// [`GridItems::visible_items`]: trait.GridItems.html#tymethod.visible_items
+/// [`GridItems::can_move_items`]: trait.GridItems.html#method.can_move_items
+/// [`Scroll`]: struct.Scroll.html
+pub struct VirtualGrid<I> {
+    builder: Box<dyn Fn() -> Box<dyn Widget<I>>>,
+    cells: BTreeMap<usize, Cell<I>>,
+    cell_size: Size,
+    band: Option<Band>,
+    moving: Option<Move>,
+}
+
+impl<I: Data> VirtualGrid<I> {
+    pub fn new<W: Widget<I> + 'static>(
+        builder: impl Fn() -> W + 'static,
+        cell_size: Size,
+    ) -> Self {
+        VirtualGrid {
+            builder: Box::new(move || Box::new(builder())),
+            cells: BTreeMap::new(),
+            cell_size,
+            band: None,
+            moving: None,
+        }
+    }
+
+    fn columns(&self, width: f64) -> usize {
+        ((width / self.cell_size.width).floor() as usize).max(1)
+    }
+
+    /// The cell at `position`, if there's one.
+    fn item_at(
+        &self,
+        position: Point,
+        width: f64,
+        len: usize,
+    ) -> Option<usize> {
+        let columns = self.columns(width);
+        if position.x < 0. || position.y < 0. {
+            return None;
+        }
+        let column = (position.x / self.cell_size.width) as usize;
+        let row = (position.y / self.cell_size.height) as usize;
+        let index = row * columns + column;
+        if column < columns && index < len {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Where a cell dropped at `position` goes, which is before the cell whose
+    /// left half or the cell before whose right half it's over.
+    fn drop_index(&self, position: Point, width: f64, len: usize) -> usize {
+        let columns = self.columns(width);
+        let column = ((position.x / self.cell_size.width).round().max(0.)
+            as usize)
+            .min(columns);
+        let row = (position.y / self.cell_size.height).max(0.) as usize;
+        (row * columns + column).min(len)
+    }
+
+    /// The top left corner of the cell at `index`, or where it would be.
+    fn cell_origin(&self, index: usize, width: f64) -> Point {
+        let columns = self.columns(width);
+        Point::new(
+            (index % columns) as f64 * self.cell_size.width,
+            (index / columns) as f64 * self.cell_size.height,
+        )
+    }
+
+    /// The cells that `rect` touches.
+    fn items_in(&self, rect: Rect, width: f64, len: usize) -> Vec<usize> {
+        let columns = self.columns(width);
+        let rows = (len + columns - 1) / columns;
+        if rect.x0 >= columns as f64 * self.cell_size.width || len == 0 {
+            return Vec::new();
+        }
+        let cell = |x: f64, size: f64, count: usize| {
+            ((x / size).max(0.) as usize).min(count.saturating_sub(1))
+        };
+        let (first_column, last_column) = (
+            cell(rect.x0, self.cell_size.width, columns),
+            cell(rect.x1, self.cell_size.width, columns),
+        );
+        let (first_row, last_row) = (
+            cell(rect.y0, self.cell_size.height, rows),
+            cell(rect.y1, self.cell_size.height, rows),
+        );
+        (first_row..=last_row)
+            .flat_map(|row| {
+                (first_column..=last_column)
+                    .map(move |column| row * columns + column)
+            })
+            .filter(|index| *index < len)
+            .collect()
+    }
+
+    /// Sends a mouse up far outside of the cells, so the cell the band
+    /// started on stops being pressed without being clicked.
+    fn release_cells<T: GridItems<I>>(
+        &mut self,
+        ctx: &mut EventCtx,
+        mouse: &MouseEvent,
+        data: &mut T,
+        env: &Env,
+    ) {
+        let outside = Point::new(f64::MIN, f64::MIN);
+        let release = Event::MouseUp(MouseEvent {
+            pos: outside,
+            window_pos: outside,
+            ..mouse.clone()
+        });
+        for (index, cell) in self.cells.iter_mut() {
+            if let Some(mut item) = data.item(*index) {
+                cell.event(ctx, &release, &mut item, env);
+            }
+        }
+    }
+
+    /// Creates the cells that came into view and drops the ones that left
+    /// it, returning whether any changed.
+    fn update_cells<T: GridItems<I>>(&mut self, data: &T) -> bool {
+        let visible = data.visible_items();
+        let visible = visible.start..visible.end.min(data.items_len());
+        let before = self.cells.len();
+        self.cells.retain(|index, _| visible.contains(index));
+        let mut changed = self.cells.len() != before;
+        for index in visible {
+            if !self.cells.contains_key(&index) {
+                self.cells.insert(index, WidgetPod::new((self.builder)()));
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+impl<T: Data + GridItems<I>, I: Data> Widget<T> for VirtualGrid<I> {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut T,
+        env: &Env,
+    ) {
+        let width = ctx.size().width;
+        match event {
+            Event::MouseDown(mouse)
+                if mouse.button.is_left()
+                    && !mouse.mods.ctrl()
+                    && !mouse.mods.shift() =>
+            {
+                let len = data.items_len();
+                match self.item_at(mouse.pos, width, len) {
+                    Some(from) if data.can_move_items() => {
+                        self.moving = Some(Move {
+                            from,
+                            start: mouse.pos,
+                            to: from,
+                            dragging: false,
+                        });
+                    }
+                    _ => {
+                        self.band = Some(Band {
+                            start: mouse.pos,
+                            end: mouse.pos,
+                            dragging: false,
+                        });
+                    }
+                }
+            }
+            Event::MouseMove(mouse) if self.moving.is_some() => {
+                let to = self.drop_index(mouse.pos, width, data.items_len());
+                let moving = self.moving.as_mut().unwrap();
+                moving.to = to;
+                if !moving.dragging
+                    && moving.start.distance(mouse.pos) > DRAG_THRESHOLD
+                {
+                    moving.dragging = true;
+                    ctx.set_active(true);
+                    self.release_cells(ctx, mouse, data, env);
+                }
+                if self.moving.as_ref().unwrap().dragging {
+                    ctx.request_paint();
+                    return;
+                }
+            }
+            Event::MouseUp(_) if self.moving.is_some() => {
+                let moving = self.moving.take().unwrap();
+                if moving.dragging {
+                    ctx.set_active(false);
+                    ctx.request_paint();
+                    data.move_item(moving.from, moving.to);
+                    return;
+                }
+            }
+            Event::MouseMove(mouse) if self.band.is_some() => {
+                let band = self.band.as_mut().unwrap();
+                band.end = mouse.pos;
+                if !band.dragging
+                    && band.start.distance(band.end) > DRAG_THRESHOLD
+                {
+                    band.dragging = true;
+                    ctx.set_active(true);
+                    self.release_cells(ctx, mouse, data, env);
+                }
+                let band = self.band.as_ref().unwrap();
+                if band.dragging {
+                    let rect = Rect::from_points(band.start, band.end);
+                    data.select_items(&self.items_in(
+                        rect,
+                        width,
+                        data.items_len(),
+                    ));
+                    ctx.request_paint();
+                    return;
+                }
+            }
+            Event::MouseUp(_) if self.band.is_some() => {
+                let band = self.band.take().unwrap();
+                if band.dragging {
+                    ctx.set_active(false);
+                    ctx.request_paint();
+                    return;
+                }
+                // clicking between or after the cells clears the selection
+                if self.item_at(band.start, width, data.items_len()).is_none() {
+                    data.select_items(&[]);
+                }
+            }
+            _ => (),
+        }
+        for (index, cell) in self.cells.iter_mut() {
+            if let Some(mut item) = data.item(*index) {
+                cell.event(ctx, event, &mut item, env);
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &T,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            // the new cells are added along with the grid itself
+            self.update_cells(data);
+        }
+        for (index, cell) in self.cells.iter_mut() {
+            if let Some(item) = data.item(*index) {
+                cell.lifecycle(ctx, event, &item, env);
+            }
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &T,
+        data: &T,
+        env: &Env,
+    ) {
+        if self.update_cells(data) {
+            ctx.children_changed();
+            ctx.request_layout();
+        } else if data.items_len() != old_data.items_len() {
+            ctx.request_layout();
+        }
+        for (index, cell) in self.cells.iter_mut() {
+            // cells that were just created are updated once they're added
+            if !cell.is_initialized() {
+                continue;
+            }
+            if let Some(item) = data.item(*index) {
+                cell.update(ctx, &item, env);
+            }
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &T,
+        env: &Env,
+    ) -> Size {
+        bc.debug_check("VirtualGrid");
+
+        let width = bc.max().width;
+        let columns = self.columns(width);
+        let cell_bc = BoxConstraints::new(Size::ZERO, self.cell_size);
+        for (index, cell) in self.cells.iter_mut() {
+            let item = match data.item(*index) {
+                Some(item) => item,
+                None => continue,
+            };
+            cell.layout(ctx, &cell_bc, &item, env);
+            let origin = Point::new(
+                (index % columns) as f64 * self.cell_size.width,
+                (index / columns) as f64 * self.cell_size.height,
+            );
+            cell.set_origin(ctx, &item, env, origin);
+        }
+
+        let rows = (data.items_len() + columns - 1) / columns;
+        bc.constrain(Size::new(width, rows as f64 * self.cell_size.height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &T, env: &Env) {
+        for (index, cell) in self.cells.iter_mut() {
+            if let Some(item) = data.item(*index) {
+                cell.paint(ctx, &item, env);
+            }
+        }
+        if let Some(band) = self.band.as_ref().filter(|band| band.dragging) {
+            let rect = Rect::from_points(band.start, band.end);
+            ctx.fill(rect, &Color::rgba8(0x16, 0x69, 0xdd, 0x30));
+            ctx.stroke(rect, &Color::rgb8(0x16, 0x69, 0xdd), 1.);
+        }
+        // a bar where the dragged cell would be dropped
+        if let Some(moving) = self.moving.as_ref().filter(|drag| drag.dragging)
+        {
+            let origin = self.cell_origin(moving.to, ctx.size().width);
+            let bar = Rect::from_origin_size(
+                origin - Vec2::new(2., 0.),
+                Size::new(4., self.cell_size.height),
+            );
+            ctx.fill(bar, &Color::rgb8(0x16, 0x69, 0xdd));
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! An image widget that can be zoomed and panned.
+
+use druid::{
+    kurbo::{Point, Rect, Size},
+    piet::{InterpolationMode, PietImage},
+    widget::prelude::*,
+    Command, Cursor, Data, ImageBuf, KbKey, Target,
+};
+
+use crate::app_commands::{SET_ZOOM, TAKE_FOCUS};
+
+/// How much a single zoom step, one wheel notch or key press, zooms by.
+const ZOOM_STEP: f64 = 1.25;
+const MIN_SCALE: f64 = 0.02;
+const MAX_SCALE: f64 = 32.;
+
+/// The zoom levels that can be jumped to directly.
+#[derive(Debug, Clone, Copy, Data, PartialEq)]
+pub enum ZoomPreset {
+    /// The whole image is visible.
+    Fit,
+    /// The image covers the whole widget, cropping whatever doesn't fit.
+    Fill,
+    /// One image pixel for every screen pixel.
+    ActualSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ZoomMode {
+    Preset(ZoomPreset),
+    /// The user zoomed or panned by hand, so the current scale and origin are
+    /// kept when the widget is resized.
+    Custom,
+}
+
+/// Displays an image that can be zoomed with the mouse wheel, centred on the
+/// cursor, panned by dragging, and switched between zoom presets.
+///
+/// Double clicking toggles between fitting the image and showing it at its
+/// actual size. When focused, `+` and `-` zoom in and out, Ctrl+0 fits the
+/// image and Ctrl+1 shows it at its actual size.
+pub struct ZoomImage {
+    image_data: ImageBuf,
+    paint_data: Option<PietImage>,
+    mode: ZoomMode,
+    /// Screen pixels per image pixel.
+    scale: f64,
+    /// Where the top left corner of the image is drawn.
+    origin: Point,
+    /// The mouse position and image origin when a drag started.
+    drag_start: Option<(Point, Point)>,
+}
+
+impl ZoomImage {
+    pub fn new(image_data: ImageBuf) -> Self {
+        ZoomImage {
+            image_data,
+            paint_data: None,
+            mode: ZoomMode::Preset(ZoomPreset::Fit),
+            scale: 1.,
+            origin: Point::ORIGIN,
+            drag_start: None,
+        }
+    }
+
+    /// Replaces the image. The zoom and position are kept if it's as large as
+    /// the previous one, like an image shown again with different edits, and
+    /// reset so the new image fits otherwise.
+    pub fn set_image_data(&mut self, image_data: ImageBuf) {
+        let resized = image_data.width() != self.image_data.width()
+            || image_data.height() != self.image_data.height();
+        self.image_data = image_data;
+        self.paint_data = None;
+        if resized {
+            self.reset_zoom();
+        }
+    }
+
+    /// Fits the image again, for when another image is shown.
+    pub fn reset_zoom(&mut self) {
+        self.mode = ZoomMode::Preset(ZoomPreset::Fit);
+        self.drag_start = None;
+    }
+
+    fn image_size(&self) -> Size {
+        Size::new(
+            self.image_data.width() as f64,
+            self.image_data.height() as f64,
+        )
+    }
+
+    fn apply_preset(&mut self, preset: ZoomPreset, size: Size) {
+        let image_size = self.image_size();
+        if image_size.width <= 0.
+            || image_size.height <= 0.
+            || size.width <= 0.
+            || size.height <= 0.
+        {
+            return;
+        }
+        let width_scale = size.width / image_size.width;
+        let height_scale = size.height / image_size.height;
+        self.scale = match preset {
+            ZoomPreset::Fit => width_scale.min(height_scale),
+            ZoomPreset::Fill => width_scale.max(height_scale),
+            ZoomPreset::ActualSize => 1.,
+        };
+        let scaled_size = image_size * self.scale;
+        self.origin = Point::new(
+            (size.width - scaled_size.width) / 2.,
+            (size.height - scaled_size.height) / 2.,
+        );
+        self.mode = ZoomMode::Preset(preset);
+    }
+
+    /// Zooms by `factor` while keeping the image point under `anchor` in place.
+    fn zoom_around(&mut self, factor: f64, anchor: Point, size: Size) {
+        let new_scale = (self.scale * factor).max(MIN_SCALE).min(MAX_SCALE);
+        let ratio = new_scale / self.scale;
+        self.origin = anchor - (anchor - self.origin) * ratio;
+        self.scale = new_scale;
+        self.mode = ZoomMode::Custom;
+        self.clamp_origin(size);
+    }
+
+    fn pan_to(&mut self, origin: Point, size: Size) {
+        self.origin = origin;
+        self.mode = ZoomMode::Custom;
+        self.clamp_origin(size);
+    }
+
+    /// Keeps the image from being panned out of view. An axis where the image
+    /// is smaller than the widget is centred instead.
+    fn clamp_origin(&mut self, size: Size) {
+        let scaled_size = self.image_size() * self.scale;
+        let clamp = |origin: f64, scaled: f64, available: f64| {
+            if scaled <= available {
+                (available - scaled) / 2.
+            } else {
+                origin.min(0.).max(available - scaled)
+            }
+        };
+        self.origin = Point::new(
+            clamp(self.origin.x, scaled_size.width, size.width),
+            clamp(self.origin.y, scaled_size.height, size.height),
+        );
+    }
+
+    fn center(size: Size) -> Point {
+        Point::new(size.width / 2., size.height / 2.)
+    }
+}
+
+impl<T: Data> Widget<T> for ZoomImage {
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        _data: &mut T,
+        _env: &Env,
+    ) {
+        let size = ctx.size();
+        match event {
+            Event::Command(cmd) if cmd.is(TAKE_FOCUS) => {
+                ctx.request_focus();
+            }
+            Event::Command(cmd) if cmd.is(SET_ZOOM) => {
+                let preset = cmd.get_unchecked(SET_ZOOM);
+                self.apply_preset(*preset, size);
+                ctx.request_paint();
+            }
+            Event::Wheel(mouse) => {
+                let factor = ZOOM_STEP.powf(-mouse.wheel_delta.y / 100.);
+                self.zoom_around(factor, mouse.pos, size);
+                ctx.set_handled();
+                ctx.request_paint();
+            }
+            Event::MouseDown(mouse) => {
+                ctx.request_focus();
+                if mouse.count == 2 {
+                    if self.mode == ZoomMode::Preset(ZoomPreset::Fit) {
+                        // zoom to the actual size around the clicked point
+                        self.zoom_around(1. / self.scale, mouse.pos, size);
+                        self.mode = ZoomMode::Preset(ZoomPreset::ActualSize);
+                    } else {
+                        self.apply_preset(ZoomPreset::Fit, size);
+                    }
+                    ctx.request_paint();
+                } else {
+                    self.drag_start = Some((mouse.pos, self.origin));
+                    ctx.set_active(true);
+                }
+            }
+            Event::MouseMove(mouse) => {
+                if let Some((start, origin)) = self.drag_start {
+                    ctx.set_cursor(&Cursor::OpenHand);
+                    self.pan_to(origin + (mouse.pos - start), size);
+                    ctx.request_paint();
+                }
+            }
+            Event::MouseUp(_) => {
+                if ctx.is_active() {
+                    ctx.set_active(false);
+                }
+                self.drag_start = None;
+            }
+            Event::KeyDown(key) => {
+                let handled = match &key.key {
+                    KbKey::Character(c) if c == "+" || c == "=" => {
+                        self.zoom_around(ZOOM_STEP, Self::center(size), size);
+                        true
+                    }
+                    KbKey::Character(c) if c == "-" => {
+                        self.zoom_around(
+                            1. / ZOOM_STEP,
+                            Self::center(size),
+                            size,
+                        );
+                        true
+                    }
+                    KbKey::Character(c) if key.mods.ctrl() && c == "0" => {
+                        self.apply_preset(ZoomPreset::Fit, size);
+                        true
+                    }
+                    KbKey::Character(c) if key.mods.ctrl() && c == "1" => {
+                        self.apply_preset(ZoomPreset::ActualSize, size);
+                        true
+                    }
+                    _ => false,
+                };
+                if handled {
+                    ctx.set_handled();
+                    ctx.request_paint();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        _data: &T,
+        _env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.register_for_focus();
+            // focus can only be requested while handling an event, so this
+            // asks for it through a command sent back to this widget
+            ctx.submit_command(Command::new(
+                TAKE_FOCUS,
+                (),
+                Target::Widget(ctx.widget_id()),
+            ));
+        }
+    }
+
+    fn update(
+        &mut self,
+        _ctx: &mut UpdateCtx,
+        _old_data: &T,
+        _data: &T,
+        _env: &Env,
+    ) {
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &T,
+        _env: &Env,
+    ) -> Size {
+        bc.debug_check("ZoomImage");
+
+        let size = if bc.is_width_bounded() && bc.is_height_bounded() {
+            bc.max()
+        } else {
+            bc.constrain(self.image_size())
+        };
+        match self.mode {
+            ZoomMode::Preset(preset) => self.apply_preset(preset, size),
+            ZoomMode::Custom => self.clamp_origin(size),
+        }
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &T, _env: &Env) {
+        if self.image_data.width() == 0 || self.image_data.height() == 0 {
+            return;
+        }
+
+        let image_rect =
+            Rect::from_origin_size(self.origin, self.image_size() * self.scale);
+        // individual pixels are easier to inspect without smoothing
+        let interpolation = if self.scale >= 2. {
+            InterpolationMode::NearestNeighbor
+        } else {
+            InterpolationMode::Bilinear
+        };
+        let clip_rect = ctx.size().to_rect();
+
+        let image_data = &self.image_data;
+        let piet_image = self
+            .paint_data
+            .get_or_insert_with(|| image_data.to_image(ctx.render_ctx));
+        ctx.with_save(|ctx| {
+            ctx.clip(clip_rect);
+            ctx.draw_image(piet_image, image_rect, interpolation);
+        });
+    }
+}
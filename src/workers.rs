@@ -0,0 +1,180 @@
+//! A shared pool of threads decoding images in the background.
+//!
+//! Decoding used to start a thread for every folder opened and every image
+//! shown, so they all competed with each other and results could come back
+//! in any order. Instead jobs are queued here by priority, so the image the
+//! user is looking at is decoded before the thumbnails of the rest of the
+//! folder, and jobs the user no longer needs can be cancelled before they
+//! start.
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
+
+use druid::Data;
+use log::error;
+use once_cell::sync::Lazy;
+
+/// How many images are decoded at the same time.
+const WORKERS: usize = 4;
+
+static POOL: Lazy<WorkerPool> = Lazy::new(WorkerPool::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Images that are read ahead, in case they're shown next.
+    Background,
+    /// Thumbnails and covers that are in view.
+    Visible,
+    /// The image the user selected.
+    Selected,
+}
+
+/// Cancels the jobs it was given to when `cancel` is called. Jobs that
+/// already started are left to finish.
+#[derive(Debug, Clone)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    parent: Option<Arc<CancelToken>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            parent: None,
+        }
+    }
+
+    /// A token that is also cancelled along with this one.
+    pub fn child(&self) -> Self {
+        CancelToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            parent: Some(Arc::new(self.clone())),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, AtomicOrdering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(AtomicOrdering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .map_or(false, |parent| parent.is_cancelled())
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Data for CancelToken {
+    fn same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+struct Job {
+    priority: Priority,
+    /// Jobs with the same priority run in the order they were queued.
+    order: u64,
+    cancel: CancelToken,
+    work: Box<dyn FnOnce() + Send>,
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+struct WorkerPool {
+    queue: Mutex<BinaryHeap<Job>>,
+    queued: Condvar,
+    next_order: AtomicU64,
+}
+
+impl WorkerPool {
+    fn new() -> Self {
+        // the workers wait for `POOL` to finish initializing before they
+        // take any jobs from it
+        for i in 0..WORKERS {
+            thread::Builder::new()
+                .name(format!("decoder-{}", i))
+                .spawn(|| POOL.run())
+                .unwrap();
+        }
+        WorkerPool {
+            queue: Mutex::new(BinaryHeap::new()),
+            queued: Condvar::new(),
+            next_order: AtomicU64::new(0),
+        }
+    }
+
+    fn run(&self) {
+        loop {
+            let job = {
+                let mut queue = self.queue.lock().unwrap();
+                loop {
+                    match queue.pop() {
+                        Some(job) => break job,
+                        None => queue = self.queued.wait(queue).unwrap(),
+                    }
+                }
+            };
+            if job.cancel.is_cancelled() {
+                continue;
+            }
+            // a decoder panicking on a broken file shouldn't take a worker
+            // down with it
+            if panic::catch_unwind(AssertUnwindSafe(job.work)).is_err() {
+                error!("A background job panicked");
+            }
+        }
+    }
+}
+
+/// Queues `work` to run on the pool, unless `cancel` is cancelled before it
+/// starts.
+pub fn spawn(
+    priority: Priority,
+    cancel: &CancelToken,
+    work: impl FnOnce() + Send + 'static,
+) {
+    let job = Job {
+        priority,
+        order: POOL.next_order.fetch_add(1, AtomicOrdering::Relaxed),
+        cancel: cancel.clone(),
+        work: Box::new(work),
+    };
+    POOL.queue.lock().unwrap().push(job);
+    POOL.queued.notify_one();
+}